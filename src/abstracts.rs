@@ -0,0 +1,65 @@
+//! The abstract, one chapter taken out of the flow of the book and printed
+//! below the title, in the `abstract` environment of the article class.
+//!
+//! Templates get it as `\mdbookabstract`, which is empty for books without
+//! an abstract, and the keywords and subject classification printed under it
+//! as `\mdbookkeywords`.
+
+use crate::labels::Labels;
+use crate::raw::escape;
+use std::collections::BTreeMap;
+
+/// `content` without its leading level one heading, the `abstract`
+/// environment prints a title of its own.
+pub(crate) fn strip_title(content: &str) -> &str {
+    let rest = content.trim_start();
+    match rest.strip_prefix("# ") {
+        Some(heading) => heading.split_once('\n').map_or("", |(_, rest)| rest),
+        None => content,
+    }
+}
+
+/// Keywords and subject classification codes, printed under the abstract,
+/// one line each.
+pub(crate) fn keywords(
+    keywords: &[String],
+    classification: &BTreeMap<String, Vec<String>>,
+    labels: &Labels,
+) -> String {
+    let mut lines = Vec::new();
+    if !keywords.is_empty() {
+        lines.push((escape(&labels.keywords), list(keywords)));
+    }
+    for (scheme, codes) in classification {
+        lines.push((escape(scheme), list(codes)));
+    }
+    lines
+        .into_iter()
+        .map(|(name, items)| format!("\\par\\smallskip\\noindent\\textbf{{{}:}} {}", name, items))
+        .collect()
+}
+
+/// Keywords and classification codes on one line, for the PDF metadata.
+pub(crate) fn pdf_keywords(
+    keywords: &[String],
+    classification: &BTreeMap<String, Vec<String>>,
+) -> String {
+    let codes = classification
+        .iter()
+        .flat_map(|(scheme, codes)| codes.iter().map(move |code| format!("{} {}", scheme, code)));
+    keywords
+        .iter()
+        .cloned()
+        .chain(codes)
+        .map(|keyword| escape(&keyword))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn list(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| escape(item))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
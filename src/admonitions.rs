@@ -0,0 +1,250 @@
+//! Admonitions, asides like notes and warnings, set apart in colored boxes.
+//!
+//! GitHub style alerts are blockquotes starting with their kind:
+//!
+//! ```markdown
+//! > [!WARNING]
+//! > Back up your data first.
+//! ```
+//!
+//! With `admonish` set, so are the fenced blocks of `mdbook-admonish`:
+//!
+//! ````markdown
+//! ```admonish warning "Data loss"
+//! Back up your data first.
+//! ```
+//! ````
+
+use crate::capabilities::Outcome;
+use crate::raw::{escape, markdown_fragment_to_tex};
+use crate::Traversal;
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+
+/// Box style of one kind of admonition, the built in style if not set.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AdmonitionStyle {
+    // Color of the frame and title, tinted for the background, in `xcolor` syntax.
+    pub color: Option<String>,
+
+    // LaTeX printed before the title.
+    pub icon: Option<String>,
+}
+
+/// Kinds of GitHub alerts, with their built in color and icon. The colors
+/// are defined in the template.
+const KINDS: &[(&str, &str, &str)] = &[
+    ("note", "admonitionnote", "\\textcircled{\\scriptsize i}"),
+    ("tip", "admonitiontip", "$\\star$"),
+    ("important", "admonitionimportant", "\\textbf{!}"),
+    ("warning", "admonitionwarning", "$\\triangle$"),
+    ("caution", "admonitioncaution", "$\\otimes$"),
+];
+
+/// The kind of the alert marker `[!KIND]`, lower case.
+fn alert_kind(marker: &str) -> Option<&'static str> {
+    let kind = marker.trim().strip_prefix("[!")?.strip_suffix(']')?;
+    KINDS
+        .iter()
+        .map(|(known, ..)| *known)
+        .find(|known| known.eq_ignore_ascii_case(kind))
+}
+
+/// The alert kind styling the `mdbook-admonish` directive `directive`.
+fn admonish_kind(directive: &str) -> &'static str {
+    match directive {
+        "tip" | "hint" | "success" | "check" | "done" => "tip",
+        "important" => "important",
+        "warning" | "attention" | "question" | "help" | "faq" => "warning",
+        "caution" | "danger" | "error" | "failure" | "fail" | "missing" | "bug" => "caution",
+        _ => "note",
+    }
+}
+
+/// Begin the box of an admonition of `kind`, titled `title`, which is LaTeX.
+/// Kinds other than those of GitHub alerts are styled like the closest one.
+pub(crate) fn begin(kind: &str, title: &str, state: &mut Traversal) -> String {
+    let style = state.cfg.admonitions.get(kind);
+    let builtin = admonish_kind(kind);
+    let (_, color, icon) = KINDS
+        .iter()
+        .find(|(known, ..)| *known == builtin)
+        .expect("All admonish kinds are alert kinds. qed");
+    let color = style
+        .and_then(|style| style.color.as_deref())
+        .unwrap_or(color);
+    let icon = style
+        .and_then(|style| style.icon.as_deref())
+        .unwrap_or(icon);
+    state.capabilities.record("Admonitions", Outcome::Rendered);
+    format!(
+        "\\begin{{admonition}}{{{}}}{{{}}}{{{}}}",
+        color, icon, title
+    )
+}
+
+/// The title of the GitHub alert of `kind`, localized.
+fn title(kind: &str, state: &Traversal) -> String {
+    let labels = &state.labels;
+    escape(match kind {
+        "tip" => &labels.tip,
+        "important" => &labels.important,
+        "warning" => &labels.warning,
+        "caution" => &labels.caution,
+        _ => &labels.note,
+    })
+}
+
+/// Turn GitHub alerts into admonition boxes, other blockquotes are left
+/// as they are.
+pub(crate) fn alerts<'a>(events: Vec<Event<'a>>, state: &mut Traversal) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        if event != Event::Start(Tag::BlockQuote) {
+            out.push(event);
+            continue;
+        }
+        let mut inner = Vec::new();
+        let mut depth = 0;
+        for event in events.by_ref() {
+            match event {
+                Event::End(Tag::BlockQuote) if depth == 0 => break,
+                Event::Start(Tag::BlockQuote) => depth += 1,
+                Event::End(Tag::BlockQuote) => depth -= 1,
+                _ => {}
+            }
+            inner.push(event);
+        }
+        let inner = alerts(inner, state);
+
+        // The marker may be split into several text events at the brackets.
+        let marker_end = match inner.first() {
+            Some(Event::Start(Tag::Paragraph)) => inner[1..]
+                .iter()
+                .position(|event| !matches!(event, Event::Text(_)))
+                .map(|idx| idx + 1),
+            _ => None,
+        };
+        let kind = marker_end.and_then(|end| {
+            let marker = inner[1..end]
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Text(text) => Some(text.as_ref()),
+                    _ => None,
+                })
+                .collect::<String>();
+            alert_kind(&marker).map(|kind| (kind, end))
+        });
+        let (kind, end) = match kind {
+            Some(kind) => kind,
+            None => {
+                out.push(Event::Start(Tag::BlockQuote));
+                out.extend(inner);
+                out.push(Event::End(Tag::BlockQuote));
+                continue;
+            }
+        };
+
+        let title = title(kind, state);
+        let begin = begin(kind, &title, state);
+        out.extend(state.raw.block(begin));
+        let mut rest = inner.into_iter().skip(end).peekable();
+        match rest.peek() {
+            // The marker was a paragraph of its own.
+            Some(Event::End(Tag::Paragraph)) => {
+                rest.next();
+            }
+            Some(Event::SoftBreak | Event::HardBreak) => {
+                rest.next();
+                out.push(Event::Start(Tag::Paragraph));
+            }
+            _ => out.push(Event::Start(Tag::Paragraph)),
+        }
+        out.extend(rest);
+        out.extend(state.raw.block("\\end{admonition}"));
+    }
+    out
+}
+
+/// Directive and title of the info string of an `mdbook-admonish` block,
+/// `admonish warning "Title"` or `admonish warning title="Title"`, `None`
+/// if it is none. The title defaults to the directive.
+pub(crate) fn parse_admonish(info: &str) -> Option<(String, String)> {
+    let rest = info.trim().strip_prefix("admonish")?;
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let (directive, rest) = match rest.find(|c: char| c.is_whitespace() || c == '"') {
+        Some(idx) => (&rest[..idx], rest[idx..].trim_start()),
+        None => (rest, ""),
+    };
+    let directive = match directive {
+        "" => "note",
+        directive => directive,
+    };
+    let quoted = |text: &str| {
+        text.strip_prefix('"')
+            .and_then(|text| text.split_once('"'))
+            .map(|(title, _)| title.to_owned())
+    };
+    let title = quoted(rest)
+        .or_else(|| {
+            rest.find("title=")
+                .and_then(|idx| quoted(&rest[idx + "title=".len()..]))
+        })
+        .unwrap_or_else(|| {
+            let mut chars = directive.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        });
+    Some((directive.to_owned(), title))
+}
+
+/// Turn the fenced blocks of `mdbook-admonish` into admonition boxes, if
+/// `admonish` is set. Their body is markdown.
+pub(crate) fn admonish<'a>(
+    events: Vec<Event<'a>>,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    if !state.cfg.admonish {
+        return Ok(events);
+    }
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let (directive, title) = match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref info))) => {
+                match parse_admonish(info) {
+                    Some(admonish) => admonish,
+                    None => {
+                        out.push(event);
+                        continue;
+                    }
+                }
+            }
+            event => {
+                out.push(event);
+                continue;
+            }
+        };
+        let mut body = String::new();
+        for event in events.by_ref() {
+            match event {
+                Event::End(Tag::CodeBlock(_)) => break,
+                Event::Text(text) => body.push_str(&text),
+                _ => {}
+            }
+        }
+        let begin = begin(&directive, &markdown_fragment_to_tex(&title)?, state);
+        out.extend(state.raw.block(format!(
+            "{}\n{}\n\\end{{admonition}}",
+            begin,
+            markdown_fragment_to_tex(&body)?
+        )));
+    }
+    Ok(out)
+}
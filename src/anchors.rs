@@ -0,0 +1,143 @@
+//! Labels of chapters and headings, named after the ids mdbook gives the
+//! headings in the HTML output, so a link to `config.md#options` finds its
+//! heading in the PDF as well.
+//!
+//! The ids are only unique within a chapter, the labels are qualified with
+//! the path of the chapter, `heading:reference/config.md:options`.
+
+use crate::Traversal;
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// `path` with forward slashes, characters LaTeX may trip over replaced.
+fn qualifier(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c,
+            '-' | '_' | '.' | '/' => c,
+            _ => '-',
+        })
+        .collect()
+}
+
+/// Label of the start of the chapter at `path`.
+pub(crate) fn chapter(path: &Path) -> String {
+    format!("chapter:{}", qualifier(path))
+}
+
+/// Label of the heading with the id `id` in the chapter at `path`.
+pub(crate) fn heading(path: &Path, id: &str) -> String {
+    format!("heading:{}:{}", qualifier(path), id)
+}
+
+/// Ids of the headings of a chapter, in order, as mdbook gives them: the
+/// explicit `{#id}`, or else the slug of the text, numbered if taken.
+#[derive(Debug, Default)]
+pub(crate) struct Slugs {
+    counter: HashMap<String, usize>,
+}
+
+impl Slugs {
+    pub(crate) fn next(&mut self, id: Option<&str>, text: &str) -> String {
+        match id {
+            Some(id) => id.to_owned(),
+            None => mdbook::utils::unique_id_from_content(text, &mut self.counter),
+        }
+    }
+}
+
+/// Ids given so far in the chapter, and whether it was labeled yet.
+#[derive(Debug, Default)]
+pub(crate) struct ChapterAnchors {
+    slugs: Slugs,
+    anchored: bool,
+}
+
+/// Text of a heading, as its slug is derived from. The alternative text
+/// of images is an attribute in HTML, and no part of it.
+fn heading_text(events: &[Event]) -> String {
+    let mut text = String::new();
+    let mut images = 0;
+    for event in events {
+        match event {
+            Event::Start(Tag::Image(..)) => images += 1,
+            Event::End(Tag::Image(..)) => images -= 1,
+            Event::Text(part) | Event::Code(part) if images == 0 => text.push_str(part),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Ids of all headings of the markdown `content`.
+pub(crate) fn ids(content: &str) -> Vec<String> {
+    let mut slugs = Slugs::default();
+    let mut ids = Vec::new();
+    let mut heading = None;
+    for event in Parser::new_ext(content, Options::all()) {
+        match event {
+            Event::Start(Tag::Heading(_, id, _)) => heading = Some((id, Vec::new())),
+            Event::End(Tag::Heading(..)) => {
+                if let Some((id, text)) = heading.take() {
+                    ids.push(slugs.next(id, &heading_text(&text)));
+                }
+            }
+            event => {
+                if let Some((_, ref mut text)) = heading {
+                    text.push(event);
+                }
+            }
+        }
+    }
+    ids
+}
+
+/// Label every heading after its id, and the chapter at its first heading,
+/// or at the start of the first piece without one.
+///
+/// The explicit id is dropped once labeled, and the classes from the end of
+/// the heading, which is where they are written back to markdown from:
+/// `cmark2tex` would print them as part of the title. The start of the
+/// heading keeps its classes for the passes after this one.
+pub(crate) fn label_headings<'a>(events: Vec<Event<'a>>, state: &mut Traversal) -> Vec<Event<'a>> {
+    let path = match state.chapter.and_then(|chapter| chapter.path.as_ref()) {
+        Some(path) => path,
+        None => return events,
+    };
+    let mut out = Vec::with_capacity(events.len());
+    // Start of the heading being passed.
+    let mut start = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading(level, id, classes)) => {
+                start = Some((out.len() + 1, id));
+                out.push(Event::Start(Tag::Heading(level, None, classes)));
+            }
+            Event::End(Tag::Heading(level, ..)) => {
+                let (idx, id) = start.take().unwrap_or((out.len(), None));
+                let id = state.anchors.slugs.next(id, &heading_text(&out[idx..]));
+                out.push(Event::End(Tag::Heading(level, None, Vec::new())));
+                let mut labels = format!("\\label{{{}}}", heading(path, &id));
+                if !state.anchors.anchored {
+                    labels.push_str(&format!("\\label{{{}}}", chapter(path)));
+                    state.anchors.anchored = true;
+                }
+                out.extend(state.raw.block(labels));
+            }
+            event => out.push(event),
+        }
+    }
+    if !state.anchors.anchored {
+        state.anchors.anchored = true;
+        let anchor = state
+            .raw
+            .block(format!("\\phantomsection\\label{{{}}}", chapter(path)));
+        out.splice(0..0, anchor);
+    }
+    out
+}
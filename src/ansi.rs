@@ -0,0 +1,258 @@
+//! ANSI escape sequences in `console` and `ansi` blocks, as terminals print
+//! colored output, converted to colored LaTeX or stripped, set by `ansi`.
+//!
+//! Colors and bold of SGR sequences, `\x1b[1;32m`, are kept, with the 16
+//! standard colors, the 256 color palette and true color. Backgrounds and
+//! all other sequences, like cursor movement, are dropped.
+
+use crate::capabilities::Outcome;
+use crate::code::take_code_block;
+use crate::{Ansi, Traversal};
+use pulldown_cmark::{Event, Tag};
+
+/// Fence languages of terminal output.
+const LANGUAGES: &[&str] = &["console", "ansi"];
+
+/// The 16 standard colors, as xterm shows them.
+const PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Color {
+    #[default]
+    Default,
+    // One of the 16 standard colors.
+    Standard(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// Style of a span of text.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Style {
+    pub(crate) color: Color,
+    pub(crate) bold: bool,
+}
+
+impl Style {
+    /// Apply the parameters of one SGR sequence.
+    fn apply(&mut self, params: &str) {
+        let mut params = params
+            .split(';')
+            .map(|param| param.parse::<u16>().unwrap_or(0));
+        while let Some(param) = params.next() {
+            match param {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                30..=37 => self.color = Color::Standard(param as u8 - 30),
+                90..=97 => self.color = Color::Standard(param as u8 - 90 + 8),
+                39 => self.color = Color::Default,
+                38 => match params.next() {
+                    Some(5) => self.color = indexed(params.next().unwrap_or(0)),
+                    Some(2) => {
+                        let mut channel = || params.next().unwrap_or(0).min(255) as u8;
+                        self.color = Color::Rgb(channel(), channel(), channel());
+                    }
+                    _ => {}
+                },
+                // Background colors take arguments too.
+                48 => match params.next() {
+                    Some(5) => {
+                        params.next();
+                    }
+                    Some(2) => {
+                        params.nth(2);
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+/// A color of the 256 color palette.
+fn indexed(index: u16) -> Color {
+    match index {
+        0..=15 => Color::Standard(index as u8),
+        16..=231 => {
+            let index = index - 16;
+            let level = |value: u16| {
+                if value == 0 {
+                    0
+                } else {
+                    (55 + value * 40) as u8
+                }
+            };
+            Color::Rgb(level(index / 36), level(index / 6 % 6), level(index % 6))
+        }
+        _ => {
+            let gray = (8 + (index.min(255) - 232) * 10) as u8;
+            Color::Rgb(gray, gray, gray)
+        }
+    }
+}
+
+/// `text` split into spans of one style each, without the escape sequences.
+pub(crate) fn parse(text: &str) -> Vec<(Style, String)> {
+    let mut spans: Vec<(Style, String)> = Vec::new();
+    let mut style = Style::default();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            match spans.last_mut() {
+                Some((last, span)) if *last == style => span.push(c),
+                _ => spans.push((style, c.to_string())),
+            }
+            continue;
+        }
+        match chars.next() {
+            // Control sequence, parameters up to a final byte.
+            Some('[') => {
+                let mut params = String::new();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        if c == 'm' {
+                            style.apply(&params);
+                        }
+                        break;
+                    }
+                    params.push(c);
+                }
+            }
+            // Operating system command, like window titles, up to BEL or ST.
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    spans
+}
+
+/// `text` without any escape sequences.
+pub(crate) fn strip(text: &str) -> String {
+    parse(text).into_iter().map(|(_, span)| span).collect()
+}
+
+/// Lines of `text` for a `Verbatim` environment with `\`, `{` and `}` as
+/// command characters, colored as in the terminal.
+pub(crate) fn to_latex(text: &str) -> String {
+    let mut tex = String::new();
+    for (style, span) in parse(text) {
+        let mut lines = span.split('\n').peekable();
+        while let Some(line) = lines.next() {
+            // Commands may not span lines, each line is styled on its own.
+            if !line.is_empty() {
+                let mut line = escape(line);
+                if style.bold {
+                    line = format!("\\textbf{{{}}}", line);
+                }
+                tex.push_str(&match style.color {
+                    Color::Default => line,
+                    Color::Standard(index) => format!("\\textcolor{{ansi{}}}{{{}}}", index, line),
+                    Color::Rgb(r, g, b) => {
+                        format!("\\textcolor[RGB]{{{},{},{}}}{{{}}}", r, g, b, line)
+                    }
+                });
+            }
+            if lines.peek().is_some() {
+                tex.push('\n');
+            }
+        }
+    }
+    tex
+}
+
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\\' => "\\textbackslash{}".to_owned(),
+            '{' => "\\{".to_owned(),
+            '}' => "\\}".to_owned(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+/// Preamble defining the standard colors.
+pub(crate) fn preamble() -> String {
+    let mut preamble = String::from("\n%% ANSI colors of terminal output.\n");
+    for (index, (r, g, b)) in PALETTE.iter().enumerate() {
+        preamble.push_str(&format!(
+            "\\definecolor{{ansi{}}}{{RGB}}{{{},{},{}}}\n",
+            index, r, g, b
+        ));
+    }
+    preamble
+}
+
+/// Convert or strip the escape sequences of terminal output blocks.
+pub(crate) fn console<'a>(events: Vec<Event<'a>>, state: &mut Traversal) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        if !matches!(event, Event::Start(Tag::CodeBlock(_))) {
+            out.push(event);
+            continue;
+        }
+        let mut block = take_code_block(event, &mut events);
+        let code = block.code();
+        let terminal = block
+            .info
+            .language
+            .as_deref()
+            .map_or(false, |language| LANGUAGES.contains(&language));
+        if !terminal || !code.contains('\x1b') {
+            out.extend(block.events);
+            continue;
+        }
+        match state.cfg.ansi {
+            Ansi::Color => {
+                state.packages.insert("fvextra");
+                state.packages.insert("xcolor");
+                state.ansi_colors = true;
+                state.capabilities.record("ANSI colors", Outcome::Rendered);
+                let mut tex = to_latex(&code);
+                if !tex.ends_with('\n') {
+                    tex.push('\n');
+                }
+                out.extend(state.raw.block(format!(
+                    "\\begin{{Verbatim}}[commandchars=\\\\\\{{\\}},breaklines,fontsize=\\footnotesize]\n{}\\end{{Verbatim}}",
+                    tex
+                )));
+            }
+            Ansi::Strip => {
+                state.capabilities.record(
+                    "ANSI colors",
+                    Outcome::Degraded("escape sequences stripped"),
+                );
+                let end = block.events.pop();
+                out.push(block.events.remove(0));
+                out.push(Event::Text(strip(&code).into()));
+                out.extend(end);
+            }
+        }
+    }
+    out
+}
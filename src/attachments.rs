@@ -0,0 +1,59 @@
+//! Files attached to the PDF, e.g. complete code listings or data files,
+//! so readers can extract them, using the `attachfile` package.
+
+use crate::paths;
+use crate::raw::escape;
+use fs_err as fs;
+use std::path::{Path, PathBuf};
+
+/// The files at `paths` relative to `root`, directories with all the files
+/// within.
+pub(crate) fn files(root: &Path, paths: &[String]) -> color_eyre::Result<Vec<PathBuf>> {
+    fn walk(path: &Path, files: &mut Vec<PathBuf>) -> color_eyre::Result<()> {
+        if path.is_dir() {
+            let mut entries = fs::read_dir(path)?
+                .map(|entry| Ok(entry?.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            entries.sort();
+            for entry in entries {
+                walk(&entry, files)?;
+            }
+        } else if path.is_file() {
+            files.push(path.to_owned());
+        } else {
+            color_eyre::eyre::bail!("Missing attachment {}", path.display());
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    for path in paths {
+        walk(&root.join(path), &mut files)?;
+    }
+    Ok(files)
+}
+
+/// An appendix listing the attached files, each of them clickable.
+///
+/// Names are given relative to `root` where possible.
+pub(crate) fn appendix(files: &[PathBuf], root: &Path, title: &str) -> String {
+    let title = escape(title);
+    let mut tex = format!(
+        "\\section*{{{}}}\n\\phantomsection\n\\addcontentsline{{toc}}{{section}}{{{}}}\n\\begin{{itemize}}\n",
+        title, title
+    );
+    for file in files {
+        let name = file
+            .strip_prefix(root)
+            .unwrap_or(file)
+            .to_string_lossy()
+            .into_owned();
+        tex.push_str(&format!(
+            "\\item \\textattachfile{{{}}}{{\\texttt{{{}}}}}\n",
+            paths::to_tex(file),
+            escape(&name)
+        ));
+    }
+    tex.push_str("\\end{itemize}\n");
+    tex
+}
@@ -0,0 +1,60 @@
+//! Authors of the book, as listed in `book.authors`, with details from
+//! `output.latex.author-details`.
+
+use crate::raw::escape;
+use std::collections::BTreeMap;
+
+/// Details of one author, printed below their name on the title page.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct AuthorDetails {
+    pub affiliation: Option<String>,
+
+    // ORCID iD, e.g. `0000-0002-1825-0097`.
+    pub orcid: Option<String>,
+
+    pub email: Option<String>,
+}
+
+/// The names, escaped and separated by commas, for templates to use as
+/// they see fit.
+pub(crate) fn list(authors: &[String]) -> String {
+    authors
+        .iter()
+        .map(|author| escape(author))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The argument of `\author`, each author with their details.
+pub(crate) fn block(authors: &[String], details: &BTreeMap<String, AuthorDetails>) -> String {
+    authors
+        .iter()
+        .map(|author| {
+            let mut tex = escape(author);
+            let details = match details.get(author) {
+                Some(details) => details,
+                None => return tex,
+            };
+            if let Some(affiliation) = &details.affiliation {
+                tex.push_str(&format!("\\\\\n\\small {}", escape(affiliation)));
+            }
+            if let Some(email) = &details.email {
+                tex.push_str(&format!(
+                    "\\\\\n\\small\\href{{mailto:{}}}{{\\texttt{{{}}}}}",
+                    email,
+                    escape(email)
+                ));
+            }
+            if let Some(orcid) = &details.orcid {
+                tex.push_str(&format!(
+                    "\\\\\n\\small ORCID \\href{{https://orcid.org/{}}}{{{}}}",
+                    orcid,
+                    escape(orcid)
+                ));
+            }
+            tex
+        })
+        .collect::<Vec<_>>()
+        .join(" \\and ")
+}
@@ -0,0 +1,86 @@
+//! `mdbook-tectonic --bench [chapters]`, converting a synthetic book to
+//! compare the speed of the conversion between versions, without a book at
+//! hand whose content changes in between.
+
+use crate::{render, Flags, LatexConfig};
+use mdbook::book::{Book, Chapter};
+use mdbook::renderer::RenderContext;
+
+/// A book of `chapters` chapters, each with a bit of everything the passes
+/// look for: headings, lists, code, tables, footnotes, alerts and links to
+/// the neighbouring chapters.
+pub(crate) fn synthetic(chapters: usize) -> Book {
+    let mut book = Book::new();
+    for idx in 0..chapters {
+        let previous = idx.saturating_sub(1);
+        let next = (idx + 1).min(chapters.saturating_sub(1));
+        let content = format!(
+            "# Chapter {idx}\n\n\
+             Some prose with *emphasis*, `code` and a footnote.[^note]\n\n\
+             ## Steps\n\n\
+             1. Read [the previous chapter](chapter-{previous}.md#steps).\n\
+             2. Continue with [the next one](chapter-{next}.md).\n\
+             3. Look it up [online](https://example.com/{idx}).\n\n\
+             ```rust\nfn main() {{\n    println!(\"chapter {idx}\");\n}}\n```\n\n\
+             | Option | Default | Meaning |\n\
+             |--------|--------:|---------|\n\
+             | `a` | 1 | The first one |\n\
+             | `b` | 2 | The second one |\n\n\
+             > [!NOTE]\n\
+             > Alerts become boxes.\n\n\
+             ## Details\n\n\
+             A longer paragraph, so there is prose to measure. The quick brown fox \
+             jumps over the lazy dog, again and again, until the chapter is long \
+             enough to take some time to convert.\n\n\
+             [^note]: The footnote of chapter {idx}.\n",
+            idx = idx,
+            previous = previous,
+            next = next,
+        );
+        book.push_item(Chapter::new(
+            &format!("Chapter {}", idx),
+            content,
+            format!("chapter-{}.md", idx),
+            Vec::new(),
+        ));
+    }
+    book
+}
+
+/// Build a synthetic book of `chapters` chapters in a temporary directory,
+/// and print how long the phases took.
+pub(crate) fn run(chapters: usize) -> color_eyre::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut config = mdbook::Config::default();
+    config.book.title = Some("Bench".to_owned());
+    let ctx = RenderContext::new(
+        dir.path(),
+        synthetic(chapters),
+        config,
+        dir.path().join("book"),
+    );
+    let cfg = LatexConfig {
+        latex: true,
+        pdf: which::which("tectonic").is_ok(),
+        timing: true,
+        ..Default::default()
+    };
+    if !cfg.pdf {
+        log::info!("No `tectonic` found, only converting");
+    }
+    // mdbook runs renderers within the destination, outputs are written there.
+    fs_err::create_dir_all(&ctx.destination)?;
+    std::env::set_current_dir(&ctx.destination)?;
+    render(&ctx, &cfg, None, Flags::default())?;
+
+    // The totals of the book follow the chapters in the report.
+    let report = fs_err::read_to_string(ctx.destination.join("report.md"))?;
+    let totals = report
+        .split("\n### ")
+        .filter(|entry| entry.starts_with("Bench\n"))
+        .last()
+        .and_then(|entry| entry.lines().find_map(|line| line.strip_prefix("- ")))
+        .unwrap_or_default();
+    log::info!("{} chapters: {}", chapters, totals);
+    Ok(())
+}
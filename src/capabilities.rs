@@ -0,0 +1,84 @@
+//! Which features of the book made it into print, and how.
+//!
+//! Print is not HTML, some content is rendered as is, some degraded to a
+//! printable equivalent and some dropped. The matrix is printed after the
+//! conversion, so the differences are visible at a glance.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Rendered,
+    // Replaced by a printable equivalent, described.
+    Degraded(&'static str),
+    Dropped,
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    rendered: usize,
+    degraded: usize,
+    dropped: usize,
+    how: BTreeSet<&'static str>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Capabilities {
+    features: BTreeMap<&'static str, Usage>,
+}
+
+impl Capabilities {
+    /// Record one use of `feature`.
+    pub(crate) fn record(&mut self, feature: &'static str, outcome: Outcome) {
+        let usage = self.features.entry(feature).or_default();
+        match outcome {
+            Outcome::Rendered => usage.rendered += 1,
+            Outcome::Degraded(how) => {
+                usage.degraded += 1;
+                usage.how.insert(how);
+            }
+            Outcome::Dropped => usage.dropped += 1,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    /// Whether `feature` was used at all.
+    #[cfg(test)]
+    pub(crate) fn contains(&self, feature: &str) -> bool {
+        self.features.contains_key(feature)
+    }
+
+    /// The matrix as plain text table.
+    pub(crate) fn render(&self) -> String {
+        let width = self
+            .features
+            .keys()
+            .map(|feature| feature.chars().count())
+            .max()
+            .unwrap_or_default()
+            .max("Feature".len());
+        let mut out = format!(
+            "{:<width$}  {:>8}  {:>8}  {:>7}  How\n",
+            "Feature",
+            "Rendered",
+            "Degraded",
+            "Dropped",
+            width = width
+        );
+        for (feature, usage) in &self.features {
+            out.push_str(&format!(
+                "{:<width$}  {:>8}  {:>8}  {:>7}  {}\n",
+                feature,
+                usage.rendered,
+                usage.degraded,
+                usage.dropped,
+                usage.how.iter().copied().collect::<Vec<_>>().join("; "),
+                width = width
+            ));
+        }
+        out
+    }
+}
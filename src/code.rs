@@ -0,0 +1,519 @@
+//! Code blocks which need more than `cmark2tex` renders them with.
+
+use crate::raw::escape;
+use crate::subprocess::{self, Limits};
+use crate::Traversal;
+use fs_err as fs;
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Report section of the example compile check.
+pub(crate) const SECTION: &str = "examples";
+
+/// Languages the template defines for `listings`.
+const LANGUAGES: &[&str] = &[
+    "rust",
+    "rs",
+    "console",
+    "handlebars",
+    "shell",
+    "json",
+    "yaml",
+    "toml",
+    "diff",
+    "javascript",
+    "text",
+    "hbs",
+    "cmd",
+    "powershell",
+    "makefile",
+    "markdown",
+    "python",
+];
+
+/// Parsed fence info string, ```` ```rust,ignore,caption="Parsing loop" ````.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Info {
+    pub(crate) language: Option<String>,
+    pub(crate) flags: Vec<String>,
+    pub(crate) attributes: Vec<(String, String)>,
+}
+
+impl Info {
+    pub(crate) fn parse(info: &str) -> Self {
+        let mut parsed = Self::default();
+        let mut items = Vec::new();
+        let mut item = String::new();
+        let mut quoted = false;
+        for c in info.chars() {
+            match c {
+                '"' => quoted = !quoted,
+                ',' | ' ' | '\t' if !quoted => items.push(std::mem::take(&mut item)),
+                c => item.push(c),
+            }
+        }
+        items.push(item);
+
+        for (idx, item) in items
+            .into_iter()
+            .filter(|item| !item.is_empty())
+            .enumerate()
+        {
+            if let Some((key, value)) = item.split_once('=') {
+                parsed.attributes.push((key.to_owned(), value.to_owned()));
+            } else if idx == 0 {
+                parsed.language = Some(item);
+            } else {
+                parsed.flags.push(item);
+            }
+        }
+        parsed
+    }
+
+    pub(crate) fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f == flag)
+    }
+
+    pub(crate) fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The language for `listings`, if the template defines it.
+    pub(crate) fn listings_language(&self) -> &str {
+        self.language
+            .as_deref()
+            .map(str::to_ascii_lowercase)
+            .and_then(|language| LANGUAGES.iter().find(|known| **known == language))
+            .copied()
+            .unwrap_or("text")
+    }
+}
+
+/// A code block taken out of the event stream.
+pub(crate) struct CodeBlock<'a> {
+    pub(crate) info: Info,
+    pub(crate) events: Vec<Event<'a>>,
+}
+
+impl<'a> CodeBlock<'a> {
+    pub(crate) fn code(&self) -> String {
+        self.events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Text(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Take the code block starting with `start` out of `events`.
+pub(crate) fn take_code_block<'a>(
+    start: Event<'a>,
+    events: &mut impl Iterator<Item = Event<'a>>,
+) -> CodeBlock<'a> {
+    let info = match start {
+        Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref info))) => Info::parse(info),
+        _ => Info::default(),
+    };
+    let mut block = vec![start];
+    for event in events {
+        let end = matches!(event, Event::End(Tag::CodeBlock(_)));
+        block.push(event);
+        if end {
+            break;
+        }
+    }
+    CodeBlock {
+        info,
+        events: block,
+    }
+}
+
+pub(crate) fn lstlisting(code: &str, options: &str) -> String {
+    let mut code = code.to_owned();
+    if !code.ends_with('\n') {
+        code.push('\n');
+    }
+    format!(
+        "\\begin{{lstlisting}}[{}]\n{}\\end{{lstlisting}}",
+        options, code
+    )
+}
+
+/// Render output blocks, ```` ```text,output ````, in a style of their own.
+///
+/// Directly following a listing, the pair shares one number and is kept
+/// on the same page.
+pub(crate) fn output_blocks<'a>(events: Vec<Event<'a>>, state: &mut Traversal) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter().peekable();
+    while let Some(event) = events.next() {
+        if !matches!(event, Event::Start(Tag::CodeBlock(_))) {
+            out.push(event);
+            continue;
+        }
+        let block = take_code_block(event, &mut events);
+        if block.info.has_flag("output") {
+            out.extend(state.raw.block(lstlisting(&block.code(), "style=output")));
+            continue;
+        }
+
+        let followed_by_output = matches!(
+            events.peek(),
+            Some(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info)))) if Info::parse(info).has_flag("output")
+        );
+        if !followed_by_output {
+            out.extend(block.events);
+            continue;
+        }
+        let start = events.next().expect("Peeked before. qed");
+        let output = take_code_block(start, &mut events);
+        let listing = escape(&state.labels.listing);
+        let tex = format!(
+            "\\refstepcounter{{lstlisting}}\n{}\n\\nopagebreak\n{}",
+            lstlisting(
+                &block.code(),
+                &format!(
+                    "language={},title={{{}~\\thelstlisting}}",
+                    block.info.listings_language(),
+                    listing
+                )
+            ),
+            lstlisting(
+                &output.code(),
+                &format!(
+                    "style=output,title={{{}~\\thelstlisting}}",
+                    escape(&state.labels.output_of_listing)
+                )
+            ),
+        );
+        out.extend(state.raw.block(tex));
+    }
+    out
+}
+
+/// A line of a Rust example as printed, `None` if it is hidden. Lines
+/// starting with `# ` are hidden by mdbook, if `hide` is set here, or else
+/// printed without the marker, and `##` stands for a literal `#`.
+pub(crate) fn rust_line(line: &str, hide: bool) -> Option<String> {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+    if let Some(escaped) = trimmed.strip_prefix("##") {
+        return Some(format!("{}#{}", indent, escaped));
+    }
+    match trimmed.strip_prefix('#') {
+        Some(rest) if rest.is_empty() || rest.starts_with(' ') => {
+            (!hide).then(|| format!("{}{}", indent, rest.strip_prefix(' ').unwrap_or(rest)))
+        }
+        _ => Some(line.to_owned()),
+    }
+}
+
+/// Strip the lines of Rust examples mdbook hides, or keep them without
+/// their marker if `hide-hidden-lines` is unset.
+pub(crate) fn hidden_lines<'a>(events: Vec<Event<'a>>, state: &mut Traversal) -> Vec<Event<'a>> {
+    let hide = state.cfg.hide_hidden_lines;
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        if !matches!(event, Event::Start(Tag::CodeBlock(_))) {
+            out.push(event);
+            continue;
+        }
+        let mut block = take_code_block(event, &mut events);
+        if block.info.language.as_deref() != Some("rust") {
+            out.extend(block.events);
+            continue;
+        }
+        let code = block
+            .code()
+            .lines()
+            .filter_map(|line| rust_line(line, hide))
+            .map(|line| line + "\n")
+            .collect::<String>();
+        let end = block.events.pop();
+        out.push(block.events.remove(0));
+        out.push(Event::Text(code.into()));
+        out.extend(end);
+    }
+    out
+}
+
+/// The revision source links point to, `source-rev` or else the commit
+/// checked out in `root`.
+pub(crate) fn revision(root: &Path, configured: Option<&str>) -> String {
+    if let Some(rev) = configured {
+        return rev.to_owned();
+    }
+    Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(root)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|rev| rev.trim().to_owned())
+        .unwrap_or_else(|| "HEAD".to_owned())
+}
+
+/// First line of an include range, `file.rs:10:20`, `file.rs:anchor` or
+/// `file.rs::20`.
+fn first_line(file: &Path, range: Option<&str>) -> usize {
+    match range {
+        None | Some("") => 1,
+        Some(range) => match range.parse::<usize>() {
+            Ok(line) => line.max(1),
+            Err(_) => crate::encoding::read_to_string(file)
+                .ok()
+                .and_then(|content| {
+                    content.lines().position(|line| {
+                        line.split("ANCHOR:")
+                            .nth(1)
+                            .map_or(false, |anchor| anchor.trim() == range)
+                    })
+                })
+                .map_or(1, |idx| idx + 2),
+        },
+    }
+}
+
+/// Included file and first line of every code block of the chapter source,
+/// if the block consists of a `{{#include}}` only.
+fn includes(source: &str, dir: &Path) -> color_eyre::Result<Vec<Option<(PathBuf, usize)>>> {
+    let re = regex::Regex::new(
+        r"^\{\{#(?:include|rustdoc_include)\s+([^}\s:]+)(?::([^}\s:]*))?(?::[^}\s]*)?\s*\}\}$",
+    )?;
+    let mut blocks = Vec::new();
+    let mut code = None;
+    for event in Parser::new_ext(source, Options::all()) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => code = Some(String::new()),
+            Event::Text(text) => {
+                if let Some(ref mut code) = code {
+                    code.push_str(&text);
+                }
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                let code = code.take().unwrap_or_default();
+                blocks.push(re.captures(code.trim()).map(|caps| {
+                    let file = crate::paths::resolve_dots(&dir.join(&caps[1]));
+                    let line = first_line(&file, caps.get(2).map(|range| range.as_str()));
+                    (file, line)
+                }));
+            }
+            _ => {}
+        }
+    }
+    Ok(blocks)
+}
+
+/// Link listings included with `{{#include path}}` to their source in the
+/// repository, below the listing or its output.
+///
+/// The includes are already expanded, so the code blocks of the chapter
+/// source are matched with the ones in the content by their order.
+pub(crate) fn source_links<'a>(
+    events: Vec<Event<'a>>,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    let (url, chapter) = match (&state.cfg.source_url, state.chapter) {
+        (Some(url), Some(chapter)) => (url, chapter),
+        _ => return Ok(events),
+    };
+    let root = &state.context.root;
+    let src = root.join(&state.context.config.book.src);
+    let source_path = match chapter.source_path {
+        Some(ref source_path) => src.join(source_path),
+        None => return Ok(events),
+    };
+    let source = match crate::encoding::read_to_string(&source_path) {
+        Ok(source) => source,
+        Err(_) => return Ok(events),
+    };
+    let dir = source_path.parent().unwrap_or(&src);
+    let includes = includes(&source, dir)?;
+    let blocks = events
+        .iter()
+        .filter(|event| matches!(event, Event::Start(Tag::CodeBlock(_))))
+        .count();
+    if blocks != includes.len() {
+        log::debug!(
+            "Code blocks of {} do not match its source, skipping source links",
+            chapter.name
+        );
+        return Ok(events);
+    }
+
+    let revision = state.revision.clone().unwrap_or_default();
+    let mut out = Vec::with_capacity(events.len());
+    let mut includes = includes.into_iter();
+    let mut pending = None;
+    let mut events = events.into_iter().peekable();
+    while let Some(event) = events.next() {
+        let start = matches!(event, Event::Start(Tag::CodeBlock(_)));
+        let end = matches!(event, Event::End(Tag::CodeBlock(_)));
+        out.push(event);
+        if start {
+            if let Some((file, line)) = includes.next().flatten() {
+                let path = file.strip_prefix(root).unwrap_or(&file);
+                let path = path
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                let link = url
+                    .replace("{rev}", &revision)
+                    .replace("{path}", &path)
+                    .replace("{start}", &line.to_string());
+                pending = Some(format!(
+                    "{{\\footnotesize\\hfill\\href{{{}}}{{\\texttt{{{}:{}}}}}}}",
+                    link.replace('%', "\\%").replace('#', "\\#"),
+                    escape(&path),
+                    line
+                ));
+            }
+        }
+        // Output blocks stay with their listing, the link goes below both.
+        let followed_by_output = matches!(
+            events.peek(),
+            Some(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info)))) if Info::parse(info).has_flag("output")
+        );
+        if end && !followed_by_output {
+            if let Some(link) = pending.take() {
+                out.extend(state.raw.block(link));
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Compile a Rust example with `rustc`, only checking it. Returns the
+/// errors, if any.
+///
+/// Lines hidden by mdbook, `# use std::fmt;`, are part of the example and
+/// examples without `fn main` are wrapped in one, as rustdoc does.
+fn compile(code: &str, edition: &str, limits: &Limits) -> color_eyre::Result<Option<String>> {
+    let mut source = code
+        .lines()
+        .map(|line| match line.trim_start().strip_prefix('#') {
+            Some(rest) if rest.is_empty() || rest.starts_with(' ') => rest.trim_start_matches(' '),
+            _ => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if !source.contains("fn main") {
+        source = format!("fn main() {{\n{}\n}}", source);
+    }
+
+    let dir = tempfile::tempdir()?;
+    let file = dir.path().join("example.rs");
+    fs::write(&file, source)?;
+    let mut command = Command::new("rustc");
+    command
+        .arg(format!("--edition={}", edition))
+        .args([
+            "--emit=metadata",
+            "--crate-type=bin",
+            "--error-format=short",
+        ])
+        .arg("--out-dir")
+        .arg(dir.path())
+        .arg(&file);
+    let output = match subprocess::run(command, None, limits) {
+        Ok(output) => output,
+        Err(err @ subprocess::Error::Timeout { .. }) => return Ok(Some(err.to_string())),
+        Err(err) => return Err(err.into()),
+    };
+    if output.status.success() {
+        return Ok(None);
+    }
+    let errors = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter(|line| line.contains("error"))
+        .filter_map(|line| {
+            line.split_once("error")
+                .map(|(_, error)| format!("error{}", error))
+        })
+        .take(3)
+        .collect::<Vec<_>>()
+        .join("; ");
+    Ok(Some(errors))
+}
+
+/// Check that the Rust examples of the chapter compile, before they are
+/// immortalized in print. Failures go into the build report, draft builds
+/// note them below the listing.
+///
+/// Examples flagged `ignore` are skipped, the ones flagged `compile_fail`
+/// must fail to compile.
+pub(crate) fn check_examples<'a>(
+    events: Vec<Event<'a>>,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    if !state.cfg.check_examples {
+        return Ok(events);
+    }
+    if which::which("rustc").is_err() {
+        log::warn!("Checking examples requires `rustc` to be installed, skipping");
+        return Ok(events);
+    }
+    // The editions are named by their serialization only.
+    let book_edition = match state.context.config.rust.edition {
+        Some(edition) => serde_json::to_string(&edition)?
+            .trim_matches('"')
+            .to_owned(),
+        None => "2015".to_owned(),
+    };
+
+    let mut findings = Vec::new();
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        if !matches!(event, Event::Start(Tag::CodeBlock(_))) {
+            out.push(event);
+            continue;
+        }
+        let block = take_code_block(event, &mut events);
+        let code = block.code();
+        let info = block.info;
+        out.extend(block.events);
+        if info.language.as_deref() != Some("rust") || info.has_flag("ignore") {
+            continue;
+        }
+        let edition = info
+            .flags
+            .iter()
+            .find_map(|flag| flag.strip_prefix("edition"))
+            .map_or(book_edition.clone(), str::to_owned);
+        let failure = match (
+            compile(&code, &edition, &state.cfg.limits)?,
+            info.has_flag("compile_fail"),
+        ) {
+            (Some(errors), false) => errors,
+            (None, true) => "compiles, although flagged `compile_fail`".to_owned(),
+            _ => continue,
+        };
+        let first_line = code
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or_default();
+        findings.push(format!("`{}`: {}", first_line.trim(), failure));
+        if state.cfg.draft {
+            out.extend(state.raw.block(format!(
+                "{{\\color{{red}}\\footnotesize [{}]}}",
+                escape(&failure)
+            )));
+        }
+    }
+    let chapter = state
+        .chapter
+        .map(|chapter| chapter.name.as_str())
+        .unwrap_or_default();
+    state.report.add(SECTION, chapter, findings);
+    Ok(out)
+}
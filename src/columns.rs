@@ -0,0 +1,176 @@
+//! Side-by-side content with fenced divs, handled on the markdown source.
+//!
+//! ```markdown
+//! ::: columns
+//! ::: column width=40%
+//! Original
+//! :::
+//! ::: column
+//! Translation
+//! :::
+//! :::
+//! ```
+//!
+//! Each column becomes a top aligned minipage. Columns without a `width`
+//! share the space left over by those with one. Widths adding up to more
+//! than the text width are shrunk in proportion.
+
+use crate::html::attribute;
+use crate::Traversal;
+use std::borrow::Cow;
+
+/// Horizontal space between two columns, as fraction of the text width.
+const GAP: f32 = 0.02;
+
+/// Name and attributes of an opening fence like `::: column width=40%`.
+fn opening_fence(line: &str) -> Option<(&str, &str)> {
+    let rest = line
+        .trim()
+        .strip_prefix(":::")?
+        .trim_start_matches(':')
+        .trim();
+    let (name, attributes) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    (!name.is_empty()).then_some((name, attributes))
+}
+
+fn is_closing_fence(line: &str) -> bool {
+    let line = line.trim();
+    line.len() >= 3 && line.chars().all(|c| c == ':')
+}
+
+/// Marker and length of a code fence line, `('~', 4)` of `~~~~rust`.
+fn code_fence(line: &str) -> Option<(char, usize)> {
+    let line = line.trim_start();
+    let marker = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = line.chars().take_while(|&c| c == marker).count();
+    (len >= 3).then_some((marker, len))
+}
+
+/// Track whether `line` opens or closes a code block, `fence` being the
+/// opening fence of the current one. Only a fence of the same marker, at
+/// least as long and without an info string closes it.
+fn track_code(fence: &mut Option<(char, usize)>, line: &str) {
+    match (*fence, code_fence(line)) {
+        (None, Some(opening)) => *fence = Some(opening),
+        (Some((marker, len)), Some((closing, closing_len)))
+            if closing == marker
+                && closing_len >= len
+                && line.trim().chars().all(|c| c == marker) =>
+        {
+            *fence = None
+        }
+        _ => {}
+    }
+}
+
+/// Width as fraction of the text width, `40%` or `0.4`.
+fn width(attributes: &str) -> Option<f32> {
+    let width = attribute(attributes, "width")?;
+    match width.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f32>().ok().map(|w| w / 100.),
+        None => width.trim().parse().ok(),
+    }
+}
+
+/// Replace `::: columns` blocks with minipages.
+pub(crate) fn columns<'t>(
+    content: &'t str,
+    state: &mut Traversal,
+) -> color_eyre::Result<Cow<'t, str>> {
+    if !content.contains(":::") {
+        return Ok(Cow::Borrowed(content));
+    }
+    let mut out = String::with_capacity(content.len());
+    let mut fence = None;
+    let mut lines = content.split_inclusive('\n');
+    while let Some(line) = lines.next() {
+        track_code(&mut fence, line);
+        match opening_fence(line) {
+            Some(("columns", _)) if fence.is_none() => render(&mut lines, &mut out, state)?,
+            _ => out.push_str(line),
+        }
+    }
+    Ok(Cow::Owned(out))
+}
+
+/// Render the columns up to the closing fence of the container.
+fn render<'t>(
+    lines: &mut impl Iterator<Item = &'t str>,
+    out: &mut String,
+    state: &mut Traversal,
+) -> color_eyre::Result<()> {
+    let mut columns: Vec<(Option<f32>, String)> = Vec::new();
+    let mut current: Option<(Option<f32>, String)> = None;
+    // Fenced divs nested within a column.
+    let mut depth = 0_usize;
+    let mut fence = None;
+    for line in lines {
+        track_code(&mut fence, line);
+        if fence.is_some() {
+            if let Some((_, ref mut column)) = current {
+                column.push_str(line);
+            }
+            continue;
+        }
+        if is_closing_fence(line) {
+            if depth > 0 {
+                depth -= 1;
+            } else if let Some(column) = current.take() {
+                columns.push(column);
+                continue;
+            } else {
+                break;
+            }
+        } else if let Some((name, attributes)) = opening_fence(line) {
+            if current.is_none() && name == "column" {
+                current = Some((width(attributes), String::new()));
+                continue;
+            }
+            depth += 1;
+        }
+        if let Some((_, ref mut column)) = current {
+            column.push_str(line);
+        }
+    }
+    if let Some(column) = current {
+        columns.push(column);
+    }
+    if columns.is_empty() {
+        return Ok(());
+    }
+
+    let available = 1. - GAP * (columns.len() - 1) as f32;
+    let fixed: f32 = columns.iter().filter_map(|(width, _)| *width).sum();
+    let flexible = columns.iter().filter(|(width, _)| width.is_none()).count();
+    // Columns with a width shrink to leave the others a tenth at least.
+    let minimum = 0.1 * flexible as f32;
+    let scale = if fixed + minimum > available {
+        log::warn!(
+            "Columns are {:.0}% of the text width together, shrinking them to fit",
+            (fixed + minimum) * 100.
+        );
+        (available - minimum) / fixed
+    } else {
+        1.
+    };
+    let share = ((available - fixed * scale) / flexible.max(1) as f32).max(0.1);
+
+    for (idx, (width, column)) in columns.iter().enumerate() {
+        let begin = format!(
+            "\\begin{{minipage}}[t]{{{:.3}\\textwidth}}",
+            width.map_or(share, |width| width * scale)
+        );
+        let tex = if idx == 0 {
+            format!("\\noindent{}", begin)
+        } else {
+            format!("\\end{{minipage}}\\hfill{}", begin)
+        };
+        out.push_str(&format!("\n\n{}\n\n", state.raw.token(tex)));
+        out.push_str(&self::columns(column, state)?);
+    }
+    out.push_str(&format!(
+        "\n\n{}\n\n",
+        state.raw.token("\\end{minipage}\\par")
+    ));
+    Ok(())
+}
@@ -0,0 +1,130 @@
+//! HTML comment handling.
+//!
+//! Comments are notes to fellow authors or directives for other tooling,
+//! neither belongs into print. They are stripped, except for directives
+//! addressed to this backend:
+//!
+//!   * `<!-- latex: \newpage -->` raw LaTeX
+//!   * `<!-- margin: some *markdown* -->` a margin note
+//!   * `<!-- index: term -->` an index entry
+//!
+//! In draft builds all other comments are rendered as highlighted notes.
+//!
+//! Pairs of comments also delimit content for specific renderers only,
+//! `<!-- only:pdf -->...<!-- /only -->`, or all but specific renderers,
+//! `<!-- not:pdf -->...<!-- /not -->`.
+
+use crate::capabilities::Outcome;
+use crate::html::{join_html, keep_html};
+use crate::index;
+use crate::raw::markdown_fragment_to_tex;
+use crate::shortcodes::replace_unescaped;
+use crate::Traversal;
+use pulldown_cmark::{Event, Tag};
+use std::borrow::Cow;
+
+/// Renderer names this backend answers to in conditional blocks.
+const RENDERERS: &[&str] = &["latex", "pdf", "print"];
+
+/// Evaluate conditional blocks, on the markdown source.
+///
+/// Multiple renderers can be listed separated by commas, `only:pdf,epub`.
+pub(crate) fn conditionals(content: &str) -> color_eyre::Result<Cow<'_, str>> {
+    let re = regex::Regex::new(
+        r"(?s)<!--\s*(only|not):\s*([\w,\s-]+?)\s*-->(.*?)<!--\s*/(?:only|not)\s*-->",
+    )?;
+    replace_unescaped(content, &re, |caps| {
+        let ours = caps[2]
+            .split(',')
+            .any(|renderer| RENDERERS.contains(&renderer.trim()));
+        Ok(if ours == (&caps[1] == "only") {
+            caps[3].to_owned()
+        } else {
+            String::new()
+        })
+    })
+}
+
+/// Tags whose content is inline, html within them is inline html.
+fn is_inline_container(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Paragraph
+            | Tag::Heading(..)
+            | Tag::TableCell
+            | Tag::Emphasis
+            | Tag::Strong
+            | Tag::Strikethrough
+            | Tag::Link(..)
+            | Tag::Image(..)
+    )
+}
+
+/// LaTeX for the content of a comment, `None` if it is stripped.
+fn comment_to_tex(body: &str, state: &mut Traversal) -> color_eyre::Result<Option<String>> {
+    let body = body.trim();
+    Ok(if let Some(tex) = body.strip_prefix("latex:") {
+        Some(tex.trim().to_owned())
+    } else if let Some(note) = body.strip_prefix("margin:") {
+        Some(format!(
+            "\\marginpar{{{}}}",
+            markdown_fragment_to_tex(note)?
+        ))
+    } else if let Some(term) = body.strip_prefix("index:") {
+        Some(index::entry(term.trim(), state))
+    } else if state.cfg.draft {
+        state.capabilities.record(
+            "HTML comments",
+            Outcome::Degraded("highlighted draft notes"),
+        );
+        Some(format!(
+            "{{\\color{{red}}\\footnotesize [{}]}}",
+            markdown_fragment_to_tex(body)?
+        ))
+    } else {
+        None
+    })
+}
+
+/// Strip HTML comments, translating directives.
+pub(crate) fn html_comments<'a>(
+    events: Vec<Event<'a>>,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    let re = regex::Regex::new(r"(?s)<!--(.*?)-->")?;
+    let mut out = Vec::with_capacity(events.len());
+    let mut inline_depth = 0_usize;
+    let mut events = events.into_iter().peekable();
+    while let Some(event) = events.next() {
+        let html = match event {
+            Event::Html(html) => html,
+            event => {
+                match event {
+                    Event::Start(ref tag) if is_inline_container(tag) => inline_depth += 1,
+                    Event::End(ref tag) if is_inline_container(tag) => inline_depth -= 1,
+                    _ => {}
+                }
+                out.push(event);
+                continue;
+            }
+        };
+
+        let html = join_html(html, &mut events);
+
+        let mut last = 0;
+        for caps in re.captures_iter(&html) {
+            let comment = caps.get(0).expect("Group 0 is the whole match. qed");
+            keep_html(&html[last..comment.start()], &mut out);
+            last = comment.end();
+            if let Some(tex) = comment_to_tex(&caps[1], state)? {
+                if inline_depth == 0 {
+                    out.extend(state.raw.block(tex));
+                } else {
+                    out.push(state.raw.inline(tex));
+                }
+            }
+        }
+        keep_html(&html[last..], &mut out);
+    }
+    Ok(out)
+}
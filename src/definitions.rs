@@ -0,0 +1,128 @@
+//! Definition lists, in the syntax of PHP Markdown Extra and pandoc, which
+//! `pulldown-cmark` parses as plain paragraphs:
+//!
+//! ```markdown
+//! Borrow checker
+//! : Enforces the rules of ownership at compile time.
+//! : Rejects programs it can not prove sound.
+//! ```
+
+use crate::Traversal;
+use pulldown_cmark::{CowStr, Event, Tag};
+
+/// Split the content of a paragraph into its lines.
+fn lines<'a>(content: Vec<Event<'a>>) -> Vec<Vec<Event<'a>>> {
+    let mut lines = vec![Vec::new()];
+    for event in content {
+        match event {
+            Event::SoftBreak | Event::HardBreak => lines.push(Vec::new()),
+            event => lines
+                .last_mut()
+                .expect("Starts with one line. qed")
+                .push(event),
+        }
+    }
+    lines
+}
+
+/// Strip the `:` marking a definition, `line` is given back if it is none.
+fn definition<'a>(mut line: Vec<Event<'a>>) -> Result<Vec<Event<'a>>, Vec<Event<'a>>> {
+    let rest = match line.first() {
+        Some(Event::Text(text)) => match text.strip_prefix(':') {
+            Some(rest) if rest.is_empty() || rest.starts_with(' ') => rest.trim_start().to_owned(),
+            _ => return Err(line),
+        },
+        _ => return Err(line),
+    };
+    if rest.is_empty() {
+        line.remove(0);
+    } else {
+        line[0] = Event::Text(CowStr::from(rest));
+    }
+    Ok(line)
+}
+
+/// The items of a paragraph which is a definition list, each a term and its
+/// definitions, or the paragraph's content if it is none.
+#[allow(clippy::type_complexity)]
+fn items<'a>(
+    content: Vec<Event<'a>>,
+) -> Result<Vec<(Vec<Event<'a>>, Vec<Vec<Event<'a>>>)>, Vec<Event<'a>>> {
+    let lines = lines(content.clone());
+    if lines.len() < 2 {
+        return Err(content);
+    }
+    let mut items: Vec<(Vec<Event<'a>>, Vec<Vec<Event<'a>>>)> = Vec::new();
+    for line in lines {
+        match (definition(line), items.last_mut()) {
+            (Ok(definition), Some((_, definitions))) => definitions.push(definition),
+            (Ok(_), None) => return Err(content),
+            (Err(term), _) => items.push((term, Vec::new())),
+        }
+    }
+    if items.iter().any(|(_, definitions)| definitions.is_empty()) {
+        return Err(content);
+    }
+    Ok(items)
+}
+
+/// Turn definition lists into `description` environments. Consecutive
+/// lists, separated by blank lines only, become one.
+pub(crate) fn definition_lists<'a>(
+    events: Vec<Event<'a>>,
+    state: &mut Traversal,
+) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut open = false;
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        if event != Event::Start(Tag::Paragraph) {
+            if open {
+                out.extend(state.raw.block("\\end{description}"));
+                open = false;
+            }
+            out.push(event);
+            continue;
+        }
+        let content = events
+            .by_ref()
+            .take_while(|event| *event != Event::End(Tag::Paragraph))
+            .collect::<Vec<_>>();
+        let items = match items(content) {
+            Ok(items) => items,
+            Err(content) => {
+                if open {
+                    out.extend(state.raw.block("\\end{description}"));
+                    open = false;
+                }
+                out.push(Event::Start(Tag::Paragraph));
+                out.extend(content);
+                out.push(Event::End(Tag::Paragraph));
+                continue;
+            }
+        };
+        if !open {
+            out.extend(state.raw.block("\\begin{description}"));
+            open = true;
+        }
+        out.push(Event::Start(Tag::Paragraph));
+        for (term, definitions) in items {
+            // Braced, terms may contain brackets.
+            out.push(state.raw.inline("\\item[{"));
+            out.extend(term);
+            out.push(state.raw.inline("}] "));
+            for (idx, definition) in definitions.into_iter().enumerate() {
+                if idx > 0 {
+                    out.push(state.raw.inline("\\par "));
+                }
+                out.extend(definition);
+            }
+            out.push(state.raw.inline("\n"));
+        }
+        out.push(Event::End(Tag::Paragraph));
+    }
+    if open {
+        out.extend(state.raw.block("\\end{description}"));
+    }
+    out
+}
@@ -0,0 +1,165 @@
+//! `mdbook-tectonic doctor`, a readiness report of the environment and the
+//! config of a book, before a build fails minutes in.
+
+use crate::{load_config, profile, Engine, Highlighting, LatexConfig, Profile};
+use std::path::{Path, PathBuf};
+
+/// External tools, the options needing them and what for.
+const TOOLS: &[(&str, &str)] = &[
+    ("qpdf", "`check-outline`, `page-map` and `split-chapters`"),
+    ("pdftoppm", "`visual`, comparing pages"),
+    ("hunspell", "`spellcheck`"),
+    ("rustc", "`check-examples`"),
+    ("git", "`source-url`, finding the revision"),
+    ("fc-list", "finding installed fonts"),
+    ("mmdc", "rendering mermaid diagrams"),
+    ("dot", "rendering graphviz diagrams"),
+    ("pygmentize", "syntax highlighting with minted"),
+];
+
+/// Tools the config of a book requires.
+pub(crate) fn required(cfg: &LatexConfig) -> Vec<&'static str> {
+    let mut required = Vec::new();
+    if cfg.check_outline || cfg.page_map || cfg.split_chapters {
+        required.push("qpdf");
+    }
+    if cfg.visual.references.is_some() {
+        required.push("pdftoppm");
+    }
+    if cfg.spellcheck.enable {
+        required.push("hunspell");
+    }
+    if cfg.check_examples {
+        required.push("rustc");
+    }
+    if cfg.highlighting == Some(Highlighting::Minted) {
+        required.push("pygmentize");
+    }
+    if cfg.source_url.is_some() && cfg.source_rev.is_none() {
+        required.push("git");
+    }
+    required
+}
+
+/// Cache of the bundle Tectonic downloads its TeX packages into.
+fn tectonic_cache() -> Option<PathBuf> {
+    let home = PathBuf::from(std::env::var_os("HOME")?);
+    Some(if cfg!(target_os = "macos") {
+        home.join("Library/Caches/Tectonic")
+    } else {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home.join(".cache"))
+            .join("Tectonic")
+    })
+}
+
+fn status(ok: bool) -> &'static str {
+    if ok {
+        "ok"
+    } else {
+        "missing"
+    }
+}
+
+/// Print the readiness report for the book at `root`, returns whether a
+/// build is expected to succeed.
+pub(crate) fn run(root: &Path) -> color_eyre::Result<bool> {
+    let mut ready = true;
+
+    println!("Config");
+    let cfg = match mdbook::Config::from_disk(root.join("book.toml"))
+        .map_err(|err| color_eyre::eyre::eyre!("{}", err))
+        .and_then(|config| load_config(&config, None))
+    {
+        Ok(cfg) => {
+            println!("  ok       {}", root.join("book.toml").display());
+            Some(cfg)
+        }
+        Err(err) => {
+            println!("  invalid  {}", err);
+            ready = false;
+            None
+        }
+    };
+    let cfg = cfg.unwrap_or_default();
+
+    println!("\nEngines");
+    let mut engines = 0;
+    for engine in [Engine::Tectonic, Engine::Lualatex] {
+        let name = match engine {
+            Engine::Tectonic => "tectonic",
+            Engine::Lualatex => "lualatex",
+        };
+        let found = which::which(name).is_ok();
+        engines += usize::from(found);
+        let selected = cfg.engine == Some(engine);
+        println!(
+            "  {:<8} {}{}",
+            status(found),
+            name,
+            if selected { " (selected)" } else { "" }
+        );
+        if selected && !found && cfg.pdf {
+            ready = false;
+        }
+    }
+    if engines == 0 && cfg.pdf {
+        ready = false;
+    }
+    if which::which("tectonic").is_ok() {
+        match tectonic_cache() {
+            Some(cache) if cache.is_dir() => {
+                println!("  ok       bundle cache {}", cache.display())
+            }
+            Some(cache) => println!(
+                "  empty    bundle cache {}, the first build downloads the packages",
+                cache.display()
+            ),
+            None => {}
+        }
+    }
+
+    println!("\nTools");
+    let required = required(&cfg);
+    for (tool, purpose) in TOOLS {
+        let found = which::which(tool).is_ok();
+        let needed = required.contains(tool);
+        println!(
+            "  {:<8} {:<11} {}{}",
+            status(found),
+            tool,
+            purpose,
+            if needed {
+                ", required by the config"
+            } else {
+                ""
+            }
+        );
+        if needed && !found {
+            ready = false;
+        }
+    }
+
+    println!("\nFonts");
+    let mut fonts = cfg.fonts.values().cloned().collect::<Vec<_>>();
+    if cfg.profile == Some(Profile::Dyslexia) {
+        fonts.extend(profile::DYSLEXIA_FONTS.iter().map(|font| font.to_string()));
+    }
+    if fonts.is_empty() {
+        println!("  none configured");
+    }
+    for font in fonts {
+        println!("  {:<8} {}", status(profile::installed(&font)), font);
+    }
+
+    println!(
+        "\n{}",
+        if ready {
+            "Ready to build."
+        } else {
+            "Not ready to build."
+        }
+    );
+    Ok(ready)
+}
@@ -0,0 +1,129 @@
+//! Text saved in other encodings than plain UTF-8.
+//!
+//! Byte order marks are stripped, wherever they are, as includes copy them
+//! into the middle of a chapter. Files this backend reads itself, like the
+//! sources of listings, are transcoded from UTF-16 or Windows-1252 with a
+//! warning. mdbook reads the chapters, and fails for any which is not
+//! UTF-8, so for them only what is left of a broken conversion, replacement
+//! characters and mojibake, is reported.
+//!
+//! Line endings are normalized to `\n` as well, so hard breaks of two
+//! trailing spaces, verbatim blocks and line numbers come out the same for
+//! files saved on Windows, `\r\n`, or old Macs, `\r`.
+
+use crate::Traversal;
+use std::borrow::Cow;
+use std::path::Path;
+
+pub(crate) const SECTION: &str = "encoding";
+
+/// The byte order mark, U+FEFF.
+const BOM: char = '\u{feff}';
+
+/// Characters of Windows-1252 from 0x80 to 0x9F, where it differs from
+/// Latin-1. Undefined ones map to the control characters of Latin-1.
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+/// UTF-8 read as Windows-1252 or Latin-1, then saved as UTF-8 again.
+const MOJIBAKE: &[&str] = &["Ã©", "Ã¨", "Ã¤", "Ã¶", "Ã¼", "ÃŸ", "Ã±", "â€"];
+
+/// `bytes` as text, and the encoding they were transcoded from if not UTF-8.
+pub(crate) fn decode(bytes: &[u8]) -> (String, Option<&'static str>) {
+    let utf16 = |bytes: &[u8], from: fn([u8; 2]) -> u16| {
+        let units = bytes
+            .chunks_exact(2)
+            .map(|pair| from([pair[0], pair[1]]))
+            .collect::<Vec<_>>();
+        String::from_utf16_lossy(&units)
+    };
+    if let Some(rest) = bytes.strip_prefix(b"\xff\xfe") {
+        return (utf16(rest, u16::from_le_bytes), Some("UTF-16LE"));
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xfe\xff") {
+        return (utf16(rest, u16::from_be_bytes), Some("UTF-16BE"));
+    }
+    let bytes = bytes.strip_prefix(b"\xef\xbb\xbf").unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (text.to_owned(), None),
+        Err(_) => {
+            let text = bytes
+                .iter()
+                .map(|byte| match byte {
+                    0x80..=0x9f => WINDOWS_1252[(byte - 0x80) as usize],
+                    byte => *byte as char,
+                })
+                .collect();
+            (text, Some("Windows-1252"))
+        }
+    }
+}
+
+/// `text` with `\n` line endings, and without byte order marks.
+pub(crate) fn normalize(text: &str) -> Cow<'_, str> {
+    if text.contains([BOM, '\r']) {
+        Cow::Owned(
+            text.replace(BOM, "")
+                .replace("\r\n", "\n")
+                .replace('\r', "\n"),
+        )
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// The content of the file at `path`, transcoded to UTF-8 with a warning if
+/// it is in another encoding, normalized.
+pub(crate) fn read_to_string(path: &Path) -> std::io::Result<String> {
+    let (text, encoding) = decode(&fs_err::read(path)?);
+    if let Some(encoding) = encoding {
+        log::warn!(
+            "{} is not UTF-8, read as {}, consider saving it as UTF-8",
+            path.display(),
+            encoding
+        );
+    }
+    Ok(normalize(&text).into_owned())
+}
+
+/// `content` of the current chapter normalized, reporting lines with
+/// replacement characters or mojibake.
+pub(crate) fn clean<'c>(content: &'c str, state: &mut Traversal) -> Cow<'c, str> {
+    let content = normalize(content);
+    let file = state
+        .chapter
+        .and_then(|chapter| chapter.source_path.as_ref().or(chapter.path.as_ref()))
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+    let mut findings = Vec::new();
+    for (idx, line) in content.lines().enumerate() {
+        if line.contains(char::REPLACEMENT_CHARACTER) {
+            findings.push(format!(
+                "`{}:{}`: replacement character, the text was not UTF-8",
+                file,
+                idx + 1
+            ));
+        } else if MOJIBAKE.iter().any(|mojibake| line.contains(mojibake)) {
+            findings.push(format!(
+                "`{}:{}`: mojibake, UTF-8 read in another encoding",
+                file,
+                idx + 1
+            ));
+        }
+    }
+    if !findings.is_empty() {
+        log::warn!(
+            "{} has {} lines of broken characters, see the build report",
+            file,
+            findings.len()
+        );
+    }
+    let chapter = state
+        .chapter
+        .map(|chapter| chapter.name.as_str())
+        .unwrap_or_default();
+    state.report.add(SECTION, chapter, findings);
+    content
+}
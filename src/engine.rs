@@ -0,0 +1,135 @@
+//! Choice of the TeX engine, according to the content of the book.
+
+use crate::{math, Engine, LatexConfig, Profile};
+use mdbook::book::{Book, BookItem};
+
+/// Content not every engine, or not without additional packages, can
+/// typeset.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Features {
+    // Chinese, Japanese or Korean text.
+    pub(crate) cjk: bool,
+    // Right to left scripts, Hebrew or Arabic.
+    pub(crate) rtl: bool,
+    // Emoji, which only LuaTeX renders in color.
+    pub(crate) emoji: bool,
+    // Fonts configured by name, as both engines load them with fontspec.
+    pub(crate) opentype: bool,
+    // Math with symbols beyond ASCII, typeset by unicode-math.
+    pub(crate) unicode_math: bool,
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}' // Hiragana, Katakana
+        | '\u{3400}'..='\u{4DBF}' // CJK Extension A
+        | '\u{4E00}'..='\u{9FFF}' // CJK Unified Ideographs
+        | '\u{AC00}'..='\u{D7AF}' // Hangul Syllables
+        | '\u{F900}'..='\u{FAFF}' // CJK Compatibility Ideographs
+    )
+}
+
+fn is_rtl(c: char) -> bool {
+    matches!(c,
+        '\u{0590}'..='\u{08FF}' // Hebrew, Arabic, Syriac, Thaana, ...
+        | '\u{FB1D}'..='\u{FDFF}' // Presentation Forms A
+        | '\u{FE70}'..='\u{FEFF}' // Arabic Presentation Forms B
+    )
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(c,
+        '\u{1F300}'..='\u{1FAFF}' // Pictographs, Emoticons, Transport, ...
+        | '\u{2600}'..='\u{27BF}' // Miscellaneous Symbols, Dingbats
+    )
+}
+
+impl Features {
+    /// Scan all chapters of `book` not ignored, and the fonts of `cfg`.
+    pub(crate) fn scan(book: &Book, cfg: &LatexConfig) -> color_eyre::Result<Self> {
+        let mut features = Self {
+            opentype: !cfg.fonts.is_empty() || cfg.profile == Some(Profile::Dyslexia),
+            ..Self::default()
+        };
+        for item in book.iter() {
+            if let BookItem::Chapter(ref ch) = *item {
+                if cfg.ignores.contains(&ch.name) {
+                    continue;
+                }
+                for c in ch.content.chars() {
+                    features.cjk |= is_cjk(c);
+                    features.rtl |= is_rtl(c);
+                    features.emoji |= is_emoji(c);
+                }
+                features.unicode_math |= math::has_unicode(&ch.content, cfg)?;
+            }
+        }
+        Ok(features)
+    }
+
+    /// Babel package options for the detected content.
+    pub(crate) fn babel_options(&self, engine: Engine) -> &'static str {
+        match engine {
+            Engine::Lualatex if self.rtl => "bidi=basic,",
+            _ => "",
+        }
+    }
+
+    /// Preamble additions for the detected content, right after the
+    /// packages, as fonts are set up by them.
+    pub(crate) fn preamble(&self, engine: Engine, vertical: bool) -> String {
+        let mut preamble = String::new();
+        if self.opentype {
+            preamble.push_str("\\usepackage{fontspec}\n");
+        }
+        // After amsmath, which is among the packages.
+        if self.unicode_math {
+            preamble.push_str("\\usepackage{unicode-math}\n");
+        }
+        if self.cjk && !vertical {
+            preamble.push_str(match engine {
+                Engine::Tectonic => "\\usepackage{xeCJK}\n",
+                Engine::Lualatex => "\\usepackage{luatexja}\n",
+            });
+        }
+        preamble
+    }
+
+    /// The `bidi` package, which must be loaded last, for right to left
+    /// scripts. LuaTeX gets babel's bidi option instead.
+    pub(crate) fn bidi(&self, engine: Engine) -> &'static str {
+        match engine {
+            Engine::Tectonic if self.rtl => "\\usepackage{bidi}\n",
+            _ => "",
+        }
+    }
+}
+
+/// The configured engine, or the one fit for the content if none is.
+///
+/// Content beyond the configured engine is only warned about, except for
+/// vertical writing, which fails the build.
+pub(crate) fn select(features: Features, cfg: &LatexConfig) -> color_eyre::Result<Engine> {
+    let needs_luatex = cfg.vertical || features.emoji;
+    let engine = match cfg.engine {
+        Some(Engine::Tectonic) if cfg.vertical => {
+            color_eyre::eyre::bail!("Vertical writing requires `engine = \"lualatex\"`")
+        }
+        Some(Engine::Tectonic) if features.emoji => {
+            log::warn!("Emoji are only rendered in color with `engine = \"lualatex\"`");
+            Engine::Tectonic
+        }
+        Some(engine) => engine,
+        None if needs_luatex && (!cfg.pdf || which::which("lualatex").is_ok()) => Engine::Lualatex,
+        None if cfg.vertical => {
+            color_eyre::eyre::bail!("Vertical writing requires `lualatex` to be installed")
+        }
+        None if needs_luatex => {
+            log::warn!("Emoji are only rendered in color by `lualatex`, which is not installed");
+            Engine::Tectonic
+        }
+        None => Engine::Tectonic,
+    };
+    log::debug!("Selected engine {:?} for {:?}", engine, features);
+    Ok(engine)
+}
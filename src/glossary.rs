@@ -0,0 +1,112 @@
+//! First use styling of glossary terms.
+
+use crate::index;
+use crate::raw::escape;
+use crate::{TermStyle, Traversal};
+use pulldown_cmark::{CowStr, Event, Tag};
+use std::collections::BTreeSet;
+
+/// Tags within which terms are left alone.
+fn is_verbatim(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Heading(..) | Tag::Link(..) | Tag::Image(..) | Tag::CodeBlock(_)
+    )
+}
+
+/// Quote the characters `makeindex` treats specially.
+fn index_entry(term: &str) -> String {
+    let mut entry = String::with_capacity(term.len());
+    for c in escape(term).chars() {
+        if matches!(c, '!' | '@' | '|' | '"') {
+            entry.push('"');
+        }
+        entry.push(c);
+    }
+    entry
+}
+
+/// Style the first occurrence of each glossary term per chapter according
+/// to `first-use`, and add an index entry for it.
+pub(crate) fn first_use<'a>(
+    events: Vec<Event<'a>>,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    let command = match state.cfg.first_use {
+        TermStyle::None => return Ok(events),
+        TermStyle::Bold => "textbf",
+        TermStyle::Italic => "emph",
+    };
+    if state.cfg.glossary.is_empty() {
+        return Ok(events);
+    }
+
+    // Longest first, so terms which contain others win.
+    let mut terms = state.cfg.glossary.clone();
+    terms.sort_by_key(|term| std::cmp::Reverse(term.len()));
+    let re = regex::Regex::new(&format!(
+        r"(?i)\b(?:{})\b",
+        terms
+            .iter()
+            .map(|term| regex::escape(term))
+            .collect::<Vec<_>>()
+            .join("|")
+    ))?;
+
+    let mut seen = BTreeSet::new();
+    let mut verbatim = 0_usize;
+    let mut out = Vec::with_capacity(events.len());
+    for event in events {
+        let text = match event {
+            Event::Text(ref text) if verbatim == 0 && seen.len() < terms.len() => text,
+            Event::Start(ref tag) if is_verbatim(tag) => {
+                verbatim += 1;
+                out.push(event);
+                continue;
+            }
+            Event::End(ref tag) if is_verbatim(tag) => {
+                verbatim -= 1;
+                out.push(event);
+                continue;
+            }
+            event => {
+                out.push(event);
+                continue;
+            }
+        };
+
+        let mut last = 0;
+        let mut pieces = Vec::new();
+        for found in re.find_iter(text) {
+            let found_lowercase = found.as_str().to_lowercase();
+            let term = match terms
+                .iter()
+                .find(|term| term.to_lowercase() == found_lowercase)
+            {
+                Some(term) => term,
+                None => continue,
+            };
+            if !seen.insert(term.to_lowercase()) {
+                continue;
+            }
+            pieces.push(Event::Text(CowStr::from(
+                text[last..found.start()].to_owned(),
+            )));
+            let entry = index::entry(&index_entry(term), state);
+            pieces.push(state.raw.inline(format!(
+                "\\{}{{{}}}{}",
+                command,
+                escape(found.as_str()),
+                entry
+            )));
+            last = found.end();
+        }
+        if pieces.is_empty() {
+            out.push(event);
+        } else {
+            out.extend(pieces);
+            out.push(Event::Text(CowStr::from(text[last..].to_owned())));
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,395 @@
+//! Syntax highlighting of code blocks, by the backend set in `highlighting`.
+//!
+//! `minted` highlights with Pygments, which the engine runs, and so needs
+//! shell escape. `listings` highlights within TeX, with language
+//! definitions and styles of its own for the languages `listings` lacks.
+//!
+//! Code blocks with a `caption` or `label` attribute are numbered listings,
+//! whatever the backend, to be referenced with `{{#ref label}}`:
+//!
+//! ````markdown
+//! ```rust,caption="Parsing loop",label=lst:parse
+//! ````
+//!
+//! Lines are highlighted as in the HTML output, by the `hl_lines` attribute,
+//! e.g. `hl_lines="1 3-5"`.
+
+use crate::capabilities::Outcome;
+use crate::code::{lstlisting, take_code_block, Info};
+use crate::raw::escape;
+use crate::{Highlighting, Traversal};
+use pulldown_cmark::{Event, Tag};
+use std::collections::BTreeMap;
+
+/// Fence languages which Pygments knows by another name.
+const PYGMENTS_ALIASES: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("sh", "bash"),
+    ("shell", "bash"),
+    ("hbs", "handlebars"),
+    ("js", "javascript"),
+    ("ts", "typescript"),
+    ("py", "python"),
+    ("yml", "yaml"),
+    ("cmd", "batch"),
+    ("md", "markdown"),
+    ("console", "shell-session"),
+];
+
+/// Languages Pygments has a lexer for, of those common in books, an unknown
+/// one fails the build.
+const PYGMENTS_LANGUAGES: &[&str] = &[
+    "rust",
+    "bash",
+    "shell-session",
+    "powershell",
+    "batch",
+    "handlebars",
+    "json",
+    "yaml",
+    "toml",
+    "ini",
+    "diff",
+    "javascript",
+    "typescript",
+    "python",
+    "c",
+    "cpp",
+    "go",
+    "java",
+    "kotlin",
+    "swift",
+    "haskell",
+    "ocaml",
+    "sql",
+    "html",
+    "xml",
+    "css",
+    "makefile",
+    "markdown",
+    "latex",
+    "wasm",
+    "text",
+];
+
+/// The Pygments lexer for the fence language, `text` if it has none.
+pub(crate) fn pygments_language(info: &Info) -> &'static str {
+    let language = info
+        .language
+        .as_deref()
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    let language = PYGMENTS_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == language)
+        .map_or(language.as_str(), |(_, name)| name);
+    PYGMENTS_LANGUAGES
+        .iter()
+        .find(|known| **known == language)
+        .copied()
+        .unwrap_or("text")
+}
+
+/// Fence languages and the `listings` language for them, either built in
+/// or defined in `LISTINGS_PREAMBLE`.
+const LISTINGS_LANGUAGES: &[(&str, &str)] = &[
+    ("rust", "mdbookrust"),
+    ("rs", "mdbookrust"),
+    ("toml", "mdbooktoml"),
+    ("javascript", "mdbookjavascript"),
+    ("js", "mdbookjavascript"),
+    ("typescript", "mdbookjavascript"),
+    ("ts", "mdbookjavascript"),
+    ("json", "mdbookjavascript"),
+    ("bash", "bash"),
+    ("sh", "bash"),
+    ("shell", "bash"),
+    ("console", "bash"),
+    ("python", "Python"),
+    ("py", "Python"),
+    ("c", "C"),
+    ("cpp", "C++"),
+    ("java", "Java"),
+    ("haskell", "Haskell"),
+    ("sql", "SQL"),
+    ("html", "HTML"),
+    ("xml", "XML"),
+    ("makefile", "make"),
+    ("latex", "TeX"),
+    ("tex", "TeX"),
+];
+
+/// Language definitions and a style per language of the `listings`
+/// backend, each style named like its language.
+pub(crate) const LISTINGS_PREAMBLE: &str = r#"
+%% Syntax highlighting with listings.
+\colorlet{mdbookkeywords}{blue!70!black}
+\colorlet{mdbooktypes}{teal!70!black}
+\colorlet{mdbookstrings}{red!60!black}
+\colorlet{mdbookcomments}{black!55}
+\lstdefinestyle{mdbookcode}{
+  keywordstyle=\color{mdbookkeywords}\bfseries,
+  keywordstyle=[2]\color{mdbooktypes},
+  stringstyle=\color{mdbookstrings},
+  commentstyle=\color{mdbookcomments}\itshape
+}
+\lstdefinelanguage{mdbookplain}{}
+\lstdefinestyle{mdbookplain}{language=mdbookplain}
+\lstdefinelanguage{mdbookrust}{
+  morekeywords={as, async, await, break, const, continue, crate, dyn, else, enum, extern, false, fn, for, if, impl, in, let, loop, match, mod, move, mut, pub, ref, return, self, Self, static, struct, super, trait, true, type, union, unsafe, use, where, while},
+  morekeywords=[2]{bool, char, f32, f64, i8, i16, i32, i64, i128, isize, str, u8, u16, u32, u64, u128, usize, String, Vec, Option, Result, Box, Some, None, Ok, Err},
+  sensitive=true,
+  morecomment=[l]{//},
+  morecomment=[s]{/*}{*/},
+  morestring=[b]"
+}
+\lstdefinestyle{mdbookrust}{style=mdbookcode, language=mdbookrust}
+\lstdefinelanguage{mdbooktoml}{
+  morekeywords={true, false},
+  sensitive=true,
+  morecomment=[l]{\#},
+  morestring=[b]",
+  morestring=[b]'
+}
+\lstdefinestyle{mdbooktoml}{style=mdbookcode, language=mdbooktoml}
+\lstdefinelanguage{mdbookjavascript}{
+  morekeywords={async, await, break, case, catch, class, const, continue, default, delete, do, else, export, extends, false, finally, for, function, if, import, in, instanceof, let, new, null, return, switch, this, throw, true, try, typeof, undefined, var, void, while, yield},
+  sensitive=true,
+  morecomment=[l]{//},
+  morecomment=[s]{/*}{*/},
+  morestring=[b]",
+  morestring=[b]',
+  morestring=[b]`
+}
+\lstdefinestyle{mdbookjavascript}{style=mdbookcode, language=mdbookjavascript}
+\lstdefinestyle{bash}{style=mdbookcode, language=bash}
+\lstdefinestyle{Python}{style=mdbookcode, language=Python}
+\lstdefinestyle{C}{style=mdbookcode, language=C}
+\lstdefinestyle{C++}{style=mdbookcode, language=C++}
+\lstdefinestyle{Java}{style=mdbookcode, language=Java}
+\lstdefinestyle{Haskell}{style=mdbookcode, language=Haskell}
+\lstdefinestyle{SQL}{style=mdbookcode, language=SQL}
+\lstdefinestyle{HTML}{style=mdbookcode, language=HTML}
+\lstdefinestyle{XML}{style=mdbookcode, language=XML}
+\lstdefinestyle{make}{style=mdbookcode, language=make}
+\lstdefinestyle{TeX}{style=mdbookcode, language=TeX}
+"#;
+
+/// The `listings` options for the fence language, the language `languages`
+/// maps it to, else the built in one, else none.
+pub(crate) fn listings_options(info: &Info, languages: &BTreeMap<String, String>) -> String {
+    let language = info
+        .language
+        .as_deref()
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    if let Some(configured) = languages.get(&language) {
+        return format!("style=mdbookcode,language={{{}}}", configured);
+    }
+    match LISTINGS_LANGUAGES
+        .iter()
+        .find(|(fence, _)| *fence == language)
+    {
+        Some((_, listings)) => format!("style={{{}}}", listings),
+        None => {
+            if !language.is_empty() {
+                log::debug!("No listings language for `{}`, printed plain", language);
+            }
+            "style=mdbookplain".to_owned()
+        }
+    }
+}
+
+/// The caption and label of a code block, from the attributes of its fence.
+fn caption(info: &Info) -> (Option<String>, Option<&str>) {
+    (
+        info.attribute("caption").map(escape),
+        info.attribute("label"),
+    )
+}
+
+/// `listings` options captioning and labelling the code block.
+fn caption_options(info: &Info) -> String {
+    let mut options = String::new();
+    let (caption, label) = caption(info);
+    if let Some(caption) = caption {
+        options.push_str(&format!(",caption={{{}}}", caption));
+    }
+    if let Some(label) = label {
+        options.push_str(&format!(",label={{{}}}", label));
+    }
+    options
+}
+
+/// Name captions of listings start with, in either backend.
+pub(crate) fn caption_names(listing: &str) -> String {
+    let listing = escape(listing);
+    format!(
+        "\\def\\lstlistingname{{{}}}\n\\def\\listingscaption{{{}}}\n",
+        listing, listing
+    )
+}
+
+/// Line numbers of `hl_lines` ranges like `1 3-5` or `1,3-5`, in order.
+pub(crate) fn line_ranges(spec: &str) -> Vec<usize> {
+    let mut lines = Vec::new();
+    for range in spec.split(|c: char| c == ',' || c.is_whitespace()) {
+        if range.is_empty() {
+            continue;
+        }
+        let parsed = match range.split_once('-') {
+            Some((first, last)) => first
+                .trim()
+                .parse()
+                .and_then(|first: usize| last.trim().parse().map(|last: usize| first..=last)),
+            None => range.parse().map(|line: usize| line..=line),
+        };
+        match parsed {
+            Ok(range) => lines.extend(range),
+            Err(_) => log::warn!("Ignoring the line range `{}` of `hl_lines`", range),
+        }
+    }
+    lines.sort_unstable();
+    lines.dedup();
+    lines
+}
+
+/// Number style of `listings` tinting the highlighted lines of a listing,
+/// as set in `\mdbookhighlighted`, all across.
+pub(crate) const HIGHLIGHT_LINES_PREAMBLE: &str = r"
+%% Highlighted lines of listings.
+\makeatletter
+\newcommand{\mdbookhighlighted}{}
+\newcommand{\mdbookhlnumber}[1]{%
+  \def\mdbook@hl{0}%
+  \@for\mdbook@line:=\mdbookhighlighted\do{\ifnum\mdbook@line=\value{lstnumber}\def\mdbook@hl{1}\fi}%
+  \ifnum\mdbook@hl=1\relax
+    \makebox[0pt][l]{\hspace{\numbersep}\textcolor{yellow!30}{\rule[-0.3\baselineskip]{\linewidth}{\baselineskip}}}%
+  \fi
+  {\tiny\color{black!50}#1}}
+\makeatother
+";
+
+/// Whether the engine must be allowed to run programs, for `highlighting`.
+pub(crate) fn needs_shell_escape(highlighting: Option<Highlighting>) -> bool {
+    highlighting == Some(Highlighting::Minted)
+}
+
+/// Typeset code blocks in `minted` or `lstlisting` environments, by the
+/// `highlighting` backend, and captioned ones in numbered listings.
+pub(crate) fn highlight<'a>(events: Vec<Event<'a>>, state: &mut Traversal) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        if !matches!(event, Event::Start(Tag::CodeBlock(_))) {
+            out.push(event);
+            continue;
+        }
+        let block = take_code_block(event, &mut events);
+        let captioned = caption(&block.info) != (None, None);
+        let highlighted = block
+            .info
+            .attribute("hl_lines")
+            .map(line_ranges)
+            .unwrap_or_default()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        if state.cfg.highlighting.is_none() && !captioned && highlighted.is_empty() {
+            out.extend(block.events);
+            continue;
+        }
+        let mut code = block.code();
+        if !code.ends_with('\n') {
+            code.push('\n');
+        }
+        if captioned {
+            state.code_captions = true;
+            state
+                .capabilities
+                .record("Code captions", Outcome::Rendered);
+        }
+        if !highlighted.is_empty() {
+            state.highlighted_lines = true;
+            state
+                .capabilities
+                .record("Line highlighting", Outcome::Rendered);
+        }
+        // Listings get the highlighted lines by their number style.
+        let (listings_highlight, open, close) = if highlighted.is_empty() {
+            ("", String::new(), "")
+        } else {
+            state.packages.insert("xcolor");
+            (
+                ",numberstyle=\\mdbookhlnumber",
+                format!("{{\\def\\mdbookhighlighted{{{}}}", highlighted),
+                "}",
+            )
+        };
+        let tex = match state.cfg.highlighting {
+            Some(Highlighting::Minted) => {
+                state.packages.insert("minted");
+                let options = match highlighted.as_str() {
+                    "" => String::new(),
+                    lines => format!("[highlightlines={{{}}}]", lines),
+                };
+                let minted = format!(
+                    "\\begin{{minted}}{}{{{}}}\n{}\\end{{minted}}",
+                    options,
+                    pygments_language(&block.info),
+                    code
+                );
+                match caption(&block.info) {
+                    (None, None) => minted,
+                    // The float of `minted` is kept in place, like listings are.
+                    (caption, label) => format!(
+                        "\\begin{{listing}}[H]\n{}\n\\caption{{{}}}{}\n\\end{{listing}}",
+                        minted,
+                        caption.unwrap_or_default(),
+                        label.map_or(String::new(), |label| format!("\\label{{{}}}", label))
+                    ),
+                }
+            }
+            Some(Highlighting::Listings) => {
+                state.packages.insert("listings");
+                state.packages.insert("xcolor");
+                format!(
+                    "{}{}{}",
+                    open,
+                    lstlisting(
+                        &code,
+                        &format!(
+                            "{}{}{}",
+                            listings_options(&block.info, &state.cfg.listings_languages),
+                            caption_options(&block.info),
+                            listings_highlight
+                        ),
+                    ),
+                    close
+                )
+            }
+            None => format!(
+                "{}{}{}",
+                open,
+                lstlisting(
+                    &code,
+                    &format!(
+                        "language={}{}{}",
+                        block.info.listings_language(),
+                        caption_options(&block.info),
+                        listings_highlight
+                    ),
+                ),
+                close
+            ),
+        };
+        if state.cfg.highlighting.is_some() {
+            state
+                .capabilities
+                .record("Syntax highlighting", Outcome::Rendered);
+        }
+        out.extend(state.raw.block(tex));
+    }
+    out
+}
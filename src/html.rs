@@ -0,0 +1,353 @@
+//! Translation of raw HTML elements which have a sensible print equivalent.
+
+use crate::capabilities::Outcome;
+use crate::raw::{escape, markdown_fragment_to_tex};
+use crate::{Details, Traversal};
+use pulldown_cmark::{CowStr, Event, Tag};
+use regex::Regex;
+use std::iter::Peekable;
+
+/// Html blocks are reported line by line, join the consecutive html events
+/// following `first`.
+pub(crate) fn join_html<'a, I>(first: CowStr<'a>, events: &mut Peekable<I>) -> String
+where
+    I: Iterator<Item = Event<'a>>,
+{
+    let mut html = first.into_string();
+    while let Some(Event::Html(more)) = events.next_if(|event| matches!(event, Event::Html(_))) {
+        html.push_str(&more);
+    }
+    html
+}
+
+/// Value of the attribute `name` within the attributes of an html tag.
+pub(crate) fn attribute<'h>(attributes: &'h str, name: &str) -> Option<&'h str> {
+    let re = Regex::new(&format!(
+        r#"(?i)\b{}\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#,
+        regex::escape(name)
+    ))
+    .expect("Parses just fine. qed");
+    let caps = re.captures(attributes)?;
+    caps.get(1)
+        .or_else(|| caps.get(2))
+        .or_else(|| caps.get(3))
+        .map(|value| value.as_str())
+}
+
+/// Output of the `<details>` translation.
+struct DetailsOut<'s, 'c, 'a> {
+    state: &'s mut Traversal<'c>,
+    out: Vec<Event<'a>>,
+    // Open `<details>`, and whether they were begun yet.
+    open: Vec<bool>,
+}
+
+impl<'s, 'c, 'a> DetailsOut<'s, 'c, 'a> {
+    /// Begin the innermost `<details>` unless already done, the summary is
+    /// not mandatory.
+    fn begin(&mut self, summary: Option<&str>) -> color_eyre::Result<()> {
+        match self.open.last_mut() {
+            Some(begun) if !*begun => *begun = true,
+            _ => return Ok(()),
+        }
+        let outcome = match self.state.cfg.details {
+            Details::Box => Outcome::Degraded("expanded in a framed box"),
+            Details::Omit => Outcome::Dropped,
+        };
+        self.state
+            .capabilities
+            .record("Collapsible details", outcome);
+        let title = match summary {
+            Some(summary) => markdown_fragment_to_tex(summary)?,
+            None => escape(&self.state.labels.details),
+        };
+        match self.state.cfg.details {
+            Details::Box => self.out.extend(self.state.raw.block(format!(
+                "\\begin{{framed}}\n\\noindent\\textbf{{{}}}\\par",
+                title
+            ))),
+            Details::Omit if self.open.len() == 1 => {
+                let note =
+                    escape(&self.state.labels.details_omitted).replace("\\{title\\}", &title);
+                self.out
+                    .extend(self.state.raw.block(format!("\\emph{{{}}}", note)))
+            }
+            Details::Omit => {}
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> color_eyre::Result<()> {
+        self.begin(None)?;
+        if self.open.pop().is_some() && self.state.cfg.details == Details::Box {
+            self.out.extend(self.state.raw.block("\\end{framed}"));
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, event: Event<'a>) -> color_eyre::Result<()> {
+        self.begin(None)?;
+        if self.open.is_empty() || self.state.cfg.details == Details::Box {
+            self.out.push(event);
+        }
+        Ok(())
+    }
+
+    fn push_html(&mut self, html: &str) -> color_eyre::Result<()> {
+        if html.trim().is_empty() {
+            return Ok(());
+        }
+        self.push(Event::Html(html.to_owned().into()))
+    }
+}
+
+/// Render `<details>` either as a framed aside titled by its `<summary>`, or
+/// omit it, leaving a note behind.
+pub(crate) fn details<'a>(
+    events: Vec<Event<'a>>,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    let re =
+        regex::Regex::new(r"(?is)<details[^>]*>|<summary[^>]*>(.*?)</summary\s*>|</details\s*>")?;
+    let mut out = DetailsOut {
+        state,
+        out: Vec::with_capacity(events.len()),
+        open: Vec::new(),
+    };
+    let mut events = events.into_iter().peekable();
+    while let Some(event) = events.next() {
+        let html = match event {
+            Event::Html(html) => join_html(html, &mut events),
+            event => {
+                out.push(event)?;
+                continue;
+            }
+        };
+
+        let mut last = 0;
+        for caps in re.captures_iter(&html) {
+            let tag = caps.get(0).expect("Group 0 is the whole match. qed");
+            out.push_html(&html[last..tag.start()])?;
+            last = tag.end();
+            if let Some(summary) = caps.get(1) {
+                out.begin(Some(summary.as_str()))?;
+            } else if tag.as_str().starts_with("</") {
+                out.end()?;
+            } else {
+                out.open.push(false);
+            }
+        }
+        out.push_html(&html[last..])?;
+    }
+    Ok(out.out)
+}
+
+/// Replace `<video>`, `<audio>` and `<iframe>` embeds, which can not exist
+/// on paper, with a framed placeholder.
+///
+/// The placeholder holds the title, the fallback content of the element and
+/// a link to the online version of the chapter if `online-url` is set, or
+/// else to the embedded media if its location is absolute.
+pub(crate) fn media<'a>(
+    events: Vec<Event<'a>>,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    let re = Regex::new(
+        r"(?is)<(video|audio|iframe)\b([^>]*?)(?:/>|>(.*?)</(?:video|audio|iframe)\s*>)",
+    )?;
+    let source = Regex::new(r"(?is)<source\b([^>]*)>")?;
+    let tags = Regex::new(r"(?s)<[^>]*>")?;
+
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter().peekable();
+    while let Some(event) = events.next() {
+        let html = match event {
+            Event::Html(html) => join_html(html, &mut events),
+            event => {
+                out.push(event);
+                continue;
+            }
+        };
+
+        let mut last = 0;
+        for caps in re.captures_iter(&html) {
+            let element = caps.get(0).expect("Group 0 is the whole match. qed");
+            keep_html(&html[last..element.start()], &mut out);
+            last = element.end();
+
+            let attributes = &caps[2];
+            let inner = caps.get(3).map(|inner| inner.as_str()).unwrap_or_default();
+            let src = attribute(attributes, "src").or_else(|| {
+                source
+                    .captures(inner)
+                    .and_then(|source| attribute(source.get(1)?.as_str(), "src"))
+            });
+
+            state.capabilities.record(
+                "Embedded media",
+                Outcome::Degraded("placeholder referring to the online version"),
+            );
+            let labels = &state.labels;
+            let kind = match caps[1].to_ascii_lowercase().as_str() {
+                "video" => &labels.video,
+                "audio" => &labels.audio,
+                _ => &labels.embedded_content,
+            };
+            let title = attribute(attributes, "title")
+                .or_else(|| src.and_then(|src| src.rsplit('/').next()))
+                .unwrap_or_default();
+            let mut tex = format!(
+                "\\begin{{framed}}\n\\noindent\\textbf{{{}: {}}}\\par\n",
+                escape(kind),
+                markdown_fragment_to_tex(title)?
+            );
+
+            let fallback = tags.replace_all(inner, "");
+            if !fallback.trim().is_empty() {
+                tex.push_str(&markdown_fragment_to_tex(fallback.trim())?);
+                tex.push_str("\\par\n");
+            }
+
+            let url = match (&state.cfg.online_url, state.chapter_url()) {
+                (Some(online), Some(chapter)) => {
+                    Some(format!("{}/{}", online.trim_end_matches('/'), chapter))
+                }
+                _ => src.filter(|src| src.contains("://")).map(str::to_owned),
+            };
+            if let Some(url) = url {
+                tex.push_str(&format!("\\noindent\\url{{{}}}\n", url));
+            }
+            tex.push_str("\\end{framed}");
+            out.extend(state.raw.block(tex));
+        }
+        keep_html(&html[last..], &mut out);
+    }
+    Ok(out)
+}
+
+/// LaTeX for a `<ruby>` element, every base text with its reading in the
+/// `<rt>` following it. Parentheses in `<rp>` are for browsers without ruby
+/// support and dropped.
+fn ruby_to_tex(html: &str) -> color_eyre::Result<String> {
+    let fallback = Regex::new(r"(?is)<rp\b[^>]*>.*?</rp\s*>")?;
+    let annotation = Regex::new(r"(?is)(.*?)<rt\b[^>]*>(.*?)(?:</rt\s*>|$)")?;
+    let tags = Regex::new(r"(?s)<[^>]*>")?;
+    let html = fallback.replace_all(html, "");
+
+    let mut tex = String::new();
+    let mut last = 0;
+    for caps in annotation.captures_iter(&html) {
+        last = caps.get(0).expect("Group 0 is the whole match. qed").end();
+        let base = tags.replace_all(&caps[1], "");
+        let reading = tags.replace_all(&caps[2], "");
+        tex.push_str(&format!(
+            "\\ruby{{{}}}{{{}}}",
+            escape(base.trim()),
+            escape(reading.trim())
+        ));
+    }
+    tex.push_str(&escape(tags.replace_all(&html[last..], "").trim()));
+    Ok(tex)
+}
+
+/// Tags of blocks, which inline html does not reach beyond.
+fn is_block(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Paragraph
+            | Tag::Heading(..)
+            | Tag::BlockQuote
+            | Tag::CodeBlock(_)
+            | Tag::List(_)
+            | Tag::Item
+            | Tag::FootnoteDefinition(_)
+            | Tag::Table(_)
+            | Tag::TableHead
+            | Tag::TableRow
+            | Tag::TableCell
+    )
+}
+
+/// Ruby annotations, `<ruby>漢<rt>かん</rt>字<rt>じ</rt></ruby>`, as used
+/// for readings of East Asian texts.
+///
+/// Inline, every tag is an html event of its own with the text in between,
+/// whereas an html block holds entire elements.
+pub(crate) fn ruby<'a>(
+    events: Vec<Event<'a>>,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    let re = Regex::new(r"(?is)<ruby\b[^>]*>.*?</ruby\s*>")?;
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let html = match event {
+            Event::Html(html) => html,
+            event => {
+                out.push(event);
+                continue;
+            }
+        };
+        if re.is_match(&html) {
+            let mut last = 0;
+            for element in re.find_iter(&html) {
+                keep_html(&html[last..element.start()], &mut out);
+                last = element.end();
+                let tex = ruby_to_tex(element.as_str())?;
+                out.extend(state.raw.block(tex));
+            }
+            keep_html(&html[last..], &mut out);
+            state.packages.insert("pxrubrica");
+            state
+                .capabilities
+                .record("Ruby annotations", Outcome::Rendered);
+        } else if html.trim_start().to_ascii_lowercase().starts_with("<ruby") {
+            // Up to the closing tag, within the paragraph, or whatever
+            // block it is in.
+            let mut element = html.to_string();
+            let mut taken = Vec::new();
+            let mut ending = None;
+            let mut closed = false;
+            for event in events.by_ref() {
+                match event {
+                    Event::Start(ref tag) | Event::End(ref tag) if is_block(tag) => {
+                        ending = Some(event);
+                        break;
+                    }
+                    Event::Html(ref more) => {
+                        element.push_str(more);
+                        closed = more.to_ascii_lowercase().contains("</ruby");
+                    }
+                    Event::Text(ref text) | Event::Code(ref text) => element.push_str(text),
+                    _ => {}
+                }
+                taken.push(event);
+                if closed {
+                    break;
+                }
+            }
+            if closed {
+                out.push(state.raw.inline(ruby_to_tex(&element)?));
+                state.packages.insert("pxrubrica");
+                state
+                    .capabilities
+                    .record("Ruby annotations", Outcome::Rendered);
+            } else {
+                log::warn!("Keeping a `<ruby>` without its closing tag as html");
+                out.push(Event::Html(html));
+                out.extend(taken);
+            }
+            out.extend(ending);
+        } else {
+            out.push(Event::Html(html));
+        }
+    }
+    Ok(out)
+}
+
+/// Keep what is left of some html, unless it is only whitespace.
+pub(crate) fn keep_html<'a>(html: &str, out: &mut Vec<Event<'a>>) {
+    if !html.trim().is_empty() {
+        out.push(Event::Html(html.to_owned().into()));
+    }
+}
@@ -0,0 +1,43 @@
+//! Labels of generated anchors, stable across runs.
+//!
+//! A label is made of its kind, a hash of the seed and the chapter, and a
+//! counter within the chapter, `footnote-1a2b3c4d-2`. Editing one chapter
+//! leaves the labels of all others untouched, so diffs of the generated
+//! LaTeX between runs stay minimal.
+
+use crate::images;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default)]
+pub(crate) struct Ids {
+    seed: String,
+    // Hash of the seed and the current chapter.
+    chapter: u32,
+    counters: BTreeMap<&'static str, usize>,
+}
+
+impl Ids {
+    pub(crate) fn new(seed: &str) -> Self {
+        let mut ids = Self {
+            seed: seed.to_owned(),
+            ..Default::default()
+        };
+        ids.enter("");
+        ids
+    }
+
+    /// Start labeling the chapter identified by `key`, its source path.
+    pub(crate) fn enter(&mut self, key: &str) {
+        let hash = images::hash(format!("{}\0{}", self.seed, key).as_bytes());
+        // Folded to 32 bit, plenty for the chapters of a book.
+        self.chapter = (hash ^ (hash >> 32)) as u32;
+        self.counters.clear();
+    }
+
+    /// The next label of `kind` within the current chapter.
+    pub(crate) fn next(&mut self, kind: &'static str) -> String {
+        let counter = self.counters.entry(kind).or_default();
+        *counter += 1;
+        format!("{}-{:08x}-{}", kind, self.chapter, counter)
+    }
+}
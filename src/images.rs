@@ -0,0 +1,523 @@
+//! Image placement beyond inline images.
+//!
+//! Images take attributes in pandoc style, right after the image:
+//!
+//! ```markdown
+//! ![The harbour in 1850](harbour.png){.fullpage}
+//! ```
+//!
+//! With `figures`, an image alone in its paragraph is a numbered figure,
+//! captioned with its alt text. Images within text, lists, quotes, tables
+//! or footnotes stay inline, floats can not go there.
+
+use crate::capabilities::Outcome;
+use crate::raw::{escape, events_to_tex};
+use crate::{links, paths};
+use crate::{ImageLayout, LatexConfig, Traversal};
+use fs_err as fs;
+use pulldown_cmark::{CowStr, Event, Tag};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// Report section of images not found.
+pub(crate) const SECTION: &str = "missing-images";
+
+/// Report section of images on the web, which are not downloaded.
+pub(crate) const REMOTE_SECTION: &str = "remote-images";
+
+/// Whether `path` is an address on the web rather than a file.
+fn is_remote(path: &str) -> bool {
+    let scheme = path.split_once("://").map(|(scheme, _)| scheme);
+    scheme.map_or(false, |scheme| {
+        scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+    })
+}
+
+/// Split leading `{.class ...}` attributes off the text following an image.
+pub(crate) fn split_attributes(text: &str) -> Option<(Vec<&str>, &str)> {
+    let rest = text.strip_prefix('{')?;
+    let (attributes, rest) = rest.split_once('}')?;
+    Some((attributes.split_whitespace().collect(), rest))
+}
+
+/// Image relative to the source directory, image paths are relative to
+/// the chapter unless they start with a slash.
+pub(crate) fn source(path: &str, chapter_path: &Path) -> PathBuf {
+    match path.strip_prefix('/') {
+        Some(path) => PathBuf::from(path),
+        None => chapter_path.join(path.strip_prefix("./").unwrap_or(path)),
+    }
+}
+
+/// Replace everything but ASCII letters, digits and dashes, LaTeX trips
+/// over spaces, `#`, `%`, `_`, further dots and non-ASCII in file names.
+pub(crate) fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+/// Sanitize all components of `path`, keeping the extension.
+fn sanitize_path(path: &Path) -> PathBuf {
+    let mut sanitized = PathBuf::new();
+    if let Some(parent) = path.parent() {
+        for component in parent.iter() {
+            sanitized.push(sanitize(&component.to_string_lossy()));
+        }
+    }
+    let mut name = sanitize(&path.file_stem().unwrap_or_default().to_string_lossy());
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&sanitize(&extension.to_string_lossy()));
+    }
+    sanitized.push(name);
+    sanitized
+}
+
+/// An image copied into the build directory.
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct Asset {
+    // Relative to the source directory.
+    pub(crate) source: PathBuf,
+    // Relative to the build directory.
+    output: PathBuf,
+    // 64 bit FNV-1a of the content, in hex.
+    hash: String,
+    // Names of the chapters referencing it.
+    pub(crate) chapters: BTreeSet<String>,
+}
+
+/// Mapping of the copied images from their source to their target, the
+/// sanitized names of distinct sources may collide and get a suffix.
+#[derive(Debug, Default)]
+pub(crate) struct Assets {
+    assets: BTreeMap<PathBuf, Asset>,
+    taken: BTreeSet<PathBuf>,
+}
+
+impl Assets {
+    /// Target of an image copied before, recording `chapter` as referencing it.
+    pub(crate) fn get(&mut self, source: &Path, chapter: &str) -> Option<&Path> {
+        let asset = self.assets.get_mut(source)?;
+        asset.chapters.insert(chapter.to_owned());
+        Some(&asset.output)
+    }
+
+    /// Record the image at `source` with content `data` as copied, returns
+    /// its target relative to the build directory.
+    pub(crate) fn insert(
+        &mut self,
+        source: PathBuf,
+        data: &[u8],
+        chapter: &str,
+        cfg: &LatexConfig,
+    ) -> PathBuf {
+        let target = target(&source, data, cfg);
+        let mut unique = target.clone();
+        // Flattened images are named by their content, sharing a name they
+        // are the same image.
+        if cfg.image_layout == ImageLayout::Mirror {
+            let mut n = 1;
+            while self.taken.contains(&unique) {
+                n += 1;
+                let mut name = format!(
+                    "{}-{}",
+                    target.file_stem().unwrap_or_default().to_string_lossy(),
+                    n
+                );
+                if let Some(extension) = target.extension() {
+                    name.push('.');
+                    name.push_str(&extension.to_string_lossy());
+                }
+                unique = target.with_file_name(name);
+            }
+        }
+        self.taken.insert(unique.clone());
+        self.assets.insert(
+            source.clone(),
+            Asset {
+                source: source.clone(),
+                output: unique.clone(),
+                hash: format!("{:016x}", hash(data)),
+                chapters: std::iter::once(chapter.to_owned()).collect(),
+            },
+        );
+        unique
+    }
+
+    /// All copied images, ordered by their source.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Asset> {
+        self.assets.values()
+    }
+
+    /// Write the manifest of all copied images as JSON.
+    pub(crate) fn write(&self, path: &Path) -> color_eyre::Result<()> {
+        let assets = self.iter().collect::<Vec<_>>();
+        fs::write(path, serde_json::to_string_pretty(&assets)?)?;
+        Ok(())
+    }
+}
+
+/// 64 bit FNV-1a, stable across builds and platforms.
+pub(crate) fn hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Where the image at `source` with content `data` is copied to, relative
+/// to the build directory.
+fn target(source: &Path, data: &[u8], cfg: &LatexConfig) -> PathBuf {
+    let dir = Path::new(&cfg.image_dir);
+    match cfg.image_layout {
+        ImageLayout::Mirror => dir.join(sanitize_path(source)),
+        ImageLayout::Flatten => {
+            let mut name = format!("{:016x}", hash(data));
+            if let Some(extension) = source.extension() {
+                name.push('.');
+                name.push_str(&sanitize(&extension.to_string_lossy()));
+            }
+            dir.join(name)
+        }
+    }
+}
+
+/// Find `relative` below `dir`, falling back to a case-insensitive match
+/// per path component, as books edited on case-insensitive file systems
+/// reference `Image.PNG` as `image.png`.
+fn find(dir: &Path, relative: &Path) -> std::io::Result<Option<PathBuf>> {
+    let mut found = dir.to_owned();
+    for component in relative.components() {
+        let name = component.as_os_str();
+        if found.join(name).exists() {
+            found.push(name);
+            continue;
+        }
+        if !found.is_dir() {
+            return Ok(None);
+        }
+        let lowercase = name.to_string_lossy().to_lowercase();
+        let mut matching = None;
+        for entry in fs::read_dir(&found)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().to_lowercase() == lowercase {
+                matching = Some(entry.file_name());
+                break;
+            }
+        }
+        match matching {
+            Some(name) => found.push(name),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(found))
+}
+
+/// Resolve the image at `source` relative to the source directory, `None`
+/// if there is no such file.
+///
+/// Symlinks are followed, but neither they nor `..` may lead outside of the
+/// book root.
+pub(crate) fn resolve(source: &Path, state: &Traversal) -> color_eyre::Result<Option<PathBuf>> {
+    let context = state.context;
+    let src = context.root.join(&context.config.book.src);
+    let found = match find(&src, source)? {
+        Some(found) if found.is_file() => found,
+        _ => return Ok(None),
+    };
+    let canonical = fs::canonicalize(&found)?;
+    let root = fs::canonicalize(&context.root)?;
+    if !canonical.starts_with(&root) {
+        color_eyre::eyre::bail!(
+            "Image {} resolves to {}, outside of the book root {}",
+            source.display(),
+            canonical.display(),
+            root.display()
+        );
+    }
+    let resolved = found
+        .strip_prefix(&src)
+        .expect("Found below the source directory. qed")
+        .to_owned();
+    if resolved != source {
+        log::warn!(
+            "Image {} differs in case from the file {}",
+            source.display(),
+            resolved.display()
+        );
+    }
+    Ok(Some(resolved))
+}
+
+/// Resolve all images against the source directory. Images whose file does
+/// not exist are replaced with a framed placeholder naming the path and the
+/// alternative text, and listed in the report. Images on the web are
+/// replaced with their alternative text and address, and listed apart.
+pub(crate) fn resolve_all<'a>(
+    events: Vec<Event<'a>>,
+    chapter_path: &Path,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    let mut findings = Vec::new();
+    let mut remote = Vec::new();
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let (link_type, path, title) = match event {
+            Event::Start(Tag::Image(link_type, path, title)) => (link_type, path, title),
+            event => {
+                out.push(event);
+                continue;
+            }
+        };
+        if is_remote(&path) {
+            let alt = alt_text(&mut events);
+            log::warn!("Image {} is on the web, printing its address", path);
+            remote.push(format!("`{}`: {}", path, alt));
+            state.capabilities.record(
+                "Images",
+                Outcome::Degraded("alternative text and address for images on the web"),
+            );
+            let url = format!("\\url{{{}}}", links::url_argument(&path));
+            out.push(state.raw.inline(match alt.trim() {
+                "" => url,
+                alt => format!("{} ({})", escape(alt), url),
+            }));
+            continue;
+        }
+        if let Some(resolved) = resolve(&source(&path, chapter_path), state)? {
+            // Relative to the source directory from now on.
+            let path = CowStr::from(format!("/{}", paths::to_tex(&resolved)));
+            out.push(Event::Start(Tag::Image(
+                link_type,
+                path.clone(),
+                title.clone(),
+            )));
+            for event in events.by_ref() {
+                if let Event::End(Tag::Image(..)) = event {
+                    out.push(Event::End(Tag::Image(link_type, path, title)));
+                    break;
+                }
+                out.push(event);
+            }
+            continue;
+        }
+
+        let alt = alt_text(&mut events);
+        log::warn!("Image {} not found, rendering a placeholder", path);
+        findings.push(format!("`{}`: {}", path, alt));
+        state
+            .capabilities
+            .record("Images", Outcome::Degraded("placeholder for missing files"));
+        out.push(
+            state
+                .raw
+                .inline(placeholder_tex(&state.labels.missing_image, &path, &alt)),
+        );
+    }
+    let chapter = state
+        .chapter
+        .map(|chapter| chapter.name.as_str())
+        .unwrap_or_default();
+    state.report.add(SECTION, chapter, findings);
+    state.report.add(REMOTE_SECTION, chapter, remote);
+    Ok(out)
+}
+
+/// The alternative text of an image, up to its end, plain text whatever
+/// markup it has.
+fn alt_text<'a>(events: &mut impl Iterator<Item = Event<'a>>) -> String {
+    let mut alt = String::new();
+    for event in events {
+        match event {
+            Event::End(Tag::Image(..)) => break,
+            Event::Text(text) | Event::Code(text) => alt.push_str(&text),
+            _ => {}
+        }
+    }
+    alt
+}
+
+fn placeholder_tex(label: &str, path: &str, alt: &str) -> String {
+    format!(
+        "\\fbox{{\\parbox{{0.8\\linewidth}}{{\\centering\\textbf{{{}}}\\\\\\texttt{{{}}}\\\\{}}}}}",
+        escape(label),
+        escape(path),
+        escape(alt)
+    )
+}
+
+/// Place images with a `.fullpage` or `.spread` class on pages of their own.
+///
+/// A full page image is a float page with its caption below, a spread puts
+/// the caption on a left page and the image on the facing right page.
+pub(crate) fn placement<'a>(
+    events: Vec<Event<'a>>,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter().peekable();
+    while let Some(event) = events.next() {
+        let path = match event {
+            Event::Start(Tag::Image(_, ref path, _)) => path.clone(),
+            event => {
+                out.push(event);
+                continue;
+            }
+        };
+        let mut image = vec![event];
+        for event in events.by_ref() {
+            let end = matches!(event, Event::End(Tag::Image(..)));
+            image.push(event);
+            if end {
+                break;
+            }
+        }
+
+        let (classes, rest) = match events.peek() {
+            Some(Event::Text(text)) => match split_attributes(text) {
+                Some((classes, rest)) => (
+                    classes.into_iter().map(str::to_owned).collect::<Vec<_>>(),
+                    rest.to_owned(),
+                ),
+                None => (Vec::new(), String::new()),
+            },
+            _ => (Vec::new(), String::new()),
+        };
+        let fullpage = classes.iter().any(|class| class == ".fullpage");
+        let spread = classes.iter().any(|class| class == ".spread");
+        if !fullpage && !spread {
+            out.extend(image);
+            continue;
+        }
+        events.next();
+
+        let caption = events_to_tex(&image[1..image.len() - 1])?;
+        state.capabilities.record(
+            if spread {
+                "Spread images"
+            } else {
+                "Full page images"
+            },
+            Outcome::Rendered,
+        );
+        let tex = if spread {
+            // `\captionof` outside of a float.
+            state.packages.insert("caption");
+            spread_tex(&path, &caption)
+        } else {
+            fullpage_tex(&path, &caption)
+        };
+        out.push(state.raw.inline(tex));
+        if !rest.is_empty() {
+            out.push(Event::Text(CowStr::from(rest)));
+        }
+    }
+    Ok(out)
+}
+
+/// Turn images alone in their paragraph into figures, captioned with their
+/// alt text, or else their title, and labeled.
+pub(crate) fn figures<'a>(
+    events: Vec<Event<'a>>,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    if !state.cfg.figures {
+        return Ok(events);
+    }
+    let mut out = Vec::with_capacity(events.len());
+    // Within lists, quotes, tables or footnotes.
+    let mut nested = 0;
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        match event {
+            Event::Start(
+                Tag::Item | Tag::BlockQuote | Tag::Table(_) | Tag::FootnoteDefinition(_),
+            ) => nested += 1,
+            Event::End(
+                Tag::Item | Tag::BlockQuote | Tag::Table(_) | Tag::FootnoteDefinition(_),
+            ) => nested -= 1,
+            Event::Start(Tag::Paragraph) if nested == 0 => {
+                let mut paragraph = vec![event];
+                for event in events.by_ref() {
+                    let end = matches!(event, Event::End(Tag::Paragraph));
+                    paragraph.push(event);
+                    if end {
+                        break;
+                    }
+                }
+                match figure_tex(&paragraph, state)? {
+                    Some(tex) => out.extend(state.raw.block(tex)),
+                    None => out.extend(paragraph),
+                }
+                continue;
+            }
+            _ => {}
+        }
+        out.push(event);
+    }
+    Ok(out)
+}
+
+/// The figure of `paragraph`, if it holds an image and nothing else.
+fn figure_tex(paragraph: &[Event], state: &mut Traversal) -> color_eyre::Result<Option<String>> {
+    let inner = &paragraph[1..paragraph.len() - 1];
+    let start = inner
+        .iter()
+        .position(|event| matches!(event, Event::Start(Tag::Image(..))));
+    let end = inner
+        .iter()
+        .position(|event| matches!(event, Event::End(Tag::Image(..))));
+    let (start, end) = match (start, end) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return Ok(None),
+    };
+    let blank = |event: &Event| match event {
+        Event::Text(text) => text.trim().is_empty(),
+        Event::SoftBreak => true,
+        _ => false,
+    };
+    if !inner[..start].iter().all(blank) || !inner[end + 1..].iter().all(blank) {
+        return Ok(None);
+    }
+    let (path, title) = match inner[start] {
+        Event::Start(Tag::Image(_, ref path, ref title)) => (path, title),
+        _ => unreachable!("Found as the start of an image. qed"),
+    };
+    let mut caption = events_to_tex(&inner[start + 1..end])?;
+    if caption.is_empty() {
+        caption = escape(title);
+    }
+    state.capabilities.record("Figures", Outcome::Rendered);
+    let mut tex = format!(
+        "\\begin{{figure}}[htbp]\n\\centering\n\\includegraphics[width=\\linewidth,height=0.6\\textheight,keepaspectratio]{{{}}}\n",
+        path
+    );
+    if !caption.is_empty() {
+        tex.push_str(&format!("\\caption{{{}}}\n", caption));
+    }
+    tex.push_str(&format!(
+        "\\label{{{}}}\n\\end{{figure}}",
+        state.ids.next("figure")
+    ));
+    Ok(Some(tex))
+}
+
+fn fullpage_tex(path: &str, caption: &str) -> String {
+    let mut tex = format!(
+        "\\begin{{figure}}[p]\n\\centering\n\\includegraphics[width=\\textwidth,height=0.9\\textheight,keepaspectratio]{{{}}}\n",
+        path
+    );
+    if !caption.is_empty() {
+        tex.push_str(&format!("\\caption{{{}}}\n", caption));
+    }
+    tex.push_str("\\end{figure}");
+    tex
+}
+
+fn spread_tex(path: &str, caption: &str) -> String {
+    format!(
+        "\\clearpage\n\\ifodd\\value{{page}}\\hbox{{}}\\newpage\\fi\n\\vspace*{{\\fill}}\n\\begin{{center}}\\begin{{minipage}}{{0.8\\textwidth}}\\captionof{{figure}}{{{}}}\\end{{minipage}}\\end{{center}}\n\\vspace*{{\\fill}}\n\\clearpage\n\\begin{{center}}\\includegraphics[width=\\textwidth,height=\\textheight,keepaspectratio]{{{}}}\\end{{center}}\n\\clearpage",
+        caption, path
+    )
+}
@@ -0,0 +1,31 @@
+//! Imposition of the PDF for printing, a second pass placing its pages
+//! onto sheets with `pdfpages`.
+
+use crate::paths;
+use crate::Imposition;
+use std::path::Path;
+
+/// Suffix of the imposed PDF, `book-booklet.pdf` next to `book.pdf`.
+pub(crate) fn suffix(imposition: Imposition) -> &'static str {
+    match imposition {
+        Imposition::Booklet => "booklet",
+        Imposition::TwoUp => "2up",
+    }
+}
+
+/// Document placing the pages of `pdf` two per landscape sheet.
+///
+/// For a booklet they are reordered such that the sheets, printed on both
+/// sides, folded and stacked, read in order. The page count is padded
+/// with blank pages to a multiple of four.
+pub(crate) fn document(pdf: &Path, imposition: Imposition) -> String {
+    let options = match imposition {
+        Imposition::Booklet => "pages=-,booklet=true,landscape",
+        Imposition::TwoUp => "pages=-,nup=2x1,landscape",
+    };
+    format!(
+        "\\documentclass{{article}}\n\\usepackage{{pdfpages}}\n\\begin{{document}}\n\\includepdf[{}]{{{}}}\n\\end{{document}}\n",
+        options,
+        paths::to_tex(pdf)
+    )
+}
@@ -0,0 +1,27 @@
+//! The index, of glossary terms and `<!-- index: term -->` directives.
+//!
+//! Entries load `imakeidx`, which collects them during the run, and print
+//! the index at its template marker, else at the end of the back matter.
+
+use crate::Traversal;
+
+/// Package the entries and the index require.
+pub(crate) const PACKAGE: &str = "imakeidx";
+
+/// Preamble following the packages, once entries were emitted.
+pub(crate) const PREAMBLE: &str = "\\makeindex\n";
+
+/// `\index` of `key`, already in `makeindex` syntax, loading the index
+/// setup along with it.
+pub(crate) fn entry(key: &str, state: &mut Traversal) -> String {
+    state.packages.insert(PACKAGE);
+    format!("\\index{{{}}}", key)
+}
+
+/// The index, `None` if there are no entries.
+pub(crate) fn print(state: &mut Traversal) -> Option<String> {
+    if !state.packages.contains(PACKAGE) {
+        return None;
+    }
+    Some(format!("\n\n{}\n\n", state.raw.token("\\printindex\n")))
+}
@@ -0,0 +1,141 @@
+//! Inline elements `cmark2tex` does not translate the way print expects.
+
+use crate::raw::events_to_tex;
+use crate::{SoftBreak, Traversal};
+use pulldown_cmark::{Event, Tag};
+use std::collections::BTreeMap;
+
+/// Turn hard line breaks into `\\`, and soft ones as well if so configured.
+///
+/// Poetry, addresses and sign-offs rely on these to keep their shape. A
+/// heading is set as one line, its breaks become spaces. Table cells can
+/// not hold either break, their `<br>` are up to `tables::tables`.
+pub(crate) fn line_breaks<'a>(events: Vec<Event<'a>>, state: &mut Traversal) -> Vec<Event<'a>> {
+    let mut in_heading = false;
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Heading(..)) => {
+                in_heading = true;
+                event
+            }
+            Event::End(Tag::Heading(..)) => {
+                in_heading = false;
+                event
+            }
+            Event::HardBreak | Event::SoftBreak if in_heading => Event::Text(" ".into()),
+            Event::HardBreak => state.raw.inline("\\\\\n"),
+            Event::SoftBreak if state.cfg.soft_break == SoftBreak::Newline => {
+                state.raw.inline("\\\\\n")
+            }
+            event => event,
+        })
+        .collect()
+}
+
+/// Turn GFM strikethrough into `\sout`, of the `ulem` package.
+pub(crate) fn strikethrough<'a>(events: Vec<Event<'a>>, state: &mut Traversal) -> Vec<Event<'a>> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Strikethrough) => state.raw.inline("\\sout{"),
+            Event::End(Tag::Strikethrough) => state.raw.inline("}"),
+            event => event,
+        })
+        .collect()
+}
+
+/// Turn task list markers, `- [ ]` and `- [x]`, into checkboxes.
+pub(crate) fn task_lists<'a>(events: Vec<Event<'a>>, state: &mut Traversal) -> Vec<Event<'a>> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::TaskListMarker(checked) => {
+                state.packages.insert("amssymb");
+                state.raw.inline(if checked {
+                    "$\\boxtimes$~"
+                } else {
+                    "$\\square$~"
+                })
+            }
+            event => event,
+        })
+        .collect()
+}
+
+/// Turn footnote references into `\footnote` with the body of their
+/// definition, which may span several paragraphs.
+///
+/// Further references to the same footnote refer to its number, references
+/// without a definition are kept as written.
+pub(crate) fn footnotes<'a>(
+    events: Vec<Event<'a>>,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    // Definitions can be anywhere in the chapter, usually at its end.
+    let mut definitions = BTreeMap::new();
+    let mut rest = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        let label = match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => label,
+            event => {
+                rest.push(event);
+                continue;
+            }
+        };
+        let mut body = Vec::new();
+        let mut depth = 0;
+        for event in events.by_ref() {
+            match event {
+                Event::End(Tag::FootnoteDefinition(_)) if depth == 0 => break,
+                Event::Start(_) => depth += 1,
+                Event::End(_) => depth -= 1,
+                _ => {}
+            }
+            body.push(event);
+        }
+        definitions.insert(label.to_string(), body);
+    }
+    if definitions.is_empty() {
+        return Ok(rest);
+    }
+
+    // Labels of the footnotes typeset so far, by their markdown label.
+    let mut typeset: BTreeMap<String, String> = BTreeMap::new();
+    let mut out = Vec::with_capacity(rest.len());
+    for event in rest {
+        let label = match event {
+            Event::FootnoteReference(ref label) => label.to_string(),
+            event => {
+                out.push(event);
+                continue;
+            }
+        };
+        if let Some(tex_label) = typeset.get(&label) {
+            out.push(
+                state
+                    .raw
+                    .inline(format!("\\textsuperscript{{\\ref{{{}}}}}", tex_label)),
+            );
+            continue;
+        }
+        match definitions.get(&label) {
+            Some(body) => {
+                let tex_label = state.ids.next("footnote");
+                let tex = format!(
+                    "\\footnote{{{}\\label{{{}}}}}",
+                    events_to_tex(body)?,
+                    tex_label
+                );
+                typeset.insert(label, tex_label);
+                out.push(state.raw.inline(tex));
+            }
+            None => {
+                log::warn!("Footnote [^{}] is not defined", label);
+                out.push(Event::Text(format!("[^{}]", label).into()));
+            }
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,230 @@
+//! Text generated by this backend, rather than taken from the book.
+//!
+//! English is built in, along with a few translations. All of it can be
+//! overridden by a localization file with one table per language:
+//!
+//! ```toml
+//! [de]
+//! details = "Einzelheiten"
+//! question = "Frage"
+//! ```
+
+use fs_err as fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub(crate) struct Labels {
+    // `<details>` without a `<summary>`.
+    pub(crate) details: String,
+    // Note left behind by an omitted `<details>`, `{title}` is replaced.
+    pub(crate) details_omitted: String,
+    pub(crate) video: String,
+    pub(crate) audio: String,
+    pub(crate) embedded_content: String,
+    // `{{#pageref anchor}}`, followed by the page number.
+    pub(crate) see_page: String,
+    pub(crate) question: String,
+    pub(crate) answer_key: String,
+    pub(crate) tracing_prompt: String,
+    pub(crate) compiles_and_prints: String,
+    pub(crate) compiles: String,
+    pub(crate) does_not_compile: String,
+    pub(crate) listing: String,
+    pub(crate) output_of_listing: String,
+    pub(crate) attachments: String,
+    // Titles of GitHub alerts.
+    pub(crate) note: String,
+    pub(crate) tip: String,
+    pub(crate) important: String,
+    pub(crate) warning: String,
+    pub(crate) caution: String,
+    // Title page of a volume of a series.
+    pub(crate) volume: String,
+    pub(crate) also_in_series: String,
+    pub(crate) keywords: String,
+    // Placeholder of an image file not found.
+    pub(crate) missing_image: String,
+    // Note where a chapter beyond the size limit is cut, `{size}` is replaced.
+    pub(crate) chapter_cut: String,
+    // Appendix crediting the images.
+    pub(crate) image_credits: String,
+    // The build report.
+    pub(crate) build_report: String,
+    pub(crate) spelling: String,
+    pub(crate) readability: String,
+    pub(crate) leftover_markers: String,
+    pub(crate) visual_changes: String,
+    pub(crate) outline: String,
+    pub(crate) examples: String,
+    pub(crate) missing_images: String,
+    pub(crate) remote_images: String,
+    pub(crate) unlicensed_images: String,
+    pub(crate) long_lines: String,
+    pub(crate) large_chapters: String,
+    pub(crate) html_tables: String,
+    pub(crate) encoding: String,
+    pub(crate) timing: String,
+    pub(crate) words: String,
+    pub(crate) sentences: String,
+    pub(crate) average_sentence_length: String,
+    pub(crate) reading_ease: String,
+    pub(crate) grade_level: String,
+    pub(crate) passive_voice: String,
+}
+
+impl Default for Labels {
+    fn default() -> Self {
+        Self {
+            details: "Details".to_owned(),
+            details_omitted: "The collapsed section “{title}” is omitted in print.".to_owned(),
+            video: "Video".to_owned(),
+            audio: "Audio".to_owned(),
+            embedded_content: "Embedded content".to_owned(),
+            see_page: "see page".to_owned(),
+            question: "Question".to_owned(),
+            answer_key: "Answer Key".to_owned(),
+            tracing_prompt: "Does this program compile? If it does, what does it print?".to_owned(),
+            compiles_and_prints: "It compiles and prints:".to_owned(),
+            compiles: "It compiles.".to_owned(),
+            does_not_compile: "It does not compile.".to_owned(),
+            listing: "Listing".to_owned(),
+            output_of_listing: "Output of Listing".to_owned(),
+            attachments: "Attachments".to_owned(),
+            note: "Note".to_owned(),
+            tip: "Tip".to_owned(),
+            important: "Important".to_owned(),
+            warning: "Warning".to_owned(),
+            caution: "Caution".to_owned(),
+            volume: "Volume".to_owned(),
+            also_in_series: "Also in this series:".to_owned(),
+            keywords: "Keywords".to_owned(),
+            missing_image: "Missing image".to_owned(),
+            chapter_cut: "The remaining {size} of this chapter are left out of the print edition."
+                .to_owned(),
+            image_credits: "Image Credits".to_owned(),
+            build_report: "Build Report".to_owned(),
+            spelling: "Spelling".to_owned(),
+            readability: "Readability".to_owned(),
+            leftover_markers: "Leftover markers".to_owned(),
+            visual_changes: "Visual changes".to_owned(),
+            outline: "PDF outline".to_owned(),
+            examples: "Rust examples".to_owned(),
+            missing_images: "Missing images".to_owned(),
+            remote_images: "Images on the web".to_owned(),
+            unlicensed_images: "Images without license".to_owned(),
+            long_lines: "Long code lines".to_owned(),
+            large_chapters: "Large chapters".to_owned(),
+            html_tables: "HTML tables left as HTML".to_owned(),
+            encoding: "Broken characters".to_owned(),
+            timing: "Timing".to_owned(),
+            words: "Words".to_owned(),
+            sentences: "Sentences".to_owned(),
+            average_sentence_length: "Average sentence length (words)".to_owned(),
+            reading_ease: "Flesch reading ease".to_owned(),
+            grade_level: "Flesch-Kincaid grade level".to_owned(),
+            passive_voice: "Passive voice (sentences)".to_owned(),
+        }
+    }
+}
+
+/// Primary subtag of a language tag, `pt` of `pt-BR`.
+pub(crate) fn primary(language: &str) -> &str {
+    language.split(['-', '_']).next().unwrap_or(language)
+}
+
+/// Babel language option for a language tag, English if unknown.
+pub(crate) fn babel(language: Option<&str>) -> &'static str {
+    match language {
+        Some("en-GB") => "british",
+        Some("en-US") => "american",
+        Some("de-AT") => "naustrian",
+        Some("pt-BR") => "brazilian",
+        Some(language) => match primary(language) {
+            "de" => "ngerman",
+            "fr" => "french",
+            "es" => "spanish",
+            "it" => "italian",
+            "nl" => "dutch",
+            "pt" => "portuguese",
+            "pl" => "polish",
+            "cs" => "czech",
+            "sv" => "swedish",
+            "da" => "danish",
+            "nb" | "no" => "norsk",
+            "fi" => "finnish",
+            "ru" => "russian",
+            "uk" => "ukrainian",
+            "el" => "greek",
+            "tr" => "turkish",
+            _ => "english",
+        },
+        None => "english",
+    }
+}
+
+impl Labels {
+    /// The built in labels for `language`.
+    pub(crate) fn builtin(language: Option<&str>) -> Self {
+        let mut labels = Self::default();
+        let see_page = match language.map(primary) {
+            Some("de") => "siehe Seite",
+            Some("fr") => "voir page",
+            Some("es") => "véase la página",
+            Some("it") => "vedi pagina",
+            Some("nl") => "zie pagina",
+            Some("pt") => "ver página",
+            _ => return labels,
+        };
+        labels.see_page = see_page.to_owned();
+        labels
+    }
+
+    /// The built in labels for `language`, overridden by the tables of the
+    /// localization file at `path` for the primary language and the full
+    /// language tag, in that order.
+    pub(crate) fn load(path: &Path, language: Option<&str>) -> color_eyre::Result<Self> {
+        let file: toml::value::Table = toml::from_str(&fs::read_to_string(path)?)?;
+        let labels = Self::builtin(language);
+        let mut merged = match toml::Value::try_from(&labels)? {
+            toml::Value::Table(table) => table,
+            _ => unreachable!("Labels serialize to a table. qed"),
+        };
+        let language = language.unwrap_or("en");
+        let mut tags = vec![primary(language)];
+        if language != primary(language) {
+            tags.push(language);
+        }
+        for tag in tags {
+            match file.get(tag) {
+                Some(toml::Value::Table(overrides)) => merged.extend(overrides.clone()),
+                Some(_) => {
+                    color_eyre::eyre::bail!("Expected a table `[{}]` in {}", tag, path.display())
+                }
+                None => {}
+            }
+        }
+        Ok(toml::Value::Table(merged).try_into()?)
+    }
+
+    /// Title of a report section.
+    pub(crate) fn report_section<'s>(&'s self, section: &'s str) -> &'s str {
+        match section {
+            crate::spelling::SECTION => &self.spelling,
+            crate::metrics::SECTION => &self.readability,
+            crate::todo::SECTION => &self.leftover_markers,
+            crate::visual::SECTION => &self.visual_changes,
+            crate::outline::SECTION => &self.outline,
+            crate::code::SECTION => &self.examples,
+            crate::images::SECTION => &self.missing_images,
+            crate::images::REMOTE_SECTION => &self.remote_images,
+            crate::licenses::SECTION => &self.unlicensed_images,
+            crate::longlines::SECTION => &self.long_lines,
+            crate::size::SECTION => &self.large_chapters,
+            crate::tables::SECTION => &self.html_tables,
+            crate::encoding::SECTION => &self.encoding,
+            crate::timing::SECTION => &self.timing,
+            _ => section,
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! License audit of the copied images, as publishers need the license of
+//! every image printed.
+//!
+//! Licenses are declared next to the image in a sidecar file, REUSE style,
+//! `harbour.png.license`:
+//!
+//! ```text
+//! SPDX-FileCopyrightText: 1850 Jane Doe
+//! SPDX-License-Identifier: CC-BY-4.0
+//! ```
+//!
+//! or centrally in a TOML file, keyed by the path relative to the source
+//! directory:
+//!
+//! ```toml
+//! ["chapter/harbour.png"]
+//! license = "CC-BY-4.0"
+//! author = "Jane Doe"
+//! source = "https://example.com/harbour"
+//! ```
+
+use crate::raw::escape;
+use crate::Traversal;
+use fs_err as fs;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Report section of images without a license.
+pub(crate) const SECTION: &str = "unlicensed-images";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LicensesConfig {
+    // Audit the licenses of all images and print an appendix crediting them.
+    pub enable: bool,
+
+    // Central declaration of licenses, relative to the book root, used if it exists.
+    pub manifest: String,
+
+    // Fail the build if an image lacks a license.
+    pub strict: bool,
+}
+
+impl Default for LicensesConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            manifest: "assets.toml".to_owned(),
+            strict: false,
+        }
+    }
+}
+
+/// License of one image.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub(crate) struct License {
+    pub(crate) license: String,
+    pub(crate) author: Option<String>,
+    pub(crate) source: Option<String>,
+}
+
+impl License {
+    /// Parse a sidecar file, the whole text is taken as license if it has
+    /// no SPDX tags.
+    pub(crate) fn parse_sidecar(text: &str) -> Self {
+        let mut license = Self::default();
+        for line in text.lines() {
+            if let Some(identifier) = line.trim().strip_prefix("SPDX-License-Identifier:") {
+                license.license = identifier.trim().to_owned();
+            } else if let Some(copyright) = line.trim().strip_prefix("SPDX-FileCopyrightText:") {
+                license.author = Some(copyright.trim().to_owned());
+            }
+        }
+        if license.license.is_empty() && license.author.is_none() {
+            license.license = text.trim().to_owned();
+        }
+        license
+    }
+}
+
+/// Look up the licenses of all copied images, the sidecar file takes
+/// precedence over the central declaration.
+///
+/// Images without one are added to the report.
+pub(crate) fn audit(state: &mut Traversal) -> color_eyre::Result<BTreeMap<String, License>> {
+    let context = state.context;
+    let manifest = context.root.join(&state.cfg.licenses.manifest);
+    let mut declared: BTreeMap<String, License> = if manifest.is_file() {
+        toml::from_str(&fs::read_to_string(&manifest)?)?
+    } else {
+        BTreeMap::new()
+    };
+
+    let src = context.root.join(&context.config.book.src);
+    let mut licenses = BTreeMap::new();
+    let mut unlicensed: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for asset in state.assets.iter() {
+        let key = asset.source.to_string_lossy().replace('\\', "/");
+        let mut sidecar = src.join(&asset.source).into_os_string();
+        sidecar.push(".license");
+        let sidecar = Path::new(&sidecar);
+        let license = if sidecar.is_file() {
+            Some(License::parse_sidecar(&fs::read_to_string(sidecar)?))
+        } else {
+            declared.remove(&key)
+        };
+        match license {
+            Some(license) if !license.license.is_empty() => {
+                licenses.insert(key, license);
+            }
+            _ => {
+                for chapter in &asset.chapters {
+                    unlicensed
+                        .entry(chapter)
+                        .or_default()
+                        .push(format!("`{}`", key));
+                }
+            }
+        }
+    }
+    for (chapter, findings) in unlicensed {
+        state.report.add(SECTION, chapter, findings);
+    }
+    Ok(licenses)
+}
+
+/// An appendix crediting the images with their license.
+pub(crate) fn appendix(licenses: &BTreeMap<String, License>, title: &str) -> String {
+    let title = escape(title);
+    let mut tex = format!(
+        "\\section*{{{}}}\n\\phantomsection\n\\addcontentsline{{toc}}{{section}}{{{}}}\n\\begin{{description}}\n",
+        title, title
+    );
+    for (path, license) in licenses {
+        let mut credit = escape(&license.license);
+        if let Some(author) = &license.author {
+            credit = format!("{}, {}", escape(author), credit);
+        }
+        if let Some(source) = &license.source {
+            credit.push_str(&format!(", \\url{{{}}}", source));
+        }
+        tex.push_str(&format!(
+            "\\item[\\texttt{{{}}}] {}\n",
+            escape(path),
+            credit
+        ));
+    }
+    tex.push_str("\\end{description}\n");
+    tex
+}
@@ -0,0 +1,269 @@
+//! Links between the chapters of the book, which in the PDF point into the
+//! document itself rather than to the markdown files.
+//!
+//! Before any chapter is converted, the chapters are collected into a map
+//! of their source paths, so a link to a chapter later in the book resolves
+//! just as well. Links to `.md` files, or the `.html` files mdbook renders
+//! them to, become `\hyperref`s to the label of the heading the fragment
+//! names, or of the chapter. Links to chapters not in the book are dead in
+//! the PDF, they are printed as their text and reported.
+//!
+//! With `url-style = "footnote"`, links to the web are printed as their text
+//! with the address in a footnote, for readers of the paper edition. Where a
+//! footnote does not work, in footnotes and tables, the address follows the
+//! text in parentheses, and headings keep their links.
+//!
+//! For handouts, `qr-codes` prints a QR code of the address of links to the
+//! web, in the margin or right after the text.
+//!
+//! The colors of links override those of the template. For print runs,
+//! `colorlinks = false` frames links with boxes hyperref only shows on
+//! screen, or leaves them black with `link-boxes = false`.
+
+use crate::anchors;
+use crate::capabilities::Outcome;
+use crate::paths::resolve_dots;
+use crate::{LatexConfig, QrCodes, Traversal, UrlStyle};
+use mdbook::book::{Book, BookItem};
+use pulldown_cmark::{Event, LinkType, Tag};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// Where a link points to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Target {
+    // Outside the book, kept as it is.
+    External,
+    // The label of a chapter or heading of the book.
+    Internal(String),
+    // A chapter which is not part of the book.
+    Dead,
+}
+
+/// Source paths and heading ids of the chapters, by the path with forward
+/// slashes.
+#[derive(Debug, Default)]
+pub(crate) struct LinkMap {
+    chapters: BTreeMap<String, (PathBuf, BTreeSet<String>)>,
+}
+
+/// `path` with forward slashes, as a key of the map.
+fn key(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl LinkMap {
+    /// Collect the chapters of `book`.
+    pub(crate) fn new(book: &Book) -> Self {
+        let mut map = Self::default();
+        for item in book.iter() {
+            if let BookItem::Chapter(ch) = item {
+                if let Some(ref path) = ch.path {
+                    let ids = anchors::ids(&ch.content).into_iter().collect();
+                    map.chapters.insert(key(path), (path.clone(), ids));
+                }
+            }
+        }
+        map
+    }
+
+    /// Where the link `dest` in the chapter at `chapter` points to.
+    pub(crate) fn resolve(&self, chapter: &Path, dest: &str) -> Target {
+        if dest.contains("://") || dest.starts_with("mailto:") || dest.starts_with('/') {
+            return Target::External;
+        }
+        let (path, fragment) = dest.split_once('#').unwrap_or((dest, ""));
+        let path = path.split('?').next().unwrap_or_default();
+        if path.is_empty() {
+            return match self.chapters.get(&key(chapter)) {
+                Some(chapter) => Target::Internal(Self::label(chapter, fragment)),
+                None => Target::External,
+            };
+        }
+        let dir = chapter.parent().unwrap_or_else(|| Path::new(""));
+        let mut path = resolve_dots(&dir.join(path));
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("md") => {}
+            Some("html") => {
+                // mdbook renders a `README.md` to `index.html`.
+                if path.file_stem().map_or(false, |stem| stem == "index")
+                    && !self.chapters.contains_key(&key(&path.with_extension("md")))
+                {
+                    path.set_file_name("README.md");
+                }
+                path.set_extension("md");
+            }
+            _ => return Target::External,
+        }
+        match self.chapters.get(&key(&path)) {
+            Some(chapter) => Target::Internal(Self::label(chapter, fragment)),
+            None => Target::Dead,
+        }
+    }
+
+    /// Label of the heading `fragment` names in `chapter`, or else of the
+    /// chapter.
+    fn label((path, ids): &(PathBuf, BTreeSet<String>), fragment: &str) -> String {
+        if ids.contains(fragment) {
+            anchors::heading(path, fragment)
+        } else {
+            if !fragment.is_empty() {
+                log::debug!("No heading `{}` in {}", fragment, path.display());
+            }
+            anchors::chapter(path)
+        }
+    }
+}
+
+/// Setup of hyperref for the colors and style of links, `None` to keep the
+/// template's.
+pub(crate) fn hypersetup(cfg: &LatexConfig) -> Option<String> {
+    let mut options = Vec::new();
+    if cfg.colorlinks {
+        let colors = [
+            ("linkcolor", &cfg.link_color),
+            ("urlcolor", &cfg.url_color),
+            ("citecolor", &cfg.cite_color),
+        ];
+        for (option, color) in colors {
+            if let Some(color) = color {
+                options.push(format!("{}={{{}}}", option, color));
+            }
+        }
+    } else if cfg.link_boxes {
+        options.push("colorlinks=false".to_owned());
+        options.push("pdfborder={0 0 1}".to_owned());
+    } else {
+        options.push("hidelinks".to_owned());
+    }
+    if options.is_empty() {
+        return None;
+    }
+    Some(format!("\\hypersetup{{{}}}\n", options.join(", ")))
+}
+
+/// `url` for `\url` within the argument of another command.
+pub(crate) fn url_argument(url: &str) -> String {
+    url.replace('%', "\\%")
+        .replace('#', "\\#")
+        .replace('{', "\\%7B")
+        .replace('}', "\\%7D")
+}
+
+/// What follows the text of a link to the web: the address in a footnote
+/// or in parentheses, and a QR code.
+fn web_suffix(url: &str, headings: usize, inline: usize, state: &mut Traversal) -> String {
+    let url = url_argument(url);
+    let mut suffix = String::new();
+    if state.cfg.url_style == UrlStyle::Footnote && headings == 0 {
+        if inline > 0 {
+            suffix.push_str(&format!(" (\\url{{{}}})", url));
+        } else {
+            suffix.push_str(&format!("\\footnote{{\\url{{{}}}}}", url));
+        }
+    }
+    let qr = match state.cfg.qr_codes {
+        QrCodes::Off => return suffix,
+        _ if headings > 0 => return suffix,
+        QrCodes::Margin if inline == 0 => format!(
+            "\\marginpar{{\\qrcode[height={}]{{{}}}}}",
+            state.cfg.qr_size, url
+        ),
+        QrCodes::Margin | QrCodes::Inline => format!(
+            "\\,\\raisebox{{-0.3\\height}}{{\\qrcode[height={}]{{{}}}}}",
+            state.cfg.qr_size, url
+        ),
+    };
+    state.packages.insert("qrcode");
+    state.capabilities.record("QR codes", Outcome::Rendered);
+    suffix.push_str(&qr);
+    suffix
+}
+
+/// Turn links to other chapters into `\hyperref`s, and links to the web
+/// into footnotes and QR codes if the config says so.
+pub(crate) fn resolve<'a>(events: Vec<Event<'a>>, state: &mut Traversal) -> Vec<Event<'a>> {
+    let chapter = state.chapter.and_then(|chapter| chapter.path.as_ref());
+    let mut out = Vec::with_capacity(events.len());
+    // How the link being converted is closed, whether it is kept as a link,
+    // and what follows it.
+    let mut closing = None;
+    // Within headings, tables and footnotes, where no footnote can go.
+    let (mut headings, mut inline) = (0, 0);
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading(..)) => {
+                headings += 1;
+                out.push(event);
+            }
+            Event::End(Tag::Heading(..)) => {
+                headings -= 1;
+                out.push(event);
+            }
+            Event::Start(Tag::Table(_) | Tag::FootnoteDefinition(_)) => {
+                inline += 1;
+                out.push(event);
+            }
+            Event::End(Tag::Table(_) | Tag::FootnoteDefinition(_)) => {
+                inline -= 1;
+                out.push(event);
+            }
+            Event::Start(Tag::Link(link_type, dest, title)) => {
+                let target = match chapter {
+                    Some(chapter) => state.links.resolve(chapter, &dest),
+                    None => Target::External,
+                };
+                match target {
+                    Target::External
+                        if !matches!(link_type, LinkType::Autolink | LinkType::Email)
+                            && (dest.contains("://") || dest.starts_with("mailto:")) =>
+                    {
+                        let suffix = web_suffix(&dest, headings, inline, state);
+                        // The address is printed, the text need not be a link.
+                        let keep = state.cfg.url_style == UrlStyle::Hyperlink || headings > 0;
+                        if keep {
+                            out.push(Event::Start(Tag::Link(link_type, dest, title)));
+                        }
+                        closing = Some((keep, suffix));
+                    }
+                    Target::External => out.push(Event::Start(Tag::Link(link_type, dest, title))),
+                    Target::Internal(label) => {
+                        state
+                            .capabilities
+                            .record("Internal links", Outcome::Rendered);
+                        out.push(state.raw.inline(format!("\\hyperref[{}]{{", label)));
+                        closing = Some((false, "}".to_owned()));
+                    }
+                    Target::Dead => {
+                        log::warn!(
+                            "{} links to `{}`, which is not part of the book",
+                            chapter
+                                .map(|path| path.display().to_string())
+                                .unwrap_or_default(),
+                            dest
+                        );
+                        state.capabilities.record(
+                            "Internal links",
+                            Outcome::Degraded("link text only, target not in the book"),
+                        );
+                        closing = Some((false, String::new()));
+                    }
+                }
+            }
+            Event::End(Tag::Link(..)) if closing.is_some() => {
+                let (keep, closing) = closing.take().expect("Checked to be some. qed");
+                if keep {
+                    out.push(event);
+                }
+                if !closing.is_empty() {
+                    out.push(state.raw.inline(closing));
+                }
+            }
+            event => out.push(event),
+        }
+    }
+    out
+}
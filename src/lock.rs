@@ -0,0 +1,116 @@
+//! Builds sharing a destination, like `mdbook serve` and a manual build,
+//! take turns by a lock file, and outputs are written to a temporary file
+//! first and renamed into place, so readers never see half of one.
+//!
+//! A lock left behind by a crashed build is stale once its process is gone,
+//! where that can be told, or else after an hour.
+
+use color_eyre::eyre::bail;
+use fs_err as fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Name of the lock file in the destination.
+const LOCK_FILE: &str = ".mdbook-latex.lock";
+
+/// Age after which a lock is stale, if its process can not be checked.
+const STALE_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// How long to wait for another build to finish.
+const WAIT: Duration = Duration::from_secs(10 * 60);
+
+/// The lock of a destination, released when dropped.
+#[derive(Debug)]
+pub(crate) struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    /// Lock `destination`, waiting for another build holding the lock.
+    pub(crate) fn acquire(destination: &Path) -> color_eyre::Result<Self> {
+        fs::create_dir_all(destination)?;
+        let path = destination.join(LOCK_FILE);
+        let start = SystemTime::now();
+        let mut waiting = false;
+        loop {
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    writeln!(file, "{}", std::process::id())?;
+                    return Ok(Self { path });
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(err.into()),
+            }
+            if is_stale(&path) {
+                log::warn!("Removing the stale lock {}", path.display());
+                // Another build may have been quicker, that's fine.
+                let _ = std::fs::remove_file(&path);
+                continue;
+            }
+            if !waiting {
+                log::info!("Waiting for another build to release {}", path.display());
+                waiting = true;
+            }
+            if start.elapsed().unwrap_or_default() > WAIT {
+                bail!(
+                    "Another build holds {}, remove it if there is none",
+                    path.display()
+                );
+            }
+            std::thread::sleep(Duration::from_millis(500));
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            log::warn!("Failed to release the lock: {}", err);
+        }
+    }
+}
+
+/// Whether the lock at `path` was left behind by a build no longer running.
+pub(crate) fn is_stale(path: &Path) -> bool {
+    let pid = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| content.trim().parse::<u32>().ok());
+    // Where processes are listed in `/proc`, a lock is stale with its process.
+    let proc = Path::new("/proc");
+    if let Some(pid) = pid {
+        if proc.join("self").exists() {
+            return !proc.join(pid.to_string()).exists();
+        }
+    }
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .map_or(false, |age| age > STALE_AFTER)
+}
+
+/// Write `data` to `path` by way of a temporary file next to it, renamed
+/// into place once complete.
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    write_atomic_with(path, |out| out.write_all(data))
+}
+
+/// Write to `path` what `write` writes, buffered, by way of a temporary
+/// file next to it, renamed into place once complete.
+pub(crate) fn write_atomic_with(
+    path: &Path,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+    let mut out = io::BufWriter::new(fs::File::create(&tmp)?);
+    write(&mut out)?;
+    out.flush()?;
+    drop(out);
+    fs::rename(&tmp, path)
+}
@@ -0,0 +1,87 @@
+//! Code lines too long for the text width, wrapped with a continuation
+//! marker rather than running off the page, and optionally listed in the
+//! build report by where they are in the source.
+
+use crate::{Highlighting, Traversal};
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+/// Report section of the scan.
+pub(crate) const SECTION: &str = "long-lines";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct LongLinesConfig {
+    // Break code lines at the text width, marking where they continue.
+    pub wrap: bool,
+
+    // Columns a code line may have before it counts as long.
+    pub max_width: usize,
+
+    // List long code lines in the build report.
+    pub warn: bool,
+}
+
+impl Default for LongLinesConfig {
+    fn default() -> Self {
+        Self {
+            wrap: true,
+            max_width: 80,
+            warn: false,
+        }
+    }
+}
+
+/// Preamble breaking code lines with a marker at the start of the
+/// continuation, or keeping them whole if wrapping is off.
+pub(crate) fn preamble(cfg: &LongLinesConfig, highlighting: Option<Highlighting>) -> String {
+    if !cfg.wrap {
+        return "\n%% Code lines are not wrapped.\n\\lstset{breaklines=false}\n".to_owned();
+    }
+    let mut preamble = r"
+%% Wrap long code lines.
+\lstset{breaklines=true,breakatwhitespace=false,postbreak=\mbox{\textcolor{gray}{\ensuremath{\hookrightarrow}}\space}}
+"
+    .to_owned();
+    if highlighting == Some(Highlighting::Minted) {
+        preamble.push_str("\\setminted{breaklines=true,breakanywhere=true}\n");
+    }
+    preamble
+}
+
+/// Add all lines of code blocks in the chapter source longer than
+/// `max-width` to the report.
+pub(crate) fn scan(content: &str, state: &mut Traversal) {
+    let cfg = &state.cfg.long_lines;
+    if !cfg.warn {
+        return;
+    }
+    let file = state
+        .chapter
+        .and_then(|chapter| chapter.source_path.as_ref().or(chapter.path.as_ref()))
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+
+    let mut findings = Vec::new();
+    let mut in_code = false;
+    for (event, range) in Parser::new_ext(content, Options::all()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code = true,
+            Event::End(Tag::CodeBlock(_)) => in_code = false,
+            Event::Text(text) if in_code => {
+                let first = content[..range.start].matches('\n').count() + 1;
+                for (idx, line) in text.lines().enumerate() {
+                    let width = line.chars().count();
+                    if width > cfg.max_width {
+                        findings.push(format!("`{}:{}`: {} columns", file, first + idx, width));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let chapter = state
+        .chapter
+        .map(|chapter| chapter.name.as_str())
+        .unwrap_or_default();
+    state.report.add(SECTION, chapter, findings);
+}
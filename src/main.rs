@@ -1,14 +1,73 @@
-use cmark2tex::markdown_to_tex;
+use crate::raw::markdown_to_tex;
 use color_eyre::eyre::bail;
-use fs::OpenOptions;
 use fs_err as fs;
-use mdbook::book::BookItem;
+use mdbook::book::{BookItem, Chapter};
 use mdbook::renderer::RenderContext;
 use pulldown_cmark::{CowStr, Event, LinkType, Options, Parser, Tag};
-use std::io::{self, BufReader, Write};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufReader};
 use std::path::Path;
 use std::path::PathBuf;
 
+mod abstracts;
+mod admonitions;
+mod anchors;
+mod ansi;
+mod attachments;
+mod authors;
+mod bench;
+mod capabilities;
+mod code;
+mod columns;
+mod comments;
+mod definitions;
+mod doctor;
+mod encoding;
+mod engine;
+mod glossary;
+mod highlighting;
+mod html;
+mod ids;
+mod images;
+mod imposition;
+mod index;
+mod inline;
+mod labels;
+mod licenses;
+mod links;
+mod lock;
+mod longlines;
+mod manifest;
+mod math;
+mod metrics;
+mod outline;
+mod overrides;
+mod pages;
+mod paths;
+mod profile;
+mod quiz;
+mod raw;
+mod report;
+mod scripts;
+mod sections;
+mod series;
+mod shortcodes;
+mod size;
+mod slides;
+mod snapshot;
+mod spelling;
+mod subprocess;
+mod tables;
+mod templates;
+mod thumbtabs;
+mod timing;
+mod title;
+mod todo;
+mod typeset;
+mod version;
+mod vertical;
+mod visual;
+
 #[cfg(test)]
 mod tests;
 
@@ -31,9 +90,367 @@ pub struct LatexConfig {
     // Use user's LaTeX template file instead of default (template.tex).
     pub custom_template: Option<String>,
 
+    // Directory whose contents are copied next to the LaTeX output, for the template to use.
+    pub custom_template_dir: Option<String>,
+
+    // Directories the engine searches for input files, like shared `.sty` files, relative to the book root.
+    pub texinputs: Vec<String>,
+
+    // Print only the warnings and errors of the engine, the full log is written next to the PDF.
+    pub log_filter: bool,
+
+    // Retry engine runs which failed to download packages, as happens on flaky networks.
+    pub retry: typeset::RetryConfig,
+
+    // Time and memory limits of external tools run on the content, like `rustc` checking examples.
+    pub limits: subprocess::Limits,
+
+    // Layout of tables.
+    pub tables: tables::TablesConfig,
+
+    // What to do with tables estimated wider than the text.
+    pub wide_table: WideTable,
+
+    // Strip the lines of Rust examples mdbook hides, `# ` prefixed, rather than print them.
+    pub hide_hidden_lines: bool,
+
+    // What to do with ANSI escape sequences in `console` and `ansi` blocks.
+    pub ansi: Ansi,
+
     // Date to be used in the LaTeX \date{} macro
     #[serde(default = "today")]
     pub date: String,
+
+    // List unnumbered sections in the table of contents, not only in the PDF bookmarks.
+    pub unnumbered_toc: bool,
+
+    // Page style of the prefix chapters, numbered in roman, if the template has no front matter
+    // marker. Left to the template if not set.
+    pub frontmatter_pagestyle: Option<String>,
+
+    // How to treat line breaks within a paragraph which are not hard breaks.
+    pub soft_break: SoftBreak,
+
+    // How links to the web are printed.
+    pub url_style: UrlStyle,
+
+    // Print a QR code of the address of links to the web, for readers of the paper copy.
+    pub qr_codes: QrCodes,
+
+    // Height of the QR codes, a LaTeX length.
+    pub qr_size: String,
+
+    // Colors of links within the book, to the web and to citations, in `xcolor` syntax. The
+    // template's colors if not set.
+    pub link_color: Option<String>,
+    pub url_color: Option<String>,
+    pub cite_color: Option<String>,
+
+    // Color the text of links, else they are boxed on screen, or black with `link-boxes = false`.
+    pub colorlinks: bool,
+
+    // Frame links with boxes, which are not printed, if `colorlinks` is off.
+    pub link_boxes: bool,
+
+    // Draft build, renders notes meant for the authors, i.e. HTML comments.
+    pub draft: bool,
+
+    // How to render collapsible `<details>` sections.
+    pub details: Details,
+
+    // Base URL of the online version of the book, print refers to it for content it can not show.
+    pub online_url: Option<String>,
+
+    // Names of the tabs to keep from tabbed content, all of them if empty.
+    pub tabs: Vec<String>,
+
+    // Where to print the answers to quiz questions.
+    pub quiz_answers: QuizAnswers,
+
+    // Print thumb tabs on the outer page edge, shifting down per top level chapter.
+    pub thumb_tabs: bool,
+
+    // Glossary terms, styled on first use per chapter according to `first_use`.
+    pub glossary: Vec<String>,
+
+    // Style of the first use of a glossary term.
+    pub first_use: TermStyle,
+
+    // Spell checking, findings go into the build report.
+    pub spellcheck: spelling::SpellcheckConfig,
+
+    // Add readability metrics per chapter to the build report.
+    pub readability: bool,
+
+    // Scan for leftover markers like `TODO`, findings go into the build report.
+    pub todo: todo::TodoConfig,
+
+    // Localization file overriding the generated text, relative to the book root.
+    pub labels: Option<String>,
+
+    // Further editions, built from translated source trees next to `book.src`, `src.de` for `de`.
+    pub languages: Vec<String>,
+
+    // Main font per language, e.g. for scripts the default font lacks.
+    pub fonts: BTreeMap<String, String>,
+
+    // TeX engine building the PDF, chosen according to the content if not set.
+    pub engine: Option<Engine>,
+
+    // Vertical writing of Japanese books, requires the `lualatex` engine.
+    pub vertical: bool,
+
+    // Checked in LaTeX to compare against with `--check-tex`, relative to the book root.
+    pub tex_baseline: Option<String>,
+
+    // Check that Rust examples compile, failures go into the build report.
+    pub check_examples: bool,
+
+    // Cross-check the PDF outline against the headings, findings go into the build report.
+    pub check_outline: bool,
+
+    // Visual regression checks of the PDF pages, findings go into the build report.
+    pub visual: visual::VisualConfig,
+
+    // Link listings included from files to their source, `{rev}`, `{path}` and `{start}` are replaced.
+    pub source_url: Option<String>,
+
+    // Revision for `source_url`, the commit checked out if not set.
+    pub source_rev: Option<String>,
+
+    // Files and directories attached to the PDF, relative to the book root.
+    pub attachments: Vec<String>,
+
+    // Attach the markdown of the whole book to the PDF, it is written regardless of `markdown`.
+    pub attach_markdown: bool,
+
+    // Directory the images are copied to, relative to the build directory.
+    pub image_dir: String,
+
+    // How the copied images are laid out within `image_dir`.
+    pub image_layout: ImageLayout,
+
+    // Number images alone in their paragraph as figures, captioned with their alt text.
+    pub figures: bool,
+
+    // Write `assets.json`, listing the copied images with their hash and the chapters using them.
+    pub asset_manifest: bool,
+
+    // Audit the licenses of the images, crediting them in an appendix.
+    pub licenses: licenses::LicensesConfig,
+
+    // Write `pages.json`, mapping each chapter to its page range in the PDF.
+    pub page_map: bool,
+
+    // Split the PDF into one PDF per chapter, according to the page map.
+    pub split_chapters: bool,
+
+    // Additionally impose the PDF for printing, e.g. as booklet.
+    pub imposition: Option<Imposition>,
+
+    // Derive a beamer slide deck or handout from the headings and lists.
+    pub slides: slides::SlidesConfig,
+
+    // Typography preset applied atop the template.
+    pub profile: Option<Profile>,
+
+    // Write `build.json`, describing the files written and the duration of each phase.
+    pub build_manifest: bool,
+
+    // List the duration of the phases of the conversion per chapter in the build report.
+    pub timing: bool,
+
+    // Mixed into the labels of generated anchors, to keep those of books typeset together apart.
+    pub id_seed: String,
+
+    // Color and icon per kind of admonition, e.g. `warning`, overriding the built in ones.
+    pub admonitions: BTreeMap<String, admonitions::AdmonitionStyle>,
+
+    // Print the fenced blocks of `mdbook-admonish` as admonitions, rather than as code.
+    pub admonish: bool,
+
+    // Pass math, `$...$` and `$$...$$`, through to LaTeX as written.
+    pub math: bool,
+
+    // Pass math delimited as by `mdbook-katex`, `\(...\)` and `\[...\]`, through as well.
+    pub katex_delimiters: bool,
+
+    // Affiliation, ORCID iD and email per author, by their name in `book.authors`.
+    pub author_details: BTreeMap<String, authors::AuthorDetails>,
+
+    // Printed below the title on the title page.
+    pub subtitle: Option<String>,
+
+    // Lines the title is broken into on the title page, the whole title if empty.
+    pub title_lines: Vec<String>,
+
+    // Series the book is a volume of.
+    pub series: series::Series,
+
+    // Print a half title, the title alone on a page, before the title page.
+    pub half_title: bool,
+
+    // Print a page listing the volumes of the series before the title page.
+    pub series_page: bool,
+
+    // Version of the book, as `git describe` gives it if not set.
+    pub version: Option<String>,
+
+    // Print the version in the footer of every page.
+    pub version_footer: bool,
+
+    // Backend highlighting the syntax of code blocks, none if not set.
+    pub highlighting: Option<Highlighting>,
+
+    // `listings` language per fence language, for those the `listings` backend does not know.
+    pub listings_languages: BTreeMap<String, String>,
+
+    // Chapter printed as the abstract below the title, by its path within `src`, e.g. `abstract.md`.
+    #[serde(rename = "abstract")]
+    pub abstract_chapter: Option<PathBuf>,
+
+    // Keywords, printed under the abstract and added to the PDF metadata.
+    pub keywords: Vec<String>,
+
+    // Subject classification codes per scheme, e.g. `MSC2020` or `ACM CCS`, like the keywords.
+    pub classification: BTreeMap<String, Vec<String>>,
+
+    // Wrapping of code lines too long for the page, and reporting them.
+    pub long_lines: longlines::LongLinesConfig,
+
+    // Reporting, cutting and converting piece by piece of very large chapters.
+    pub chapter_size: size::SizeConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Engine {
+    Tectonic,
+    Lualatex,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Highlighting {
+    // Pygments, by way of the `minted` package, which requires shell escape.
+    Minted,
+    // The `listings` package, within TeX.
+    Listings,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Ansi {
+    // Print the text in the colors the terminal shows.
+    Color,
+    // Print the text plain.
+    Strip,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WideTable {
+    // Turn the table sideways, on a page of its own.
+    Rotate,
+    // Scale the table down to the text width.
+    Shrink,
+    // Leave it running into the margin.
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Oversize {
+    // Drop the rest of the chapter.
+    Truncate,
+    // Keep only the headings of the rest, for the table of contents and links.
+    Outline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SoftBreak {
+    // Join the lines, as HTML does.
+    Space,
+    // Keep the line break.
+    Newline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UrlStyle {
+    // A clickable link on the text.
+    Hyperlink,
+    // The text, with the address in a footnote.
+    Footnote,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QrCodes {
+    Off,
+    // In the margin next to the line of the link, inline within tables and footnotes.
+    Margin,
+    // Right after the text of the link.
+    Inline,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Details {
+    // A framed box titled by the summary.
+    Box,
+    // Leave out the content, with a note in its place.
+    Omit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuizAnswers {
+    // Not at all.
+    Hidden,
+    // Right below each question.
+    Inline,
+    // In an answer key at the end of the book.
+    Appendix,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TermStyle {
+    // Leave terms as they are.
+    None,
+    Bold,
+    Italic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ImageLayout {
+    // Keep the paths relative to the source directory.
+    Mirror,
+    // All in one directory, named by the hash of their content.
+    Flatten,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Imposition {
+    // Folded sheets, read in order once stacked.
+    Booklet,
+    // Two pages side by side per sheet.
+    TwoUp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Profile {
+    // Small pages for e-ink readers.
+    Ereader,
+    // At least 14pt with strong contrast, for accessibility.
+    LargePrint,
+    // Dyslexia friendly font and spacing.
+    Dyslexia,
 }
 
 fn today() -> String {
@@ -48,19 +465,184 @@ impl Default for LatexConfig {
             pdf: true,
             markdown: true,
             custom_template: None,
+            custom_template_dir: None,
+            texinputs: Vec::new(),
+            log_filter: true,
+            retry: Default::default(),
+            limits: Default::default(),
+            tables: Default::default(),
+            wide_table: WideTable::None,
+            hide_hidden_lines: true,
+            ansi: Ansi::Color,
             date: today(),
+            unnumbered_toc: false,
+            frontmatter_pagestyle: Some("plain".to_owned()),
+            soft_break: SoftBreak::Space,
+            url_style: UrlStyle::Hyperlink,
+            qr_codes: QrCodes::Off,
+            qr_size: "1.5cm".to_owned(),
+            link_color: None,
+            url_color: None,
+            cite_color: None,
+            colorlinks: true,
+            link_boxes: true,
+            draft: false,
+            details: Details::Box,
+            online_url: None,
+            tabs: Default::default(),
+            quiz_answers: QuizAnswers::Hidden,
+            thumb_tabs: false,
+            glossary: Default::default(),
+            first_use: TermStyle::None,
+            spellcheck: Default::default(),
+            readability: false,
+            todo: Default::default(),
+            labels: None,
+            languages: Default::default(),
+            fonts: Default::default(),
+            engine: None,
+            vertical: false,
+            tex_baseline: None,
+            check_examples: false,
+            check_outline: false,
+            visual: Default::default(),
+            source_url: None,
+            source_rev: None,
+            attachments: Default::default(),
+            attach_markdown: false,
+            image_dir: "images".to_owned(),
+            image_layout: ImageLayout::Mirror,
+            figures: false,
+            asset_manifest: false,
+            licenses: Default::default(),
+            page_map: false,
+            split_chapters: false,
+            imposition: None,
+            slides: Default::default(),
+            profile: None,
+            build_manifest: false,
+            timing: false,
+            id_seed: String::new(),
+            admonitions: Default::default(),
+            admonish: false,
+            math: false,
+            katex_delimiters: false,
+            author_details: Default::default(),
+            subtitle: None,
+            title_lines: Vec::new(),
+            series: Default::default(),
+            half_title: false,
+            series_page: false,
+            version: None,
+            version_footer: false,
+            highlighting: None,
+            listings_languages: Default::default(),
+            abstract_chapter: None,
+            keywords: Vec::new(),
+            classification: Default::default(),
+            long_lines: Default::default(),
+            chapter_size: Default::default(),
         }
     }
 }
 
+/// State carried across all chapters while traversing the book.
+pub(crate) struct Traversal<'c> {
+    pub(crate) context: &'c RenderContext,
+    pub(crate) cfg: &'c LatexConfig,
+    pub(crate) raw: raw::Passthrough,
+    // Labels of generated anchors.
+    pub(crate) ids: ids::Ids,
+    // The chapter currently traversed.
+    pub(crate) chapter: Option<&'c Chapter>,
+    pub(crate) quiz: quiz::QuizState,
+    pub(crate) report: report::Report,
+    pub(crate) labels: labels::Labels,
+    // Packages the content requires, added to the preamble.
+    pub(crate) packages: BTreeSet<&'static str>,
+    pub(crate) capabilities: capabilities::Capabilities,
+    // Revision of the repository source links point to.
+    pub(crate) revision: Option<String>,
+    // Images copied so far, by their source.
+    pub(crate) assets: images::Assets,
+    // Labels of the chapters links may point to.
+    pub(crate) links: links::LinkMap,
+    // Heading ids of the chapter, across the pieces it is converted in.
+    pub(crate) anchors: anchors::ChapterAnchors,
+    pub(crate) timings: timing::Timings,
+    // Whether listings highlight lines, by `\mdbookhlnumber`.
+    pub(crate) highlighted_lines: bool,
+    // Whether terminal output is colored, by the `ansi` colors.
+    pub(crate) ansi_colors: bool,
+    // Whether listings are captioned, named by the `listing` label.
+    pub(crate) code_captions: bool,
+}
+
+impl<'c> Traversal<'c> {
+    pub(crate) fn new(context: &'c RenderContext, cfg: &'c LatexConfig) -> Self {
+        Self {
+            context,
+            cfg,
+            raw: Default::default(),
+            ids: ids::Ids::new(&cfg.id_seed),
+            chapter: None,
+            quiz: Default::default(),
+            report: Default::default(),
+            labels: labels::Labels::builtin(context.config.book.language.as_deref()),
+            packages: Default::default(),
+            capabilities: Default::default(),
+            revision: None,
+            assets: Default::default(),
+            links: links::LinkMap::new(&context.book),
+            anchors: Default::default(),
+            timings: Default::default(),
+            highlighted_lines: false,
+            ansi_colors: false,
+            code_captions: false,
+        }
+    }
+
+    /// Path of the current chapter within the HTML output.
+    pub(crate) fn chapter_url(&self) -> Option<String> {
+        let mut path = self.chapter?.path.clone()?;
+        path.set_extension("html");
+        Some(
+            path.components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+        )
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 enum Error {
     #[error("Failed to parse STDIN as `RenderContext` JSON: {0:?}")]
     MdBook(mdbook::errors::Error),
+    #[error("Failed to load the `{0}` edition: {1:?}")]
+    Edition(String, mdbook::errors::Error),
     #[error(transparent)]
     Regex(#[from] regex::Error),
 }
 
+/// Read the options from the book config, overridden by the profile and the
+/// environment. The `profile` given on the command line takes precedence
+/// over the one of the environment.
+fn load_config(config: &mdbook::Config, profile: Option<&str>) -> color_eyre::Result<LatexConfig> {
+    let mut config = config
+        .get("output.latex")
+        .cloned()
+        .unwrap_or_else(|| toml::Value::Table(Default::default()));
+    let profile = profile
+        .map(str::to_owned)
+        .or_else(|| std::env::var(overrides::PROFILE_VAR).ok());
+    overrides::select(&mut config, profile.as_deref())?;
+    overrides::apply(&mut config, std::env::vars());
+    config.try_into().map_err(|err| {
+        color_eyre::eyre::eyre!("Error reading \"output.latex\" configuration: {}", err)
+    })
+}
+
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
 
@@ -72,6 +654,21 @@ fn main() -> color_eyre::Result<()> {
         .filter(Some("cmark2tex"), LevelFilter::Warn)
         .init();
 
+    let (flags, profile) = match Invocation::parse(std::env::args().skip(1))? {
+        Invocation::Doctor(root) => {
+            let root = match root {
+                Some(root) => root,
+                None => std::env::current_dir()?,
+            };
+            if !doctor::run(&root)? {
+                bail!("Not ready to build, see above");
+            }
+            return Ok(());
+        }
+        Invocation::Bench(chapters) => return bench::run(chapters),
+        Invocation::Render { flags, profile } => (flags, profile),
+    };
+
     let stdin = BufReader::new(io::stdin());
 
     // Get markdown source from the mdbook command via stdin
@@ -93,121 +690,750 @@ fn main() -> color_eyre::Result<()> {
 
     log::debug!(
         "mdbook-tectonic called from {}!",
-        std::env::current_dir().unwrap().display()
+        std::env::current_dir()?.display()
     );
 
-    // Get configuration options from book.toml.
-    let cfg: LatexConfig = ctx
-        .config
-        .get_deserialized_opt("output.latex")
-        .expect("Error reading \"output.latex\" configuration")
+    let cfg = load_config(&ctx.config, profile.as_deref())?;
+
+    if (flags.check_tex || flags.update_tex) && cfg.tex_baseline.is_none() {
+        bail!("`--check-tex` and `--update-tex` require `tex-baseline` to be set");
+    }
+
+    // Held until all editions are written.
+    let _lock = lock::Lock::acquire(&ctx.destination)?;
+    render(&ctx, &cfg, None, flags)?;
+
+    // Editions in other languages, from translated source trees.
+    for language in &cfg.languages {
+        let mut config = ctx.config.clone();
+        config.book.src = PathBuf::from(format!("{}.{}", config.book.src.display(), language));
+        config.book.language = Some(language.clone());
+        let book = edition_book(&ctx.root, &config)
+            .map_err(|err| Error::Edition(language.clone(), err))?;
+        let mut edition = ctx.clone();
+        edition.book = book;
+        edition.config = config;
+        render(&edition, &cfg, Some(language), flags)?;
+    }
+
+    Ok(())
+}
+
+/// The book of an edition, loaded from its source tree and passed through
+/// the preprocessors built into mdbook, which expand `{{#include}}` and turn
+/// `README.md` into index chapters.
+///
+/// Preprocessors of other crates are skipped, mdbook offers no way to run
+/// them outside of its own builds.
+fn edition_book(
+    root: &Path,
+    config: &mdbook::Config,
+) -> mdbook::errors::Result<mdbook::book::Book> {
+    use mdbook::preprocess::{
+        IndexPreprocessor, LinkPreprocessor, Preprocessor, PreprocessorContext,
+    };
+
+    let mut book = mdbook::book::load_book(root.join(&config.book.src), &config.build)?;
+    if !config.build.use_default_preprocessors {
+        return Ok(book);
+    }
+    // The context has no public constructor, but is what mdbook sends
+    // preprocessors as JSON.
+    let ctx: PreprocessorContext = serde_json::from_value(serde_json::json!({
+        "root": root,
+        "config": config,
+        "renderer": "latex",
+        "mdbook_version": mdbook::MDBOOK_VERSION,
+    }))?;
+    let preprocessors: [Box<dyn Preprocessor>; 2] = [
+        Box::new(LinkPreprocessor::new()),
+        Box::new(IndexPreprocessor::new()),
+    ];
+    for preprocessor in preprocessors {
+        book = preprocessor.run(&ctx, book)?;
+    }
+    let skipped = config
+        .get("preprocessor")
+        .and_then(toml::Value::as_table)
+        .map(|table| {
+            table
+                .keys()
+                .filter(|name| !["links", "index"].contains(&name.as_str()))
+                .map(|name| format!("`{}`", name))
+                .collect::<Vec<_>>()
+        })
         .unwrap_or_default();
+    if !skipped.is_empty() {
+        log::warn!(
+            "Preprocessors {} are not run for the `{}` edition",
+            skipped.join(", "),
+            config.book.language.as_deref().unwrap_or_default()
+        );
+    }
+    Ok(book)
+}
+
+/// What the command line asks for.
+#[derive(Debug, PartialEq)]
+enum Invocation {
+    // `doctor [root]`, checking the book at `root`, the current directory if
+    // not given.
+    Doctor(Option<PathBuf>),
+    // `--bench [chapters]`, converting a synthetic book.
+    Bench(usize),
+    // Rendering the book mdbook passes on stdin, the default.
+    Render {
+        flags: Flags,
+        // `--profile <name>`, the options profile to select.
+        profile: Option<String>,
+    },
+}
+
+impl Invocation {
+    /// Parse the arguments following the program name.
+    fn parse(args: impl IntoIterator<Item = String>) -> color_eyre::Result<Self> {
+        let mut args = args.into_iter().peekable();
+        match args.peek().map(String::as_str) {
+            Some("doctor") => return Ok(Self::Doctor(args.nth(1).map(PathBuf::from))),
+            Some("--bench") => {
+                let chapters = match args.nth(1) {
+                    Some(chapters) => chapters.parse()?,
+                    None => 200,
+                };
+                return Ok(Self::Bench(chapters));
+            }
+            _ => {}
+        }
+        let mut flags = Flags::default();
+        let mut profile = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--check-tex" => flags.check_tex = true,
+                "--update-tex" => flags.update_tex = true,
+                "--update-pages" => flags.update_pages = true,
+                "--verbose" => flags.verbose = true,
+                "--profile" => match args.next() {
+                    Some(name) => profile = Some(name),
+                    None => bail!("`--profile` requires the name of a profile"),
+                },
+                _ => log::warn!("Ignoring the unknown argument `{}`", arg),
+            }
+        }
+        Ok(Self::Render { flags, profile })
+    }
+}
+
+/// Command line flags, passed along by mdbook as part of the `command` of
+/// the renderer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Flags {
+    // Compare the LaTeX against the baseline instead of writing any output.
+    check_tex: bool,
+    // Replace the baseline with the LaTeX instead of writing any output.
+    update_tex: bool,
+    // Replace the reference pages of the visual comparison.
+    update_pages: bool,
+    // Print all of the engine output, whatever `log-filter` says.
+    verbose: bool,
+}
 
+/// Render the book, `edition` is the language of a translated edition, which
+/// is appended to the file names.
+fn render(
+    ctx: &RenderContext,
+    cfg: &LatexConfig,
+    edition: Option<&str>,
+    flags: Flags,
+) -> color_eyre::Result<()> {
     // Read book's config values (title, authors).
     let title = ctx
         .config
         .book
         .title
-        .as_ref()
-        .map(|s| s.as_str())
+        .as_deref()
         .unwrap_or("<Unknown Title>");
-    let authors = ctx.config.book.authors.join(" \\and ");
+    let authors = authors::block(&ctx.config.book.authors, &cfg.author_details);
     let date = cfg.date.clone();
+    let language = ctx.config.book.language.as_deref();
+    let features = engine::Features::scan(&ctx.book, cfg)?;
+    let engine = engine::select(features, cfg)?;
+    let name = match edition {
+        Some(edition) => format!("{}-{}", title, edition),
+        None => title.to_owned(),
+    };
+
+    let mut state = Traversal::new(ctx, cfg);
+    if let Some(ref path) = cfg.labels {
+        state.labels = labels::Labels::load(&ctx.root.join(path), language)?;
+    }
+    if cfg.source_url.is_some() {
+        state.revision = Some(code::revision(&ctx.root, cfg.source_rev.as_deref()));
+    }
 
     // Copy template data into memory.
-    let mut template = if let Some(custom_template) = cfg.custom_template {
+    let mut template = if let Some(ref custom_template) = cfg.custom_template {
         let mut custom_template_path = ctx.root.clone();
         custom_template_path.push(custom_template);
-        fs::read_to_string(custom_template_path)?
+        let template = fs::read_to_string(custom_template_path)?;
+        templates::validate(&template, custom_template)?;
+        template
     } else {
         include_str!("template.tex").to_string()
     };
+    let mut typesetter = typeset::Typesetter::new(engine);
+    typesetter.shell_escape = highlighting::needs_shell_escape(cfg.highlighting);
+    typesetter.filter_log = cfg.log_filter && !flags.verbose;
+    typesetter.retry = cfg.retry.clone();
+    if let Some(ref custom_template) = cfg.custom_template {
+        // Resources of the template are found relative to it.
+        if let Some(dir) = ctx.root.join(custom_template).parent() {
+            typesetter.search_paths.push(dir.to_owned());
+        }
+    }
+    for dir in &cfg.texinputs {
+        let path = ctx.root.join(dir);
+        if !path.is_dir() {
+            log::warn!("The TeX input directory {} does not exist", dir);
+        }
+        typesetter.search_paths.push(path);
+    }
+    if let Some(ref dir) = cfg.custom_template_dir {
+        let path = ctx.root.join(dir);
+        if !path.is_dir() {
+            bail!("The custom template directory {} does not exist", dir);
+        }
+        let copied = templates::copy_dir(&path, &std::env::current_dir()?)?;
+        log::debug!("Copied {} files of the template directory {}", copied, dir);
+    }
 
     // Add title and author information.
-    template = template.replace(r"\title{}", &format!("\\title{{{}}}", title));
+    let title_page = series::title(
+        &title::page(title, &cfg.title_lines, cfg.subtitle.as_deref()),
+        &cfg.series,
+        &state.labels,
+    );
+    template = template.replace(r"\title{}", &format!("\\title{{{}}}", title_page));
+    template = template.replace(
+        r"\newcommand{\mdbooktitle}{}",
+        &format!("\\newcommand{{\\mdbooktitle}}{{{}}}", title::plain(title)),
+    );
+    template = template.replace(
+        r"\newcommand{\mdbookseries}{}",
+        &format!(
+            "\\newcommand{{\\mdbookseries}}{{{}}}",
+            series::line(&cfg.series, &state.labels)
+        ),
+    );
+    template = template.replace(
+        r"\newcommand{\mdbookvolumes}{}",
+        &format!(
+            "\\newcommand{{\\mdbookvolumes}}{{{}}}",
+            series::others(&cfg.series, &state.labels)
+        ),
+    );
+    let front_pages = series::front_pages(
+        &title::plain(title),
+        &cfg.series,
+        cfg.half_title,
+        cfg.series_page,
+        &state.labels,
+    );
+    if !front_pages.is_empty() {
+        if !template.contains(r"\newcommand{\mdbookfrontpages}{}") {
+            log::warn!("The template lacks `\\newcommand{{\\mdbookfrontpages}}{{}}`, no half title or series page is printed");
+        }
+        template = template.replace(
+            r"\newcommand{\mdbookfrontpages}{}",
+            &format!("\\newcommand{{\\mdbookfrontpages}}{{{}}}", front_pages),
+        );
+    }
     template = template.replace(r"\author{}", &format!("\\author{{{}}}", authors));
+    template = template.replace(
+        r"\newcommand{\mdbookauthors}{}",
+        &format!(
+            "\\newcommand{{\\mdbookauthors}}{{{}}}",
+            authors::list(&ctx.config.book.authors)
+        ),
+    );
     template = template.replace(r"\date{}", &format!("\\date{{{}}}", date));
+    template = template.replace(
+        r"\newcommand{\mdbookkeywords}{}",
+        &format!(
+            "\\newcommand{{\\mdbookkeywords}}{{{}}}",
+            abstracts::keywords(&cfg.keywords, &cfg.classification, &state.labels)
+        ),
+    );
+    template = template.replace(
+        r"\newcommand{\mdbookpdfkeywords}{}",
+        &format!(
+            "\\newcommand{{\\mdbookpdfkeywords}}{{{}}}",
+            abstracts::pdf_keywords(&cfg.keywords, &cfg.classification)
+        ),
+    );
+    let version = version::resolve(&ctx.root, cfg.version.as_deref());
+    template = template.replace(
+        r"\newcommand{\mdbookversion}{}",
+        &format!(
+            "\\newcommand{{\\mdbookversion}}{{{}}}",
+            raw::escape(version.as_deref().unwrap_or_default())
+        ),
+    );
+    if cfg.vertical {
+        template = template.replace(
+            r"\documentclass{article}",
+            &format!("\\documentclass{{{}}}", vertical::DOCUMENT_CLASS),
+        );
+    }
+    template = template.replace(
+        r"\usepackage[english]{babel}",
+        &format!(
+            "\\usepackage[{}{}]{{babel}}",
+            features.babel_options(engine),
+            labels::babel(language)
+        ),
+    );
 
-    let mut latex = String::new();
+    let top_level_chapters = ctx
+        .book
+        .sections
+        .iter()
+        .filter(|item| match item {
+            BookItem::Chapter(ch) => {
+                ch.path.is_some()
+                    && ch.path != cfg.abstract_chapter
+                    && !cfg.ignores.contains(&ch.name)
+            }
+            _ => false,
+        })
+        .count();
 
-    // Iterate through markdown source and push the chapters onto one single string.
-    let mut content = String::new();
+    if let Some(language) = language.filter(|language| scripts::is_no_space(language)) {
+        if !cfg.fonts.contains_key(language) {
+            log::warn!(
+                "The default font lacks the script of `{}`, set one in `fonts`",
+                language
+            );
+        }
+    }
+
+    let mut top_level_chapter = 0;
+    let mut manifest = manifest::Manifest::new(edition, cfg.profile, engine);
+    let start = std::time::Instant::now();
+
+    // Iterate through markdown source and collect the chapters per template marker.
+    let mut content = templates::Content::default();
+    let mut abstract_markdown = None;
+    let mut after_numbered = false;
+    let mut in_frontmatter = false;
+    // Whether the front matter was set apart, by `\mdbookfrontmatter`.
+    let mut matter_switched = false;
+    let mut part = None;
     for item in ctx.book.iter() {
-        // Iterate through each chapter.
-        if let BookItem::Chapter(ref ch) = *item {
-            if cfg.ignores.contains(&ch.name) {
+        let ch = match item {
+            BookItem::Chapter(ch) => ch,
+            BookItem::PartTitle(title) => {
+                part = Some(title.as_str());
                 continue;
             }
-
-            // Add chapter path to relative links.
-            content.push_str(&traverse_markdown(
-                &ch.content,
-                ch.path.as_ref().unwrap().parent().unwrap(),
-                &ctx,
+            BookItem::Separator => continue,
+        };
+        if cfg.ignores.contains(&ch.name) {
+            continue;
+        }
+        // Draft chapters, listed in `SUMMARY.md` without a file, have no content.
+        let path = match ch.path {
+            Some(ref path) => path,
+            None => continue,
+        };
+        state.chapter = Some(ch);
+        state.ids.enter(&path.to_string_lossy().replace('\\', "/"));
+        if cfg.abstract_chapter.as_ref() == Some(path) {
+            abstract_markdown = Some(traverse_markdown(
+                abstracts::strip_title(&ch.content),
+                path.parent().unwrap_or_else(|| Path::new("")),
+                false,
+                &mut state,
             )?);
+            continue;
         }
+        let frontmatter = ch.number.is_none() && !after_numbered;
+        let marker =
+            templates::chapter_marker(&template, ch.number.is_some(), after_numbered, part);
+        after_numbered |= ch.number.is_some();
+        let content = content.at(&marker);
+
+        // Without a marker of its own, the front matter is set apart here.
+        if cfg.frontmatter_pagestyle.is_some() {
+            let switch = match (frontmatter, in_frontmatter) {
+                (true, false) if marker == templates::BEGIN => Some("\\mdbookfrontmatter"),
+                (false, true) => Some("\\mdbookmainmatter"),
+                _ => None,
+            };
+            if let Some(switch) = switch {
+                in_frontmatter = frontmatter;
+                matter_switched = true;
+                state
+                    .capabilities
+                    .record("Front matter page style", capabilities::Outcome::Rendered);
+                content.push_str(&format!("\n\n{}\n\n", state.raw.token(switch)));
+            }
+        }
+
+        if cfg.thumb_tabs && ch.parent_names.is_empty() {
+            top_level_chapter += 1;
+            let select = thumbtabs::select(top_level_chapter, top_level_chapters);
+            content.push_str(&format!("\n\n{}\n\n", state.raw.token(select)));
+        }
+
+        // Add chapter path to relative links.
+        content.push_str(&traverse_markdown(
+            &ch.content,
+            path.parent().unwrap_or_else(|| Path::new("")),
+            ch.number.is_some(),
+            &mut state,
+        )?);
+    }
+    let backmatter = templates::or_begin(&template, templates::BACKMATTER);
+    if let Some(answer_key) = quiz::answer_key(&mut state) {
+        content.at(backmatter).push_str(&answer_key);
+    }
+    if let Some(index) = index::print(&mut state) {
+        let marker = match templates::find(&template, templates::INDEX) {
+            Some(_) => templates::INDEX,
+            None => backmatter,
+        };
+        content.at(marker).push_str(&index);
+    }
+    let empty = content.is_empty();
+    if empty {
+        log::warn!(
+            "The book {} has no content, writing an empty document",
+            name
+        );
+    }
+    let licenses = if cfg.licenses.enable {
+        licenses::audit(&mut state)?
+    } else {
+        Default::default()
+    };
+    let mut attached = attachments::files(&ctx.root, &cfg.attachments)?;
+    if !attached.is_empty() || cfg.attach_markdown {
+        state.packages.insert("attachfile");
+    }
+    if features.emoji && engine == Engine::Tectonic {
+        state.capabilities.record(
+            "Emoji",
+            capabilities::Outcome::Degraded("monochrome, as far as the font has them"),
+        );
+    }
+    if !state.capabilities.is_empty() {
+        log::info!(
+            "Print capabilities of {}:\n{}",
+            name,
+            state.capabilities.render()
+        );
+    }
+
+    // Preamble additions, inserted right before the document begins.
+    let mut preamble = String::new();
+    for package in &state.packages {
+        preamble.push_str(&format!("\\usepackage{{{}}}\n", package));
+    }
+    if state.packages.contains(index::PACKAGE) {
+        preamble.push_str(index::PREAMBLE);
+    }
+    preamble.push_str(&features.preamble(engine, cfg.vertical));
+    if let Some(font) = language.and_then(|language| cfg.fonts.get(language)) {
+        preamble.push_str(&format!("\\setmainfont{{{}}}\n", font));
+    }
+    if let Some(line_breaking) = scripts::line_breaking(language, engine) {
+        preamble.push_str(&line_breaking);
+    }
+    if cfg.vertical {
+        preamble.push_str(vertical::preamble());
+    }
+    if cfg.thumb_tabs {
+        preamble.push_str(&thumbtabs::preamble(top_level_chapters));
+    }
+    if let Some(profile) = cfg.profile {
+        preamble.push_str(&profile::preamble(profile));
+    }
+    if state.packages.contains("listings") {
+        preamble.push_str(highlighting::LISTINGS_PREAMBLE);
+    }
+    if state.highlighted_lines && cfg.highlighting != Some(Highlighting::Minted) {
+        preamble.push_str(highlighting::HIGHLIGHT_LINES_PREAMBLE);
+    }
+    preamble.push_str(&longlines::preamble(&cfg.long_lines, cfg.highlighting));
+    if state.ansi_colors {
+        preamble.push_str(&ansi::preamble());
+    }
+    if let Some(hypersetup) = links::hypersetup(cfg) {
+        preamble.push_str(&hypersetup);
+    }
+    if let (Some(pagestyle), true) = (cfg.frontmatter_pagestyle.as_deref(), matter_switched) {
+        preamble.push_str(&templates::matter_preamble(pagestyle));
+    }
+    if cfg.half_title || cfg.series_page {
+        preamble.push_str(series::PAGES_PREAMBLE);
+    }
+    if state.code_captions {
+        preamble.push_str(&highlighting::caption_names(&state.labels.listing));
+    }
+    match version {
+        Some(ref version) if cfg.version_footer => preamble.push_str(&version::footer(version)),
+        None if cfg.version_footer => log::warn!("No version to print, set `version`"),
+        _ => {}
+    }
+    // Last, as bidi has to be loaded after every other package.
+    preamble.push_str(features.bidi(engine));
+    if !preamble.is_empty() {
+        let marker = templates::find(&template, templates::PREAMBLE);
+        if let Some(pos) = marker.or_else(|| template.find(r"\begin{document}")) {
+            template.insert_str(pos, &preamble);
+        } else {
+            log::warn!(
+                "Missing `\\begin{{document}}` in tex template, skipping preamble additions"
+            );
+        }
+    }
+
+    let report = ctx.destination.join(match edition {
+        Some(edition) => format!("report-{}.md", edition),
+        None => "report.md".to_owned(),
+    });
+    if !state.report.is_empty() {
+        state.report.write(&report, &state.labels)?;
+    }
+    manifest.artifact(report.strip_prefix(&ctx.destination).unwrap_or(&report));
+    if cfg.asset_manifest {
+        let assets = match edition {
+            Some(edition) => format!("assets-{}.json", edition),
+            None => "assets.json".to_owned(),
+        };
+        state.assets.write(&ctx.destination.join(&assets))?;
+        manifest.artifact(assets);
+    }
+    if cfg.spellcheck.strict && state.report.count(spelling::SECTION) > 0 {
+        bail!("Misspelled words found, see {}", report.display());
+    }
+    if cfg.todo.strict && !cfg.draft && state.report.count(todo::SECTION) > 0 {
+        bail!(
+            "Leftover markers found in a release build, see {}",
+            report.display()
+        );
+    }
+    if cfg.licenses.strict && state.report.count(licenses::SECTION) > 0 {
+        bail!("Images without license found, see {}", report.display());
     }
 
     // println!("{}", content);
-    if cfg.markdown {
+    if cfg.markdown || cfg.attach_markdown {
         // Output markdown file.
-        output_markdown(".md", title, &content, &ctx.destination)?;
+        let markdown = output_markdown(
+            ".md",
+            &name,
+            &state.raw.restore(&format!(
+                "{}{}",
+                abstract_markdown.as_deref().unwrap_or_default(),
+                content.concat()
+            )),
+            &ctx.destination,
+        )?;
+        if cfg.attach_markdown {
+            attached.push(std::env::current_dir()?.join(&markdown));
+        }
+        manifest.artifact(markdown);
     }
 
     if cfg.latex || cfg.pdf {
+        let template_start = std::time::Instant::now();
         // convert markdown data to LaTeX
-        latex.push_str(&markdown_to_tex(content)?);
-
-        // Insert new LaTeX data into template after "%% mdbook-tectonic begin".
-        const BEGIN: &str = "mdbook-tectonic begin";
-        let pos = if let Some(pos) = template.find(&BEGIN) {
-            pos
-        } else {
-            bail!("Missing injection point `%% {}` in tex template", BEGIN);
-        } + BEGIN.len();
+        let mut latex = templates::Content::default();
+        for (marker, markdown) in content.into_regions() {
+            latex
+                .at(&marker)
+                .push_str(&state.raw.restore(&markdown_to_tex(markdown)?));
+        }
+        let abstract_tex = match abstract_markdown {
+            Some(ref markdown) => state.raw.restore(&markdown_to_tex(markdown.clone())?),
+            None => String::new(),
+        };
+        template = template.replace(
+            r"\newcommand{\mdbookabstract}{}",
+            &format!(
+                "\\newcommand{{\\mdbookabstract}}{{{}}}",
+                abstract_tex.trim()
+            ),
+        );
+        if empty {
+            // A document without a single page fails to typeset.
+            latex.at(templates::BEGIN).push_str("\n\\mbox{}\n");
+        }
+        if !licenses.is_empty() {
+            latex
+                .at(backmatter)
+                .push_str(&licenses::appendix(&licenses, &state.labels.image_credits));
+        }
+        if !attached.is_empty() {
+            latex.at(backmatter).push_str(&attachments::appendix(
+                &attached,
+                &ctx.root,
+                &state.labels.attachments,
+            ));
+        }
 
-        template.insert_str(pos, &latex);
+        // Insert new LaTeX data into template after the markers, like "%% mdbook-tectonic begin".
+        let document = latex.assemble(&template)?;
+        manifest.phase("convert", start);
+        state.timings.book_phase("template", template_start);
 
-        if cfg.latex {
-            // Output latex file.
-            output_markdown(".tex", title, &template, &ctx.destination)?;
+        if let (true, Some(baseline)) = (
+            flags.check_tex || flags.update_tex,
+            cfg.tex_baseline.as_ref(),
+        ) {
+            let baseline = snapshot::baseline(&ctx.root.join(baseline), edition);
+            return snapshot::check(&baseline, &document.to_string(), flags.update_tex);
         }
 
+        // Output latex file, the PDF is built from it. Written piece by piece,
+        // and dropped before the engine runs, which needs the memory more.
+        let tex = output_path(".tex", &name, &ctx.destination)?;
+        log::debug!("Writing {} of LaTeX", size::format_size(document.len()));
+        lock::write_atomic_with(&tex, |out| document.write_to(out))?;
+        drop(document);
+        manifest.artifact(&tex);
+
         // Output PDF file.
         if cfg.pdf {
-            // let mut input = tempfile::NamedTempFile::new()?;
-            // input.write(template.as_bytes())?;
-
-            // Write PDF with tectonic.
-            let cwd = std::env::current_dir()?;
-            println!("Writing PDF to {} with Tectonic...", cwd.display());
-            // FIXME launch tectonic process
-            let tectonic = which::which("tectonic")?;
-            let mut child = std::process::Command::new(tectonic)
-                .arg("--outfmt=pdf")
-                .arg(format!("-o={}", cwd.display()))
-                .arg("-")
-                .stdin(std::process::Stdio::piped())
-                .spawn()?;
-            {
-                let mut tectonic_stdin = child.stdin.as_mut().unwrap();
-                let mut tectonic_stdin = std::io::BufWriter::new(&mut tectonic_stdin);
-                tectonic_stdin.write(template.as_bytes())?;
+            let start = std::time::Instant::now();
+            typesetter.run(&tex)?;
+            manifest.phase("typeset", start);
+            state.timings.book_phase("compile", start);
+            let pdf = tex.with_extension("pdf");
+            manifest.artifact(&pdf);
+            manifest.artifact(tex.with_extension("log"));
+
+            let start = std::time::Instant::now();
+
+            // Checks of the PDF, their findings are added to the report.
+            let mut findings = 0;
+            if cfg.check_outline {
+                let outline = outline::validate(&pdf, &ctx.book, cfg)?;
+                findings += outline.len();
+                state.report.add(outline::SECTION, &name, outline);
+            }
+            if let Some(ref references) = cfg.visual.references {
+                let changes = visual::compare(
+                    &pdf,
+                    &ctx.destination.join("pages").join(&name),
+                    &snapshot::baseline(&ctx.root.join(references), edition),
+                    &cfg.visual,
+                    flags.update_pages,
+                )?;
+                findings += changes.len();
+                state.report.add(visual::SECTION, &name, changes);
+            }
+            if cfg.page_map || cfg.split_chapters {
+                match pages::map(&pdf, &ctx.book, cfg)? {
+                    Some(ranges) => {
+                        if cfg.page_map {
+                            let map = match edition {
+                                Some(edition) => format!("pages-{}.json", edition),
+                                None => "pages.json".to_owned(),
+                            };
+                            pages::write(&ctx.destination.join(&map), &ranges)?;
+                            manifest.artifact(map);
+                        }
+                        if cfg.split_chapters {
+                            let chapters = Path::new("chapters").join(&name);
+                            pages::split(&pdf, &ranges, &ctx.destination.join(&chapters))?;
+                            manifest.artifact(chapters);
+                        }
+                    }
+                    None => log::warn!(
+                        "The page map and splitting require `qpdf` to be installed, skipping"
+                    ),
+                }
             }
-            if let Some(retval) = child.wait()?.code() {
-                if retval != 0 {
-                    bail!("Subprocess `tectonic` terminated with exit code {}", retval)
+            manifest.phase("checks", start);
+            if let Some(imposition) = cfg.imposition {
+                let start = std::time::Instant::now();
+                let imposed = output_markdown(
+                    ".tex",
+                    &format!("{}-{}", name, imposition::suffix(imposition)),
+                    &imposition::document(&pdf, imposition),
+                    &ctx.destination,
+                )?;
+                typeset::Typesetter {
+                    filter_log: typesetter.filter_log,
+                    retry: typesetter.retry.clone(),
+                    ..typeset::Typesetter::new(engine)
+                }
+                .run(&imposed)?;
+                manifest.phase("imposition", start);
+                manifest.artifact(imposed.with_extension("pdf"));
+                if !cfg.latex {
+                    fs::remove_file(&imposed)?;
                 }
-            } else {
-                bail!("Failed to launch subprocess `tectonic`")
+                manifest.artifact(imposed);
+            }
+            if findings > 0 {
+                state.report.write(&report, &state.labels)?;
+            }
+            if cfg.visual.strict && state.report.count(visual::SECTION) > 0 {
+                bail!("Pages changed visually, see {}", report.display());
             }
         }
+        if cfg.timing {
+            let timings = state.timings.book();
+            log::info!("Timing of {}: {}", name, timings);
+            state.report.add(timing::SECTION, &name, vec![timings]);
+            state.report.write(&report, &state.labels)?;
+        }
+        if !cfg.latex {
+            fs::remove_file(&tex)?;
+        }
+    }
+
+    if cfg.slides.enable {
+        let start = std::time::Instant::now();
+        let slides = output_markdown(
+            ".tex",
+            &format!("{}-slides", name),
+            &slides::document(&ctx.book, cfg, &title_page, &authors)?,
+            &ctx.destination,
+        )?;
+        if cfg.pdf {
+            typeset::Typesetter {
+                filter_log: typesetter.filter_log,
+                retry: typesetter.retry.clone(),
+                ..typeset::Typesetter::new(engine)
+            }
+            .run(&slides)?;
+            manifest.artifact(slides.with_extension("pdf"));
+        }
+        if !cfg.latex {
+            fs::remove_file(&slides)?;
+        }
+        manifest.artifact(slides);
+        manifest.phase("slides", start);
+    }
+
+    if cfg.build_manifest {
+        manifest.write(
+            &ctx.destination,
+            &ctx.destination.join(match edition {
+                Some(edition) => format!("build-{}.json", edition),
+                None => "build.json".to_owned(),
+            }),
+        )?;
     }
 
     Ok(())
 }
 
-/// Output plain text file.
+/// Output plain text file, returns its path.
 ///
 /// Used for writing markdown and latex data to files.
 fn output_markdown<P: AsRef<Path>>(
@@ -215,7 +1441,18 @@ fn output_markdown<P: AsRef<Path>>(
     filename: &str,
     data: &str,
     destination: P,
-) -> Result<(), io::Error> {
+) -> Result<PathBuf, io::Error> {
+    let path = output_path(extension, filename, destination)?;
+    lock::write_atomic(&path, data.as_bytes())?;
+    Ok(path)
+}
+
+/// Path of the output `filename` with `extension`, creating its directory.
+fn output_path<P: AsRef<Path>>(
+    extension: &str,
+    filename: &str,
+    destination: P,
+) -> Result<PathBuf, io::Error> {
     // the title might contain a lot of stuff, so limit it to sane chars
     let re = regex::Regex::new("[^A-Za-z0-9_-]").expect("Parses just fine. qed");
     let filename = str::replace(filename, move |c: char| re.is_match(&c.to_string()), "");
@@ -225,28 +1462,117 @@ fn output_markdown<P: AsRef<Path>>(
 
     // Create output directory/file.
     fs::create_dir_all(destination)?;
-
-    let mut file = OpenOptions::new()
-        .create(true)
-        .truncate(true)
-        .write(true)
-        .open(&path)?;
-    file.write_all(data.as_bytes())?;
-    Ok(())
+    Ok(path)
 }
 
 /// This Function parses the markdown file, alters some elements and writes it back to markdown.
 ///
 /// Changes done:
-///   * change image paths to be relative to images
-///   * copy the image files into the images directory in the target directory
+///   * report large chapters, and cut those beyond the size limit
+///   * scan for leftover `TODO` markers
+///   * scan for code lines too long for the page
+///   * resolve images, with placeholders for those not found
+///   * change image paths to be relative to the build directory
+///   * copy the image files into the image directory in the build directory
+///   * label headings with the ids mdbook gives them
+///   * turn links to other chapters into references within the PDF
+///   * turn unnumbered headings into starred sections with bookmarks
+///   * box GitHub alerts, `> [!NOTE]`, and `mdbook-admonish` blocks
+///   * turn definition lists into `description` environments
+///   * turn tables into `tabular` environments, aligning the columns,
+///     long ones into `longtable`
+///   * convert HTML tables, cells spanning columns and rows included
+///   * translate hard (and optionally soft) line breaks
+///   * strike through `~~text~~`
+///   * print task list markers as checkboxes
+///   * strip HTML comments, except for directives to this backend
+///   * render `<details>` as framed boxes
+///   * replace embedded media with placeholders
+///   * annotate `<ruby>` base texts with their readings
+///   * set short numbers upright in vertical writing
+///   * record dropped raw HTML
+///   * place full page and spread images
+///   * turn images alone in their paragraph into figures
+///   * check that Rust examples compile
+///   * strip the lines of Rust examples mdbook hides
+///   * color terminal output as ANSI escape sequences say
+///   * link included listings to their source
+///   * attach output blocks to their listing
+///   * highlight code blocks with `minted` or `listings`, caption them and
+///     highlight lines
+///   * style the first use of glossary terms
+///   * turn footnotes into `\footnote`
+///   * check the spelling and measure readability
+///   * drop content meant for other renderers
+///   * flatten tabbed content
+///   * turn quizzes into printable questions
+///   * resolve page references
+///   * lay out `::: columns` side by side
+///   * pass math through as written
+///   * convert large chapters piece by piece
 fn traverse_markdown(
     content: &str,
     chapter_path: &Path,
-    context: &RenderContext,
-) -> std::io::Result<String> {
-    let parser = Parser::new_ext(content, Options::all());
+    numbered: bool,
+    state: &mut Traversal,
+) -> color_eyre::Result<String> {
+    let start = std::time::Instant::now();
+    state.anchors = Default::default();
+    let content = encoding::clean(content, state);
+    let content = size::guard(&content, state);
+    todo::scan(&content, state)?;
+    longlines::scan(&content, state);
+    let content = comments::conditionals(&content)?;
+    let content = shortcodes::tabs(&content, state)?;
+    let content = quiz::quizzes(&content, state)?;
+    let content = shortcodes::pagerefs(&content, state)?;
+    let content = shortcodes::unescape(&content)?;
+    let content = columns::columns(&content, state)?;
+    let content = math::math(&content, state)?;
+    state.timings.chapter_phase("parse", start);
+    let mut new_content = String::with_capacity(content.len());
+    // The checks only reporting see the chapter as a whole, not its pieces.
+    if state.cfg.spellcheck.enable || state.cfg.readability {
+        let events = Parser::new_ext(&content, Options::all()).collect::<Vec<_>>();
+        spelling::check(&events, state)?;
+        metrics::measure(&events, state)?;
+    }
+    for piece in size::pieces(&content, state.cfg.chapter_size.piece) {
+        if !new_content.is_empty() {
+            new_content.push_str("\n\n");
+        }
+        traverse_events(&piece, chapter_path, numbered, state, &mut new_content)?;
+    }
+    let timings = state.timings.finish_chapter();
+    if state.cfg.timing {
+        let chapter = state
+            .chapter
+            .map(|chapter| chapter.name.as_str())
+            .unwrap_or_default();
+        state.report.add(timing::SECTION, chapter, vec![timings]);
+    }
+    Ok(new_content)
+}
+
+/// The events of `content`, a chapter or a piece of it, converted by the
+/// passes listed for `traverse_markdown`, appended to `out` as markdown.
+///
+/// The events borrow from `content` wherever the passes leave them be, the
+/// markdown is the only copy made, as `cmark2tex` takes nothing else.
+fn traverse_events(
+    content: &str,
+    chapter_path: &Path,
+    numbered: bool,
+    state: &mut Traversal,
+    out: &mut String,
+) -> color_eyre::Result<()> {
+    let start = std::time::Instant::now();
+    let parser = Parser::new_ext(content, Options::all()).collect();
+    state.timings.chapter_phase("parse", start);
+    let start = std::time::Instant::now();
+    let parser = images::resolve_all(parser, chapter_path, state)?;
     let parser = parser
+        .into_iter()
         .map(|event| {
             Ok(match event {
                 Event::Start(Tag::Image(link_type, path, title)) => {
@@ -256,7 +1582,7 @@ fn traverse_markdown(
                         path,
                         title,
                         chapter_path,
-                        context,
+                        state,
                     )?)
                 }
                 Event::End(Tag::Image(link_type, path, title)) => {
@@ -266,18 +1592,53 @@ fn traverse_markdown(
                         path,
                         title,
                         chapter_path,
-                        context,
+                        state,
                     )?)
                 }
                 _ => event,
             })
         })
         .collect::<std::io::Result<Vec<Event>>>()?;
-    let mut new_content = String::new();
-
-    pulldown_cmark_to_cmark::cmark(parser.into_iter(), &mut new_content)
+    state.timings.chapter_phase("images", start);
+    let start = std::time::Instant::now();
+    let parser = anchors::label_headings(parser, state);
+    let parser = links::resolve(parser, state);
+    let parser = sections::unnumbered_headings(parser, numbered, state)?;
+    let parser = admonitions::alerts(parser, state);
+    let parser = admonitions::admonish(parser, state)?;
+    let parser = definitions::definition_lists(parser, state);
+    let parser = tables::tables(parser, state);
+    let parser = tables::html_tables(parser, state)?;
+    let parser = inline::line_breaks(parser, state);
+    let parser = inline::strikethrough(parser, state);
+    let parser = inline::task_lists(parser, state);
+    let parser = comments::html_comments(parser, state)?;
+    let parser = html::details(parser, state)?;
+    let parser = html::media(parser, state)?;
+    let parser = html::ruby(parser, state)?;
+    let parser = vertical::numbers(parser, state)?;
+    let parser = images::placement(parser, state)?;
+    let parser = images::figures(parser, state)?;
+    let parser = code::check_examples(parser, state)?;
+    let parser = code::hidden_lines(parser, state);
+    let parser = ansi::console(parser, state);
+    let parser = code::source_links(parser, state)?;
+    let parser = code::output_blocks(parser, state);
+    let parser = highlighting::highlight(parser, state);
+    let parser = glossary::first_use(parser, state)?;
+    let parser = inline::footnotes(parser, state)?;
+    // Html without a print equivalent, `cmark2tex` drops it.
+    for event in &parser {
+        if matches!(event, Event::Html(html) if !html.trim().is_empty()) {
+            state
+                .capabilities
+                .record("Raw HTML", capabilities::Outcome::Dropped);
+        }
+    }
+    pulldown_cmark_to_cmark::cmark(parser.into_iter(), out)
         .expect("Event mod is minimal, must work. qed");
-    Ok(new_content)
+    state.timings.chapter_phase("convert", start);
+    Ok(())
 }
 
 /// Take the values of a Tag::Image and create a new Tag::Image
@@ -287,13 +1648,22 @@ fn parse_image_tag<'a>(
     path: CowStr<'a>,
     title: CowStr<'a>,
     chapter_path: &'a Path,
-    context: &'a RenderContext,
+    state: &mut Traversal,
 ) -> std::io::Result<Tag<'a>> {
-    // cleaning and converting the path found.
-    let imagefn = dbg!(path.as_ref().strip_prefix("./").unwrap_or(path.as_ref()));
-    let source = dbg!(&context.config.book.src).join(imagefn);
-    let sourceimage = dbg!(&context.root).join(&source);
-    let targetimage = dbg!(&context.destination).join(&source);
+    let context = state.context;
+    let source = images::source(&path, chapter_path);
+    let chapter = state
+        .chapter
+        .map(|chapter| chapter.name.as_str())
+        .unwrap_or_default();
+    if let Some(target) = state.assets.get(&source, chapter) {
+        return Ok(Tag::Image(link_type, paths::to_tex(target).into(), title));
+    }
+    let sourceimage = context.root.join(&context.config.book.src).join(&source);
+    let target = state
+        .assets
+        .insert(source, &fs::read(&sourceimage)?, chapter, state.cfg);
+    let targetimage = context.destination.join(&target);
 
     if sourceimage != targetimage {
         log::debug!(
@@ -301,9 +1671,9 @@ fn parse_image_tag<'a>(
             sourceimage.display(),
             targetimage.display()
         );
-        fs::create_dir_all(dbg!(targetimage.parent().unwrap()))?;
+        fs::create_dir_all(targetimage.parent().expect("Target is a file. qed"))?;
         fs::copy(&sourceimage, &targetimage)?;
     }
     // create the new image
-    Ok(Tag::Image(link_type, imagefn.to_owned().into(), title))
+    Ok(Tag::Image(link_type, paths::to_tex(&target).into(), title))
 }
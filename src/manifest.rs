@@ -0,0 +1,91 @@
+//! Build manifest, `build.json`, describing the files written, for release
+//! pipelines to pick them up without guessing their names.
+
+use crate::{images, Engine, Profile};
+use fs_err as fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// A file written by the build.
+#[derive(Debug, serde::Serialize)]
+struct Artifact {
+    // Relative to the build directory.
+    path: PathBuf,
+    size: u64,
+    // 64 bit FNV-1a of the content, in hex, as in `assets.json`.
+    hash: String,
+}
+
+/// Duration of one phase of the build.
+#[derive(Debug, serde::Serialize)]
+struct Phase {
+    name: &'static str,
+    seconds: f64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct Manifest {
+    // Language of the edition, `None` for the book itself.
+    edition: Option<String>,
+    profile: Option<Profile>,
+    engine: Engine,
+    artifacts: Vec<Artifact>,
+    phases: Vec<Phase>,
+    // Files recorded, described when writing, as some are removed again.
+    #[serde(skip)]
+    written: Vec<PathBuf>,
+}
+
+impl Manifest {
+    pub(crate) fn new(edition: Option<&str>, profile: Option<Profile>, engine: Engine) -> Self {
+        Self {
+            edition: edition.map(str::to_owned),
+            profile,
+            engine,
+            artifacts: Vec::new(),
+            phases: Vec::new(),
+            written: Vec::new(),
+        }
+    }
+
+    /// Record the file at `path`, relative to the build directory.
+    pub(crate) fn artifact(&mut self, path: impl Into<PathBuf>) {
+        self.written.push(path.into());
+    }
+
+    /// Record the phase `name` as ending now.
+    pub(crate) fn phase(&mut self, name: &'static str, start: Instant) {
+        self.phases.push(Phase {
+            name,
+            seconds: start.elapsed().as_secs_f64(),
+        });
+    }
+
+    /// Describe the recorded files still present below `destination`, and
+    /// write the manifest as JSON to `path`.
+    pub(crate) fn write(mut self, destination: &Path, path: &Path) -> color_eyre::Result<()> {
+        for written in std::mem::take(&mut self.written) {
+            let full = destination.join(&written);
+            let mut files = Vec::new();
+            if full.is_dir() {
+                for entry in fs::read_dir(&full)? {
+                    files.push(written.join(entry?.file_name()));
+                }
+                files.sort();
+            } else if full.is_file() {
+                files.push(written);
+            }
+            for file in files {
+                let data = fs::read(destination.join(&file))?;
+                self.artifacts.push(Artifact {
+                    path: file,
+                    size: data.len() as u64,
+                    hash: format!("{:016x}", images::hash(&data)),
+                });
+            }
+        }
+        fs::write(path, serde_json::to_string_pretty(&self)?)?;
+        log::info!("Build manifest written to {}", path.display());
+        Ok(())
+    }
+}
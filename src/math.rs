@@ -0,0 +1,109 @@
+//! Math, `$...$` inline and `$$...$$` on display, passed through to LaTeX
+//! as written. With `katex-delimiters` set, so is math delimited like
+//! `mdbook-katex` does it, `\(...\)` inline and `\[...\]` on display.
+//!
+//! Handled on the markdown source before it is parsed, as parsing would
+//! take backslashes for escapes and underscores for emphasis. Dollars in
+//! code and escaped ones, `\$`, are left alone, as are those which do not
+//! delimit math in the sense of pandoc, like in "from $5 to $10".
+
+use crate::capabilities::Outcome;
+use crate::{LatexConfig, Traversal};
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use regex::Regex;
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// Byte ranges of code blocks and spans, verbatim to the math, the
+/// shortcodes and the other passes on the source as well.
+pub(crate) fn code_ranges(content: &str) -> Vec<Range<usize>> {
+    Parser::new_ext(content, Options::all())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Code(_) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Byte ranges of the math in `content` and its LaTeX, as far as `math`
+/// and `katex-delimiters` are set.
+fn spans(content: &str, cfg: &LatexConfig) -> color_eyre::Result<Vec<(Range<usize>, String)>> {
+    let mut alternatives = Vec::new();
+    if cfg.math {
+        alternatives.push(r"\$\$(?P<display>.+?)\$\$|\$(?P<inline>[^$\n]+?)\$");
+    }
+    if cfg.katex_delimiters {
+        alternatives.push(r"\\\[(?P<katex_display>.+?)\\\]|\\\((?P<katex_inline>.+?)\\\)");
+    }
+    if alternatives.is_empty() {
+        return Ok(Vec::new());
+    }
+    let re = Regex::new(&format!("(?s){}", alternatives.join("|")))?;
+    let code = code_ranges(content);
+    let mut spans = Vec::new();
+    for caps in re.captures_iter(content) {
+        let whole = caps.get(0).expect("Group 0 is the whole match. qed");
+        if content[..whole.start()].ends_with('\\')
+            || code
+                .iter()
+                .any(|range| range.start < whole.end() && whole.start() < range.end)
+        {
+            continue;
+        }
+        let tex = match (caps.name("display"), caps.name("inline")) {
+            (Some(display), _) => format!("\\[{}\\]", display.as_str()),
+            _ if caps.name("katex_display").is_some() || caps.name("katex_inline").is_some() => {
+                whole.as_str().to_owned()
+            }
+            (_, Some(inline)) => {
+                // No space inside the dollars and no digit right after
+                // them, as in amounts of money.
+                let inline = inline.as_str();
+                let digit_follows = content[whole.end()..]
+                    .chars()
+                    .next()
+                    .map_or(false, |c| c.is_ascii_digit());
+                if inline.starts_with(char::is_whitespace)
+                    || inline.ends_with(char::is_whitespace)
+                    || digit_follows
+                {
+                    continue;
+                }
+                format!("${}$", inline)
+            }
+            _ => unreachable!("One of the alternatives matched. qed"),
+        };
+        spans.push((whole.range(), tex));
+    }
+    Ok(spans)
+}
+
+/// Whether math in `content` has symbols beyond ASCII, `$α ≤ β$`, which
+/// take `unicode-math` to typeset.
+pub(crate) fn has_unicode(content: &str, cfg: &LatexConfig) -> color_eyre::Result<bool> {
+    Ok(spans(content, cfg)?.iter().any(|(_, tex)| !tex.is_ascii()))
+}
+
+/// Replace math with raw tokens of its LaTeX, if `math` or
+/// `katex-delimiters` is set.
+pub(crate) fn math<'t>(
+    content: &'t str,
+    state: &mut Traversal,
+) -> color_eyre::Result<Cow<'t, str>> {
+    let spans = spans(content, state.cfg)?;
+    if spans.is_empty() {
+        return Ok(Cow::Borrowed(content));
+    }
+    let mut out = String::new();
+    let mut last = 0;
+    for (range, tex) in spans {
+        state.capabilities.record("Math", Outcome::Rendered);
+        state.packages.insert("amsmath");
+        out.push_str(&content[last..range.start]);
+        out.push_str(&state.raw.token(tex));
+        last = range.end;
+    }
+    out.push_str(&content[last..]);
+    Ok(Cow::Owned(out))
+}
@@ -0,0 +1,84 @@
+//! Readability and style metrics per chapter.
+//!
+//! The scores are the English Flesch formulas, for other languages they
+//! are only good for comparing chapters with each other.
+
+use crate::report::prose;
+use crate::Traversal;
+use pulldown_cmark::Event;
+
+/// Report section of the metrics.
+pub(crate) const SECTION: &str = "readability";
+
+/// Syllables of a word, counted as groups of vowels.
+fn syllables(word: &str) -> usize {
+    let word = word.to_lowercase();
+    let mut count = 0;
+    let mut previous_vowel = false;
+    for c in word.chars() {
+        let vowel = "aeiouyäöüéèàáíóú".contains(c);
+        if vowel && !previous_vowel {
+            count += 1;
+        }
+        previous_vowel = vowel;
+    }
+    if word.ends_with('e') && !word.ends_with("le") && count > 1 {
+        count -= 1;
+    }
+    count.max(1)
+}
+
+/// Add readability metrics of a chapter to the report.
+pub(crate) fn measure(events: &[Event], state: &mut Traversal) -> color_eyre::Result<()> {
+    if !state.cfg.readability {
+        return Ok(());
+    }
+    let text = prose(events, &state.raw);
+    let sentences = regex::Regex::new(r"[.!?]+(?:\s|$)")?
+        .split(&text)
+        .filter(|sentence| sentence.chars().any(char::is_alphabetic))
+        .collect::<Vec<_>>();
+    let words = text
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| word.chars().any(char::is_alphabetic))
+        .collect::<Vec<_>>();
+    if sentences.is_empty() || words.is_empty() {
+        return Ok(());
+    }
+    let passive =
+        regex::Regex::new(r"(?i)\b(?:am|is|are|was|were|be|been|being)\s+\w+(?:ed|en)\b")?;
+    let passive = sentences
+        .iter()
+        .filter(|sentence| passive.is_match(sentence))
+        .count();
+
+    let syllables = words.iter().map(|word| syllables(word)).sum::<usize>() as f64;
+    let words_per_sentence = words.len() as f64 / sentences.len() as f64;
+    let syllables_per_word = syllables / words.len() as f64;
+    let reading_ease = 206.835 - 1.015 * words_per_sentence - 84.6 * syllables_per_word;
+    let grade = 0.39 * words_per_sentence + 11.8 * syllables_per_word - 15.59;
+
+    let labels = &state.labels;
+    let findings = vec![
+        format!("{}: {}", labels.words, words.len()),
+        format!("{}: {}", labels.sentences, sentences.len()),
+        format!(
+            "{}: {:.1}",
+            labels.average_sentence_length, words_per_sentence
+        ),
+        format!("{}: {:.1}", labels.reading_ease, reading_ease),
+        format!("{}: {:.1}", labels.grade_level, grade),
+        format!(
+            "{}: {}, {:.0}%",
+            labels.passive_voice,
+            passive,
+            100. * passive as f64 / sentences.len() as f64
+        ),
+    ];
+    let chapter = state
+        .chapter
+        .map(|chapter| chapter.name.as_str())
+        .unwrap_or_default();
+    state.report.add(SECTION, chapter, findings);
+    Ok(())
+}
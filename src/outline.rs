@@ -0,0 +1,164 @@
+//! Validation of the PDF outline against the headings of the book.
+//!
+//! Bookmarks missing or duplicated in the outline are usually a sign of
+//! escaping or labeling gone wrong. The outline is read with `qpdf`.
+
+use crate::{comments, LatexConfig};
+use mdbook::book::{Book, BookItem};
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+/// Report section of the validation.
+pub(crate) const SECTION: &str = "outline";
+
+/// Deepest heading level with a bookmark, hyperref follows the `tocdepth`
+/// of the article class.
+const DEPTH: usize = 3;
+
+/// The outline and the pages of a PDF, as dumped by `qpdf --json`.
+#[derive(Debug, serde::Deserialize)]
+pub(crate) struct Document {
+    pub(crate) outlines: Vec<Item>,
+    #[serde(default)]
+    pub(crate) pages: Vec<serde::de::IgnoredAny>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub(crate) struct Item {
+    pub(crate) title: String,
+    #[serde(default)]
+    pub(crate) kids: Vec<Item>,
+    // Page the bookmark points to, counting from one.
+    #[serde(default, rename = "destpageposfrom1")]
+    pub(crate) page: Option<usize>,
+}
+
+/// Collapse whitespace, titles are compared as typeset.
+pub(crate) fn normalize(title: &str) -> String {
+    title.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Headings of a chapter up to `DEPTH` with their depth, in order.
+pub(crate) fn headings(content: &str) -> color_eyre::Result<Vec<(usize, String)>> {
+    let content = comments::conditionals(content)?;
+    let mut headings = Vec::new();
+    let mut heading: Option<(usize, String)> = None;
+    for event in Parser::new_ext(&content, Options::all()) {
+        match event {
+            Event::Start(Tag::Heading(level, ..)) => {
+                let depth = match level {
+                    HeadingLevel::H1 => 1,
+                    HeadingLevel::H2 => 2,
+                    HeadingLevel::H3 => 3,
+                    _ => continue,
+                };
+                heading = Some((depth, String::new()));
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, ref mut title)) = heading {
+                    title.push_str(&text);
+                }
+            }
+            Event::End(Tag::Heading(..)) => {
+                if let Some((depth, title)) = heading.take() {
+                    headings.push((depth, normalize(&title)));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(headings)
+}
+
+/// Number of bookmarks per depth and title, expected from the headings of
+/// all chapters not ignored.
+fn expected(
+    book: &Book,
+    cfg: &LatexConfig,
+) -> color_eyre::Result<BTreeMap<(usize, String), usize>> {
+    let mut expected = BTreeMap::new();
+    for item in book.iter() {
+        let ch = match item {
+            BookItem::Chapter(ch) if !cfg.ignores.contains(&ch.name) => ch,
+            _ => continue,
+        };
+        for heading in headings(&ch.content)? {
+            *expected.entry(heading).or_default() += 1;
+        }
+    }
+    Ok(expected)
+}
+
+/// Read the outline and pages of `pdf`, `None` if `qpdf` is not installed.
+pub(crate) fn read(pdf: &Path) -> color_eyre::Result<Option<Document>> {
+    let qpdf = match which::which("qpdf") {
+        Ok(qpdf) => qpdf,
+        Err(_) => return Ok(None),
+    };
+    let output = Command::new(qpdf)
+        .arg("--json")
+        .arg("--json-key=outlines")
+        .arg("--json-key=pages")
+        .arg(pdf)
+        .output()?;
+    if !output.status.success() {
+        color_eyre::eyre::bail!("Subprocess `qpdf` failed with {}", output.status);
+    }
+    Ok(Some(serde_json::from_slice(&output.stdout)?))
+}
+
+fn collect(items: &[Item], depth: usize, found: &mut BTreeMap<(usize, String), usize>) {
+    for item in items {
+        *found.entry((depth, normalize(&item.title))).or_default() += 1;
+        if depth < DEPTH {
+            collect(&item.kids, depth + 1, found);
+        }
+    }
+}
+
+/// Compare the outline of `pdf` against the headings of `book`, returns the
+/// missing and duplicated bookmarks.
+pub(crate) fn validate(
+    pdf: &Path,
+    book: &Book,
+    cfg: &LatexConfig,
+) -> color_eyre::Result<Vec<String>> {
+    let outlines = match read(pdf)? {
+        Some(document) => document.outlines,
+        None => {
+            log::warn!("Validating the PDF outline requires `qpdf` to be installed, skipping");
+            return Ok(Vec::new());
+        }
+    };
+    compare(&outlines, book, cfg)
+}
+
+/// Compare `outlines` against the headings of `book`, returns the missing
+/// and duplicated bookmarks.
+pub(crate) fn compare(
+    outlines: &[Item],
+    book: &Book,
+    cfg: &LatexConfig,
+) -> color_eyre::Result<Vec<String>> {
+    let mut found = BTreeMap::new();
+    collect(outlines, 1, &mut found);
+
+    let mut findings = Vec::new();
+    for ((depth, title), count) in expected(book, cfg)? {
+        match found
+            .get(&(depth, title.clone()))
+            .copied()
+            .unwrap_or_default()
+        {
+            0 => findings.push(format!("missing bookmark at level {}: {}", depth, title)),
+            n if n > count => findings.push(format!(
+                "duplicated bookmark at level {}: {} ({} times, expected {})",
+                depth, title, n, count
+            )),
+            _ => {}
+        }
+    }
+    Ok(findings)
+}
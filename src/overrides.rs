@@ -0,0 +1,112 @@
+//! Overrides of config keys by named profiles and from the environment, so
+//! workflows and CI pipelines can vary the outputs without editing the book.
+//!
+//! Profiles are tables of options within `profiles`, merged over the others
+//! if selected:
+//!
+//! ```toml
+//! [output.latex.profiles.draft]
+//! draft = true
+//! pdf = false
+//! ```
+//!
+//! `MDBOOK_LATEX__PDF=false` sets `pdf`, nested keys are separated by
+//! double underscores, `MDBOOK_LATEX__TODO__STRICT=true` sets `todo.strict`.
+
+use toml::Value;
+
+const PREFIX: &str = "MDBOOK_LATEX__";
+
+/// Environment variable selecting a profile.
+pub(crate) const PROFILE_VAR: &str = "MDBOOK_LATEX_PROFILE";
+
+/// Merge `overrides` over `base`, tables key by key.
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Table(base), Value::Table(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Remove the profiles from `config` and merge the one named `profile`
+/// over the other options.
+pub(crate) fn select(config: &mut Value, profile: Option<&str>) -> color_eyre::Result<()> {
+    let mut profiles = match config
+        .as_table_mut()
+        .and_then(|table| table.remove("profiles"))
+    {
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => color_eyre::eyre::bail!("Expected a table of profiles"),
+        None => Default::default(),
+    };
+    if let Some(name) = profile {
+        match profiles.remove(name) {
+            Some(overrides) => {
+                log::info!("Building with profile `{}`", name);
+                merge(config, overrides);
+            }
+            None => color_eyre::eyre::bail!(
+                "Unknown profile `{}`, available are: {}",
+                name,
+                profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Key path of an environment variable, `None` if it has none.
+fn key(name: &str) -> Option<Vec<String>> {
+    let path = name.strip_prefix(PREFIX)?;
+    Some(
+        path.split("__")
+            .map(|key| key.to_lowercase().replace('_', "-"))
+            .collect(),
+    )
+}
+
+/// Parse `raw` as a TOML value, anything not valid TOML is taken as string.
+fn value(raw: &str) -> Value {
+    toml::from_str::<toml::value::Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_owned()))
+}
+
+/// Merge the overrides among `vars` over `config`, the `output.latex` table.
+pub(crate) fn apply(config: &mut Value, vars: impl Iterator<Item = (String, String)>) {
+    for (name, raw) in vars {
+        let path = match key(&name) {
+            Some(path) if !path.iter().any(String::is_empty) => path,
+            _ => continue,
+        };
+        log::info!("Overriding `{}` from the environment", path.join("."));
+        let mut table = &mut *config;
+        for key in &path[..path.len() - 1] {
+            if !table.is_table() {
+                *table = Value::Table(Default::default());
+            }
+            table = table
+                .as_table_mut()
+                .expect("Made a table. qed")
+                .entry(key.clone())
+                .or_insert_with(|| Value::Table(Default::default()));
+        }
+        if !table.is_table() {
+            *table = Value::Table(Default::default());
+        }
+        table
+            .as_table_mut()
+            .expect("Made a table. qed")
+            .insert(path[path.len() - 1].clone(), value(&raw));
+    }
+}
@@ -0,0 +1,130 @@
+//! Page ranges of the chapters within the PDF, for downstream tools to
+//! deep link into it or split it.
+//!
+//! A chapter starts at the bookmark of its first heading and ends right
+//! before the next chapter starts. Given the page ranges, the PDF is split
+//! into one PDF per chapter without typesetting it again.
+
+use crate::outline::{self, Document, Item};
+use crate::{images, LatexConfig};
+use fs_err as fs;
+use mdbook::book::{Book, BookItem};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Pages of one chapter, counting from one.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub(crate) struct Range {
+    pub(crate) chapter: String,
+    // Source of the chapter, relative to the source directory.
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) first: usize,
+    pub(crate) last: usize,
+}
+
+/// All bookmarks in document order.
+fn flatten<'i>(items: &'i [Item], flat: &mut Vec<&'i Item>) {
+    for item in items {
+        flat.push(item);
+        flatten(&item.kids, flat);
+    }
+}
+
+/// Map each chapter not ignored to its page range, `None` if `qpdf` is not
+/// installed. Chapters without a heading in the outline are left out.
+pub(crate) fn map(
+    pdf: &Path,
+    book: &Book,
+    cfg: &LatexConfig,
+) -> color_eyre::Result<Option<Vec<Range>>> {
+    match outline::read(pdf)? {
+        Some(document) => Ok(Some(ranges(&document, book, cfg)?)),
+        None => Ok(None),
+    }
+}
+
+/// Page ranges of the chapters not ignored, according to the outline and
+/// pages of `document`.
+pub(crate) fn ranges(
+    document: &Document,
+    book: &Book,
+    cfg: &LatexConfig,
+) -> color_eyre::Result<Vec<Range>> {
+    let mut bookmarks = Vec::new();
+    flatten(&document.outlines, &mut bookmarks);
+
+    // Bookmarks are in the order of the chapters, search on from the last one found.
+    let mut cursor = 0;
+    let mut ranges: Vec<Range> = Vec::new();
+    for item in book.iter() {
+        let ch = match item {
+            BookItem::Chapter(ch) if !cfg.ignores.contains(&ch.name) => ch,
+            _ => continue,
+        };
+        let title = match outline::headings(&ch.content)?.into_iter().next() {
+            Some((_, title)) => title,
+            None => continue,
+        };
+        let found = bookmarks[cursor..]
+            .iter()
+            .position(|bookmark| outline::normalize(&bookmark.title) == title);
+        let (idx, first) = match found.and_then(|idx| Some((idx, bookmarks[cursor + idx].page?))) {
+            Some(found) => found,
+            None => {
+                log::warn!("Chapter {} not found in the PDF outline", ch.name);
+                continue;
+            }
+        };
+        cursor += idx + 1;
+        if let Some(previous) = ranges.last_mut() {
+            previous.last = first.saturating_sub(1).max(previous.first);
+        }
+        ranges.push(Range {
+            chapter: ch.name.clone(),
+            path: ch.path.clone(),
+            first,
+            last: document.pages.len().max(first),
+        });
+    }
+    Ok(ranges)
+}
+
+/// Write the page ranges as JSON.
+pub(crate) fn write(path: &Path, ranges: &[Range]) -> color_eyre::Result<()> {
+    fs::write(path, serde_json::to_string_pretty(ranges)?)?;
+    log::info!("Page map written to {}", path.display());
+    Ok(())
+}
+
+/// File of the `idx`th chapter, counting from zero, within `dir`.
+pub(crate) fn part(dir: &Path, idx: usize, range: &Range) -> PathBuf {
+    dir.join(format!(
+        "{:02}-{}.pdf",
+        idx + 1,
+        images::sanitize(&range.chapter)
+    ))
+}
+
+/// Split `pdf` into one PDF per chapter in `dir`, numbered in order and
+/// named after the chapter. Page labels, i.e. the page numbers shown, are
+/// kept as in the book.
+pub(crate) fn split(pdf: &Path, ranges: &[Range], dir: &Path) -> color_eyre::Result<()> {
+    let qpdf = which::which("qpdf")?;
+    fs::create_dir_all(dir)?;
+    for (idx, range) in ranges.iter().enumerate() {
+        let part = part(dir, idx, range);
+        let status = Command::new(&qpdf)
+            .arg(pdf)
+            .arg("--pages")
+            .arg(".")
+            .arg(format!("{}-{}", range.first, range.last))
+            .arg("--")
+            .arg(&part)
+            .status()?;
+        if !status.success() {
+            color_eyre::eyre::bail!("Subprocess `qpdf` failed with {}", status);
+        }
+    }
+    log::info!("Split into {} chapters in {}", ranges.len(), dir.display());
+    Ok(())
+}
@@ -0,0 +1,42 @@
+//! Paths as LaTeX takes them, with forward slashes.
+//!
+//! TeX reads a backslash as the start of a command, so the paths of Windows
+//! have to be turned around, `\\?\` verbatim prefixes dropped and UNC shares,
+//! `\\server\share`, written as `//server/share`. Names which are no valid
+//! UTF-8 are converted lossily, they are copied under sanitized names where
+//! it matters, like for images.
+
+use std::path::{Component, Path, PathBuf};
+
+/// `path` with forward slashes, for the LaTeX or markdown output.
+pub(crate) fn to_tex(path: &Path) -> String {
+    normalize(&path.to_string_lossy(), cfg!(windows))
+}
+
+/// `path` with forward slashes, if it is a `windows` one.
+pub(crate) fn normalize(path: &str, windows: bool) -> String {
+    if !windows {
+        return path.to_owned();
+    }
+    let path = if let Some(share) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", share)
+    } else {
+        path.strip_prefix(r"\\?\").unwrap_or(path).to_owned()
+    };
+    path.replace('\\', "/")
+}
+
+/// Resolve `.` and `..` without touching the file system.
+pub(crate) fn resolve_dots(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
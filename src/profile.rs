@@ -0,0 +1,85 @@
+//! Typography presets, composed atop any template by amending its
+//! preamble.
+
+use crate::Profile;
+use std::process::Command;
+
+/// Fonts designed for readers with dyslexia, by preference.
+pub(crate) const DYSLEXIA_FONTS: &[&str] = &["OpenDyslexic", "Atkinson Hyperlegible"];
+
+/// Whether a font `family` is installed, according to fontconfig.
+pub(crate) fn installed(family: &str) -> bool {
+    Command::new("fc-list")
+        .arg(family)
+        .arg("family")
+        .output()
+        .map_or(false, |output| {
+            output.status.success() && !output.stdout.is_empty()
+        })
+}
+
+/// Preamble additions of `profile`.
+pub(crate) fn preamble(profile: Profile) -> String {
+    match profile {
+        // E-ink readers show a page at a time, small and within margins of
+        // their own, along with the progress. Lines are short, so rather
+        // stretch spaces than overflow.
+        Profile::Ereader => r"\usepackage{geometry}
+\geometry{paperwidth=90mm,paperheight=120mm,margin=0mm}
+\pagestyle{empty}
+\usepackage{anyfontsize}
+\makeatletter
+\renewcommand\normalsize{\@setfontsize\normalsize{12}{15}}
+\makeatother
+\AtBeginDocument{\normalsize}
+\tolerance=9999
+\emergencystretch=3em
+\hbadness=10000
+\lstset{basicstyle=\scriptsize\ttfamily,numbers=none,breaklines=true}
+"
+        .to_owned(),
+        // Accessibility guidelines ask for at least 14pt, nothing smaller than
+        // 12pt, generous leading and strong contrast, in code as well.
+        Profile::LargePrint => r"\usepackage{anyfontsize}
+\makeatletter
+\renewcommand\normalsize{\@setfontsize\normalsize{14}{21}}
+\renewcommand\small{\@setfontsize\small{13}{19}}
+\renewcommand\footnotesize{\@setfontsize\footnotesize{12}{18}}
+\renewcommand\scriptsize{\@setfontsize\scriptsize{12}{18}}
+\renewcommand\tiny{\@setfontsize\tiny{12}{18}}
+\renewcommand\large{\@setfontsize\large{17}{24}}
+\renewcommand\Large{\@setfontsize\Large{20}{28}}
+\renewcommand\LARGE{\@setfontsize\LARGE{24}{32}}
+\renewcommand\huge{\@setfontsize\huge{29}{38}}
+\makeatother
+\AtBeginDocument{\normalsize}
+\setlength{\arrayrulewidth}{1pt}
+\setlength{\fboxrule}{1.2pt}
+\AtBeginDocument{\ifdefined\heavyrulewidth\setlength{\heavyrulewidth}{2pt}\setlength{\lightrulewidth}{1.2pt}\fi}
+\lstset{basicstyle=\normalsize\ttfamily,framerule=1.2pt,rulecolor=\color{black},backgroundcolor=\color{white},commentstyle=\color{black}\itshape,keywordstyle=\color{blue!70!black}\bfseries,stringstyle=\color{red!70!black},numberstyle=\footnotesize\color{black}}
+"
+        .to_owned(),
+        // Extra spacing between letters and words and a ragged right margin,
+        // so word spacing stays even, are what help most besides the font.
+        Profile::Dyslexia => {
+            let mut tex = String::new();
+            match DYSLEXIA_FONTS.iter().find(|family| installed(family)) {
+                Some(font) => tex.push_str(&format!(
+                    "\\setmainfont{{{}}}[LetterSpace=4,WordSpace=1.4]\n",
+                    font
+                )),
+                None => log::warn!(
+                    "None of the fonts {} is installed, keeping the font and its spacing",
+                    DYSLEXIA_FONTS.join(", ")
+                ),
+            }
+            tex.push_str(
+                r"\usepackage{ragged2e}
+\AtBeginDocument{\RaggedRight}
+\linespread{1.3}
+",
+            );
+            tex
+        }
+    }
+}
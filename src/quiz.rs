@@ -0,0 +1,212 @@
+//! Printable degradation of `mdbook-quiz` quizzes.
+//!
+//! Quizzes are picked up either as `{{#quiz path/to/quiz.toml}}` shortcode,
+//! if the preprocessor is restricted to the HTML renderer, or from the
+//! placeholder element the preprocessor leaves behind, which carries the
+//! questions as JSON.
+
+use crate::capabilities::Outcome;
+use crate::html::attribute;
+use crate::raw::{escape, markdown_fragment_to_tex};
+use crate::shortcodes::replace_unescaped;
+use crate::{QuizAnswers, Traversal};
+use std::borrow::Cow;
+
+#[derive(Debug, serde::Deserialize)]
+struct Quiz {
+    questions: Vec<Question>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Question {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    prompt: Prompt,
+    #[serde(default)]
+    answer: Answer,
+    context: Option<String>,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Prompt {
+    prompt: Option<String>,
+    program: Option<String>,
+    distractors: Vec<String>,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Answer {
+    answer: Option<OneOrMany>,
+    does_compile: Option<bool>,
+    stdout: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn to_vec(&self) -> Vec<String> {
+        match self {
+            Self::One(one) => vec![one.clone()],
+            Self::Many(many) => many.clone(),
+        }
+    }
+}
+
+/// Questions printed so far, and the answer key if it goes to an appendix.
+#[derive(Debug, Default)]
+pub(crate) struct QuizState {
+    questions: usize,
+    answers: Vec<String>,
+}
+
+fn unescape_html(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Replace quizzes with printable question blocks.
+pub(crate) fn quizzes<'t>(
+    content: &'t str,
+    state: &mut Traversal,
+) -> color_eyre::Result<Cow<'t, str>> {
+    let shortcode = regex::Regex::new(r"\{\{#quiz\s+([^}]+?)\s*\}\}")?;
+    let content = replace_unescaped(content, &shortcode, |caps| {
+        let mut path = state.context.root.join(&state.context.config.book.src);
+        if let Some(chapter) = state.chapter {
+            if let Some(dir) = chapter
+                .source_path
+                .as_ref()
+                .or(chapter.path.as_ref())
+                .and_then(|path| path.parent())
+            {
+                path.push(dir);
+            }
+        }
+        path.push(&caps[1]);
+        let quiz: Quiz = toml::from_str(&crate::encoding::read_to_string(&path)?)?;
+        render(quiz, state)
+    })?;
+
+    let placeholder =
+        regex::Regex::new(r#"(?s)<div\s+([^>]*class="quiz-placeholder"[^>]*)>\s*</div>"#)?;
+    let rendered = replace_unescaped(&content, &placeholder, |caps| {
+        let questions = attribute(&caps[1], "data-quiz-questions").unwrap_or("{}");
+        let quiz: Quiz = serde_json::from_str(&unescape_html(questions))?;
+        render(quiz, state)
+    })?;
+    Ok(match rendered {
+        Cow::Owned(rendered) => Cow::Owned(rendered),
+        Cow::Borrowed(_) => content,
+    })
+}
+
+/// Render all questions of a quiz, returns the raw block token to put in
+/// its place.
+fn render(quiz: Quiz, state: &mut Traversal) -> color_eyre::Result<String> {
+    let mut tex = String::new();
+    for question in quiz.questions {
+        state.quiz.questions += 1;
+        let number = state.quiz.questions;
+        let labels = &state.labels;
+        tex.push_str(&format!(
+            "\\begin{{framed}}\n\\noindent\\textbf{{{} {}}}\\par\n",
+            escape(&labels.question),
+            number
+        ));
+        if let Some(ref prompt) = question.prompt.prompt {
+            tex.push_str(&markdown_fragment_to_tex(prompt)?);
+            tex.push_str("\\par\n");
+        }
+
+        let answers = question
+            .answer
+            .answer
+            .as_ref()
+            .map(OneOrMany::to_vec)
+            .unwrap_or_default();
+        let answer = match question.kind.as_str() {
+            "MultipleChoice" => {
+                // Sorted, so the position does not give the answer away.
+                let mut choices = answers.clone();
+                choices.extend(question.prompt.distractors.iter().cloned());
+                choices.sort();
+                tex.push_str("\\begin{enumerate}\\renewcommand{\\labelenumi}{(\\alph{enumi})}\n");
+                for choice in choices {
+                    tex.push_str(&format!("\\item {}\n", markdown_fragment_to_tex(&choice)?));
+                }
+                tex.push_str("\\end{enumerate}\n");
+                answers.join("; ")
+            }
+            "Tracing" => {
+                if let Some(ref program) = question.prompt.program {
+                    tex.push_str(&markdown_fragment_to_tex(&format!(
+                        "```rust\n{}\n```",
+                        program.trim_end()
+                    ))?);
+                    tex.push('\n');
+                }
+                tex.push_str(&format!("{}\\par\n", escape(&labels.tracing_prompt)));
+                match (question.answer.does_compile, question.answer.stdout) {
+                    (Some(true), Some(stdout)) => {
+                        format!("{}\n\n```text\n{}\n```", labels.compiles_and_prints, stdout)
+                    }
+                    (Some(true), None) => labels.compiles.clone(),
+                    _ => labels.does_not_compile.clone(),
+                }
+            }
+            _ => {
+                tex.push_str("\\vspace{2\\baselineskip}\n");
+                answers.join("; ")
+            }
+        };
+        tex.push_str("\\end{framed}\n");
+
+        let mut answer = format!("**{} {}:** {}", labels.question, number, answer);
+        if let Some(context) = question.context {
+            answer.push_str(&format!("\n\n{}", context));
+        }
+        let answer = markdown_fragment_to_tex(&answer)?;
+        state.capabilities.record(
+            "Quizzes",
+            Outcome::Degraded(match state.cfg.quiz_answers {
+                QuizAnswers::Hidden => "printed questions, answers hidden",
+                QuizAnswers::Inline => "printed questions, answers inline",
+                QuizAnswers::Appendix => "printed questions, answers in an appendix",
+            }),
+        );
+        match state.cfg.quiz_answers {
+            QuizAnswers::Hidden => {}
+            QuizAnswers::Inline => tex.push_str(&format!("{{\\small {}}}\n", answer)),
+            QuizAnswers::Appendix => state.quiz.answers.push(answer),
+        }
+    }
+    Ok(format!("\n\n{}\n\n", state.raw.token(tex)))
+}
+
+/// The answer key appendix, if there is any to print.
+pub(crate) fn answer_key(state: &mut Traversal) -> Option<String> {
+    if state.quiz.answers.is_empty() {
+        return None;
+    }
+    let title = escape(&state.labels.answer_key);
+    let mut tex = format!(
+        "\\section*{{{}}}\n\\phantomsection\n\\addcontentsline{{toc}}{{section}}{{{}}}\n",
+        title, title
+    );
+    for answer in state.quiz.answers.drain(..) {
+        tex.push_str(&answer);
+        tex.push_str("\\par\\medskip\n");
+    }
+    Some(format!("\n\n{}\n\n", state.raw.token(tex)))
+}
@@ -0,0 +1,122 @@
+//! Raw LaTeX passthrough.
+//!
+//! The intermediate markdown is converted by `cmark2tex`, which escapes
+//! everything it is handed. Fragments that must reach the `.tex` file
+//! verbatim are stashed here and represented by an opaque, purely
+//! alphanumeric token until after the conversion.
+
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
+use regex::{Captures, Regex};
+
+const TOKEN_PREFIX: &str = "MDBOOKTECTONICRAW";
+
+#[derive(Debug)]
+pub(crate) struct Passthrough {
+    fragments: Vec<String>,
+    token: Regex,
+}
+
+impl Default for Passthrough {
+    fn default() -> Self {
+        Self {
+            fragments: Vec::new(),
+            token: Regex::new(&format!("{}([0-9]{{8}})X", TOKEN_PREFIX))
+                .expect("Parses just fine. qed"),
+        }
+    }
+}
+
+impl Passthrough {
+    /// Stash a fragment and return the token standing in for it.
+    pub(crate) fn token(&mut self, tex: impl Into<String>) -> String {
+        let token = format!("{}{:08}X", TOKEN_PREFIX, self.fragments.len());
+        self.fragments.push(tex.into());
+        token
+    }
+
+    /// Stash an inline fragment and return the event standing in for it.
+    pub(crate) fn inline<'a>(&mut self, tex: impl Into<String>) -> Event<'a> {
+        Event::Text(CowStr::from(self.token(tex)))
+    }
+
+    /// Stash a block level fragment, which gets a paragraph of its own.
+    pub(crate) fn block<'a>(&mut self, tex: impl Into<String>) -> [Event<'a>; 3] {
+        [
+            Event::Start(Tag::Paragraph),
+            self.inline(tex),
+            Event::End(Tag::Paragraph),
+        ]
+    }
+
+    /// Remove all tokens from `text`, for checks only interested in prose.
+    pub(crate) fn strip<'t>(&self, text: &'t str) -> std::borrow::Cow<'t, str> {
+        self.token.replace_all(text, " ")
+    }
+
+    /// Replace all tokens in `text` with their fragments.
+    ///
+    /// Fragments may themselves contain tokens of fragments stashed
+    /// earlier, these are expanded as well.
+    pub(crate) fn restore(&self, text: &str) -> String {
+        self.token
+            .replace_all(text, |caps: &Captures| {
+                let idx: usize = caps[1].parse().expect("Token is all digits. qed");
+                self.fragments
+                    .get(idx)
+                    .map(|fragment| self.restore(fragment))
+                    .unwrap_or_else(|| caps[0].to_owned())
+            })
+            .into_owned()
+    }
+}
+
+/// Escape plain text for LaTeX.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// How `cmark2tex` includes an image, from the `src` directory two levels
+/// up. Images are copied next to the `.tex` file and referred to from there.
+const IMAGE_PREFIX: &str = "\\includegraphics[width=\\textwidth]{../../src/";
+
+/// Convert markdown to LaTeX with `cmark2tex`, images referred to by the
+/// path they are copied to.
+pub(crate) fn markdown_to_tex(markdown: String) -> color_eyre::Result<String> {
+    let tex = cmark2tex::markdown_to_tex(markdown)?;
+    Ok(tex.replace(IMAGE_PREFIX, "\\includegraphics[width=\\textwidth]{"))
+}
+
+/// Line end `cmark2tex` puts after every paragraph.
+const PARAGRAPH_END: &str = "~\\\\";
+
+/// Convert a run of markdown events to LaTeX, for embedding it within a
+/// raw fragment.
+///
+/// The line end after the last paragraph is dropped, as inline content like
+/// a title or a caption is converted as a paragraph of its own as well.
+pub(crate) fn events_to_tex<'a>(events: &[Event<'a>]) -> color_eyre::Result<String> {
+    let mut markdown = String::new();
+    pulldown_cmark_to_cmark::cmark(events.iter(), &mut markdown)?;
+    let tex = markdown_to_tex(markdown)?;
+    let tex = tex.trim();
+    Ok(tex.strip_suffix(PARAGRAPH_END).unwrap_or(tex).to_owned())
+}
+
+/// Convert a snippet of markdown to LaTeX, for embedding it within a raw
+/// fragment.
+pub(crate) fn markdown_fragment_to_tex(markdown: &str) -> color_eyre::Result<String> {
+    events_to_tex(&Parser::new_ext(markdown, Options::all()).collect::<Vec<_>>())
+}
@@ -0,0 +1,101 @@
+//! Build report, the findings of optional checks written alongside the
+//! outputs as `report.md`.
+
+use crate::labels::Labels;
+use crate::raw::Passthrough;
+use fs_err as fs;
+use pulldown_cmark::{Event, Tag};
+use std::path::Path;
+
+/// The prose of a chapter, without code.
+pub(crate) fn prose(events: &[Event], raw: &Passthrough) -> String {
+    let mut text = String::new();
+    let mut in_code = false;
+    for event in events {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code = true,
+            Event::End(Tag::CodeBlock(_)) => in_code = false,
+            Event::Text(t) if !in_code => text.push_str(&raw.strip(t)),
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak | Event::End(_) => text.push('\n'),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Findings of one check, grouped by chapter.
+#[derive(Debug, Default)]
+struct Section {
+    title: String,
+    chapters: Vec<(String, Vec<String>)>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Report {
+    sections: Vec<Section>,
+}
+
+impl Report {
+    /// Add findings of the check `section` for `chapter`, the section title
+    /// is looked up in the labels when rendering.
+    pub(crate) fn add(&mut self, section: &str, chapter: &str, findings: Vec<String>) {
+        if findings.is_empty() {
+            return;
+        }
+        let idx = match self.sections.iter().position(|s| s.title == section) {
+            Some(idx) => idx,
+            None => {
+                self.sections.push(Section {
+                    title: section.to_owned(),
+                    chapters: Vec::new(),
+                });
+                self.sections.len() - 1
+            }
+        };
+        let chapters = &mut self.sections[idx].chapters;
+        // Chapters converted piece by piece add findings more than once.
+        match chapters.last_mut() {
+            Some((last, existing)) if last == chapter => existing.extend(findings),
+            _ => chapters.push((chapter.to_owned(), findings)),
+        }
+    }
+
+    /// Number of findings of the check `section`.
+    pub(crate) fn count(&self, section: &str) -> usize {
+        self.sections
+            .iter()
+            .filter(|s| s.title == section)
+            .flat_map(|s| s.chapters.iter())
+            .map(|(_, findings)| findings.len())
+            .sum()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.sections.is_empty()
+    }
+
+    fn render(&self, labels: &Labels) -> String {
+        let mut out = format!("# {}\n", labels.build_report);
+        for section in &self.sections {
+            out.push_str(&format!("\n## {}\n", labels.report_section(&section.title)));
+            for (chapter, findings) in &section.chapters {
+                out.push_str(&format!("\n### {}\n\n", chapter));
+                for finding in findings {
+                    out.push_str(&format!("- {}\n", finding));
+                }
+            }
+        }
+        out
+    }
+
+    /// Write the report to `path`, usually `report.md` in the destination.
+    pub(crate) fn write(&self, path: &Path, labels: &Labels) -> std::io::Result<()> {
+        if let Some(destination) = path.parent() {
+            fs::create_dir_all(destination)?;
+        }
+        fs::write(path, self.render(labels))?;
+        log::info!("Build report written to {}", path.display());
+        Ok(())
+    }
+}
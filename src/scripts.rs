@@ -0,0 +1,36 @@
+//! Line breaking of scripts without spaces between words, like Thai or
+//! Khmer, which TeX would otherwise only break at the rare spaces, setting
+//! whole paragraphs as single overflowing lines.
+
+use crate::labels::primary;
+use crate::Engine;
+
+/// Languages written without spaces between words, with their babel name.
+const NO_SPACE: &[(&str, &str)] = &[
+    ("th", "thai"),
+    ("lo", "lao"),
+    ("km", "khmer"),
+    ("my", "burmese"),
+];
+
+/// Whether `language` is written without spaces between words.
+pub(crate) fn is_no_space(language: &str) -> bool {
+    NO_SPACE.iter().any(|(tag, _)| *tag == primary(language))
+}
+
+/// Preamble additions setting up line breaking for `language`, if written
+/// without spaces between words.
+///
+/// XeTeX breaks lines the way ICU does for the locale, LuaTeX with the
+/// rules of the babel language definition.
+pub(crate) fn line_breaking(language: Option<&str>, engine: Engine) -> Option<String> {
+    let language = primary(language?);
+    let (tag, name) = NO_SPACE.iter().find(|(tag, _)| *tag == language)?;
+    Some(match engine {
+        Engine::Tectonic => format!(
+            "\\XeTeXlinebreaklocale \"{}\"\n\\XeTeXlinebreakskip = 0pt plus 1pt\n",
+            tag
+        ),
+        Engine::Lualatex => format!("\\babelprovide[import, main]{{{}}}\n", name),
+    })
+}
@@ -0,0 +1,80 @@
+//! Sectioning which `cmark2tex` can not express on its own.
+
+use crate::raw::events_to_tex;
+use crate::Traversal;
+use pulldown_cmark::{Event, HeadingLevel, Tag};
+
+/// Sectioning command and bookmark depth for a heading level, the same
+/// commands `cmark2tex` uses for numbered headings.
+pub(crate) fn sectioning(level: HeadingLevel) -> (&'static str, u8) {
+    match level {
+        HeadingLevel::H1 => ("chapter", 0),
+        HeadingLevel::H2 => ("section", 1),
+        HeadingLevel::H3 => ("subsection", 2),
+        HeadingLevel::H4 => ("subsubsection", 3),
+        HeadingLevel::H5 => ("paragraph", 4),
+        HeadingLevel::H6 => ("subparagraph", 5),
+    }
+}
+
+/// Pandoc style `{.unnumbered}` or `{-}` heading attribute.
+fn is_unnumbered(classes: &[&str]) -> bool {
+    classes
+        .iter()
+        .any(|class| *class == "unnumbered" || *class == "-")
+}
+
+/// Turn unnumbered headings into starred sections.
+///
+/// Headings are unnumbered if their chapter is (prefix and suffix chapters
+/// of `SUMMARY.md`) or if they carry an unnumbered attribute. Starred
+/// sections neither show up in the PDF bookmarks nor the table of contents,
+/// so a bookmark is added explicitly, or a full table of contents entry if
+/// `unnumbered-toc` is set.
+///
+/// Without a sectioning counter being stepped, hyperref would anchor labels
+/// at the previous numbered unit. A `\phantomsection` provides the anchor
+/// for both the label of the heading id and the bookmark.
+pub(crate) fn unnumbered_headings<'a>(
+    events: Vec<Event<'a>>,
+    chapter_numbered: bool,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+    while let Some(event) = events.next() {
+        match event {
+            Event::Start(Tag::Heading(level, id, ref classes))
+                if !chapter_numbered || is_unnumbered(classes) =>
+            {
+                let title = events
+                    .by_ref()
+                    .take_while(|event| !matches!(event, Event::End(Tag::Heading(..))))
+                    .collect::<Vec<_>>();
+                let title = events_to_tex(&title)?;
+                let (command, depth) = sectioning(level);
+
+                let mut tex = format!("\\{}*{{{}}}\n\\phantomsection\n", command, title);
+                if let Some(id) = id {
+                    tex.push_str(&format!("\\label{{{}}}\n", id));
+                }
+                if state.cfg.unnumbered_toc {
+                    tex.push_str(&format!(
+                        "\\addcontentsline{{toc}}{{{}}}{{{}}}",
+                        command, title
+                    ));
+                } else {
+                    tex.push_str(&format!(
+                        "\\pdfbookmark[{}]{{{}}}{{{}}}",
+                        depth,
+                        title,
+                        state.ids.next("unnumbered")
+                    ));
+                }
+                out.extend(state.raw.block(tex));
+            }
+            event => out.push(event),
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,145 @@
+//! Series a book is a volume of, printed above the title, with a note on
+//! the title page referencing the other volumes.
+//!
+//! Before the title page, a half title and a series page listing all the
+//! volumes may be printed, laid out by `\mdbookhalftitlepage` and
+//! `\mdbookseriespage`, which templates can define in a style of their own.
+
+use crate::labels::Labels;
+use crate::raw::escape;
+
+/// The series and the volume of this book in it.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Series {
+    pub name: Option<String>,
+
+    // Number of this volume, counting from one.
+    pub volume: Option<usize>,
+
+    // All volumes of the series, in order, this one included.
+    pub volumes: Vec<Volume>,
+}
+
+/// One volume of the series.
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Volume {
+    pub title: String,
+
+    // Where to get the volume, its title links there.
+    pub url: Option<String>,
+}
+
+/// The series and volume number on one line, "Series, Volume 2", empty if
+/// the book is in no series.
+pub(crate) fn line(series: &Series, labels: &Labels) -> String {
+    let volume = series
+        .volume
+        .map(|volume| format!("{} {}", escape(&labels.volume), volume));
+    match (&series.name, volume) {
+        (Some(name), Some(volume)) => format!("{}, {}", escape(name), volume),
+        (Some(name), None) => escape(name),
+        (None, Some(volume)) => volume,
+        (None, None) => String::new(),
+    }
+}
+
+/// The other volumes of the series, "Volume 1: Title; Volume 3: Title",
+/// linked if they have a URL.
+pub(crate) fn others(series: &Series, labels: &Labels) -> String {
+    series
+        .volumes
+        .iter()
+        .enumerate()
+        .map(|(idx, volume)| (idx + 1, volume))
+        .filter(|(number, _)| Some(*number) != series.volume)
+        .map(|(number, volume)| {
+            let title = match &volume.url {
+                Some(url) => format!("\\href{{{}}}{{{}}}", url, escape(&volume.title)),
+                None => escape(&volume.title),
+            };
+            format!("{} {}: {}", escape(&labels.volume), number, title)
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// The argument of `\title`, `page` with the series above and the other
+/// volumes in a note.
+pub(crate) fn title(page: &str, series: &Series, labels: &Labels) -> String {
+    let mut tex = String::new();
+    let line = line(series, labels);
+    if !line.is_empty() {
+        tex.push_str(&format!("{{\\normalsize {}}}\\\\[1em]\n", line));
+    }
+    tex.push_str(page);
+    let others = others(series, labels);
+    if !others.is_empty() {
+        tex.push_str(&format!(
+            "\\thanks{{{} {}.}}",
+            escape(&labels.also_in_series),
+            others
+        ));
+    }
+    tex
+}
+
+/// Default layouts of the half title and series pages, the title, and the
+/// series and its volumes centered on pages of their own.
+pub(crate) const PAGES_PREAMBLE: &str = r"\providecommand{\mdbookhalftitlepage}[1]{%
+  \thispagestyle{empty}\vspace*{0.3\textheight}\begin{center}{\Large #1}\end{center}\clearpage}
+\providecommand{\mdbookseriespage}[2]{%
+  \thispagestyle{empty}\vspace*{0.2\textheight}\begin{center}{\large #1}\par\bigskip #2\end{center}\clearpage}
+";
+
+/// All volumes of the series, a line each, this one in bold.
+pub(crate) fn list(series: &Series, labels: &Labels) -> String {
+    series
+        .volumes
+        .iter()
+        .enumerate()
+        .map(|(idx, volume)| {
+            let line = format!(
+                "{} {}: {}",
+                escape(&labels.volume),
+                idx + 1,
+                escape(&volume.title)
+            );
+            if Some(idx + 1) == series.volume {
+                format!("\\textbf{{{}}}", line)
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\\\\\n")
+}
+
+/// The pages before the title page, the half title and the series page,
+/// as far as they are asked for.
+pub(crate) fn front_pages(
+    title: &str,
+    series: &Series,
+    half_title: bool,
+    series_page: bool,
+    labels: &Labels,
+) -> String {
+    let mut tex = String::new();
+    if half_title {
+        tex.push_str(&format!("\\mdbookhalftitlepage{{{}}}", title));
+    }
+    if series_page {
+        if series.name.is_none() && series.volumes.is_empty() {
+            log::warn!("No series to print a series page for, set `series`");
+        } else {
+            let name = series.name.as_deref().map(escape).unwrap_or_default();
+            tex.push_str(&format!(
+                "\\mdbookseriespage{{{}}}{{{}}}",
+                name,
+                list(series, labels)
+            ));
+        }
+    }
+    tex
+}
@@ -0,0 +1,112 @@
+//! Shortcodes, handled on the markdown source before it is parsed.
+//!
+//! Shortcodes are written as `{{#name ...}}` like the ones built into
+//! mdbook, and can be escaped the same way, `\{{#name}}`, which prints
+//! as written without the backslash. Code is left alone.
+
+use crate::capabilities::Outcome;
+use crate::html::attribute;
+use crate::raw::escape;
+use crate::{math, Traversal};
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+
+/// Replace all unescaped matches of `re` outside of code with what
+/// `replace` returns.
+pub(crate) fn replace_unescaped<'t>(
+    content: &'t str,
+    re: &Regex,
+    mut replace: impl FnMut(&Captures) -> color_eyre::Result<String>,
+) -> color_eyre::Result<Cow<'t, str>> {
+    let mut out = String::new();
+    let mut last = 0;
+    // Only parsed once there is a match at all.
+    let mut code = None;
+    for caps in re.captures_iter(content) {
+        let whole = caps.get(0).expect("Group 0 is the whole match. qed");
+        if content[..whole.start()].ends_with('\\') {
+            continue;
+        }
+        let code = code.get_or_insert_with(|| math::code_ranges(content));
+        if code.iter().any(|range| range.contains(&whole.start())) {
+            continue;
+        }
+        out.push_str(&content[last..whole.start()]);
+        out.push_str(&replace(&caps)?);
+        last = whole.end();
+    }
+    if last == 0 {
+        return Ok(Cow::Borrowed(content));
+    }
+    out.push_str(&content[last..]);
+    Ok(Cow::Owned(out))
+}
+
+/// Drop the backslash of escaped shortcodes outside of code, once all
+/// shortcodes are replaced.
+pub(crate) fn unescape(content: &str) -> color_eyre::Result<Cow<'_, str>> {
+    let re = Regex::new(r"\\\{\{#")?;
+    replace_unescaped(content, &re, |_| Ok("{{#".to_owned()))
+}
+
+/// Flatten tabbed content as written for `mdbook-tabs`.
+///
+/// ```markdown
+/// {{#tabs }}
+/// {{#tab name="Rust" }}
+/// ...
+/// {{#endtab }}
+/// {{#endtabs }}
+/// ```
+///
+/// Each tab becomes a sequence of its name as caption, followed by its
+/// content. If `tabs` is configured only the named tabs are kept.
+pub(crate) fn tabs<'t>(
+    content: &'t str,
+    state: &mut Traversal,
+) -> color_eyre::Result<Cow<'t, str>> {
+    let tabs = Regex::new(r"(?s)\{\{#tabs\b[^}]*\}\}(.*?)\{\{#endtabs\s*\}\}")?;
+    let tab = Regex::new(r"(?s)\{\{#tab\b([^}]*)\}\}(.*?)\{\{#endtab\s*\}\}")?;
+    replace_unescaped(content, &tabs, |caps| {
+        let mut flat = String::from("\n\n");
+        for caps in tab.captures_iter(&caps[1]) {
+            let name = attribute(&caps[1], "name").unwrap_or_default();
+            if !state.cfg.tabs.is_empty() && !state.cfg.tabs.iter().any(|tab| tab == name) {
+                state.capabilities.record("Tabs", Outcome::Dropped);
+                continue;
+            }
+            state
+                .capabilities
+                .record("Tabs", Outcome::Degraded("printed one after the other"));
+            if !name.is_empty() {
+                flat.push_str(&format!("**{}:**\n\n", name));
+            }
+            flat.push_str(caps[2].trim());
+            flat.push_str("\n\n");
+        }
+        Ok(flat)
+    })
+}
+
+/// Page references, `{{#pageref anchor}}` becomes "see page 42", only
+/// print can tell. `{{#ref label}}` becomes the number of a labelled
+/// listing.
+pub(crate) fn pagerefs<'t>(
+    content: &'t str,
+    state: &mut Traversal,
+) -> color_eyre::Result<Cow<'t, str>> {
+    let re = Regex::new(r"\{\{#(pageref|ref)\s+([^}\s]+)\s*\}\}")?;
+    let see_page = escape(&state.labels.see_page);
+    replace_unescaped(content, &re, |caps| {
+        if &caps[1] == "ref" {
+            state.capabilities.record("References", Outcome::Rendered);
+            return Ok(state.raw.token(format!("\\ref{{{}}}", &caps[2])));
+        }
+        state
+            .capabilities
+            .record("Page references", Outcome::Rendered);
+        Ok(state
+            .raw
+            .token(format!("{}~\\pageref{{{}}}", see_page, &caps[2])))
+    })
+}
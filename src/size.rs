@@ -0,0 +1,193 @@
+//! Chapters of megabytes, usually generated ones like API dumps, which take
+//! long to convert and may exhaust the memory of the engine.
+//!
+//! Chapters larger than `warn` are reported. Beyond `limit`, what is left of
+//! a chapter is dropped, or only its headings kept, as `oversize` says, with
+//! a note where it was cut. Chapters larger than `piece` are converted one
+//! piece of whole blocks at a time, rather than all of their events at once.
+
+use crate::capabilities::Outcome;
+use crate::raw::escape;
+use crate::{Oversize, Traversal};
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use std::borrow::Cow;
+
+pub(crate) const SECTION: &str = "chapter-size";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SizeConfig {
+    // Report chapters larger than this many KiB.
+    pub warn: usize,
+
+    // Cut chapters larger than this many KiB, converted in full if not set.
+    pub limit: Option<usize>,
+
+    // What to do with the rest of a chapter beyond the limit.
+    pub oversize: Oversize,
+
+    // Convert chapters larger than this many KiB piece by piece.
+    pub piece: usize,
+}
+
+impl Default for SizeConfig {
+    fn default() -> Self {
+        Self {
+            warn: 1024,
+            limit: None,
+            oversize: Oversize::Truncate,
+            piece: 512,
+        }
+    }
+}
+
+/// `bytes` for humans.
+pub(crate) fn format_size(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{} KiB", (bytes + 1023) / 1024)
+    }
+}
+
+/// Offsets of the blocks at the top level of `content`, where it can be
+/// cut without breaking a list, quote or code block apart.
+pub(crate) fn boundaries(content: &str) -> Vec<usize> {
+    let mut depth = 0;
+    let mut starts = Vec::new();
+    for (event, range) in Parser::new_ext(content, Options::all()).into_offset_iter() {
+        match event {
+            Event::Start(_) => {
+                if depth == 0 {
+                    starts.push(range.start);
+                }
+                depth += 1;
+            }
+            Event::End(_) => depth -= 1,
+            _ => {}
+        }
+    }
+    starts
+}
+
+/// Report `content` if large, and cut it beyond the limit.
+pub(crate) fn guard<'c>(content: &'c str, state: &mut Traversal) -> Cow<'c, str> {
+    let cfg = &state.cfg.chapter_size;
+    let size = content.len();
+    let chapter = state
+        .chapter
+        .map(|chapter| chapter.name.as_str())
+        .unwrap_or_default();
+    if size > cfg.warn * 1024 {
+        let file = state
+            .chapter
+            .and_then(|chapter| chapter.source_path.as_ref().or(chapter.path.as_ref()))
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        log::warn!("{} is {}, conversion may be slow", file, format_size(size));
+        let finding = format!("`{}`: {}", file, format_size(size));
+        state.report.add(SECTION, chapter, vec![finding]);
+    }
+    let limit = match cfg.limit {
+        Some(limit) if size > limit * 1024 => limit * 1024,
+        _ => return Cow::Borrowed(content),
+    };
+    let cut = boundaries(content)
+        .into_iter()
+        .take_while(|start| *start <= limit)
+        .last()
+        .unwrap_or_default();
+    let (kept, rest) = content.split_at(cut);
+    let note = escape(&state.labels.chapter_cut).replace("\\{size\\}", &format_size(rest.len()));
+    let mut out = kept.to_owned();
+    out.push_str(&format!(
+        "\n\n{}\n\n",
+        state.raw.token(format!("\\emph{{{}}}", note))
+    ));
+    let outcome = match cfg.oversize {
+        Oversize::Truncate => Outcome::Degraded("cut at the size limit"),
+        Oversize::Outline => {
+            // Keep the structure of the chapter, and the anchors of links.
+            let mut depth = 0;
+            for (event, range) in Parser::new_ext(rest, Options::all()).into_offset_iter() {
+                match event {
+                    Event::Start(Tag::Heading(..)) if depth == 0 => {
+                        out.push_str(rest[range].trim());
+                        out.push_str("\n\n");
+                        depth += 1;
+                    }
+                    Event::Start(_) => depth += 1,
+                    Event::End(_) => depth -= 1,
+                    _ => {}
+                }
+            }
+            Outcome::Degraded("headings only beyond the size limit")
+        }
+    };
+    state.capabilities.record("Oversized chapters", outcome);
+    Cow::Owned(out)
+}
+
+/// `content` in pieces of whole blocks of about `piece` KiB each. All
+/// pieces get the link reference and footnote definitions of the chapter,
+/// wherever in it those are.
+pub(crate) fn pieces(content: &str, piece: usize) -> Vec<Cow<'_, str>> {
+    let piece = piece.max(1) * 1024;
+    if content.len() <= piece {
+        return vec![Cow::Borrowed(content)];
+    }
+    let mut depth = 0;
+    let mut starts = Vec::new();
+    let mut definitions = String::new();
+    let mut footnote = None;
+    let mut parser = Parser::new_ext(content, Options::all()).into_offset_iter();
+    for (event, range) in parser.by_ref() {
+        match event {
+            Event::Start(tag) => {
+                if depth == 0 {
+                    starts.push(range.start);
+                    if matches!(tag, Tag::FootnoteDefinition(_)) {
+                        footnote = Some(range.start);
+                    }
+                }
+                depth += 1;
+            }
+            Event::End(_) => {
+                depth -= 1;
+                if depth == 0 {
+                    if let Some(start) = footnote.take() {
+                        definitions.push_str(&content[start..range.end]);
+                        definitions.push_str("\n\n");
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut references = parser
+        .reference_definitions()
+        .iter()
+        .map(|(_, definition)| definition.span.clone())
+        .collect::<Vec<_>>();
+    references.sort_by_key(|span| span.start);
+    for span in references {
+        definitions.push_str(content[span].trim_end());
+        definitions.push('\n');
+    }
+
+    let mut pieces = Vec::new();
+    let (mut start, mut last) = (0, 0);
+    for boundary in starts {
+        if boundary - start > piece && last > start {
+            pieces.push(&content[start..last]);
+            start = last;
+        }
+        last = boundary;
+    }
+    pieces.push(&content[start..]);
+    log::debug!("Converting {} pieces", pieces.len());
+    pieces
+        .into_iter()
+        .map(|piece| Cow::Owned(format!("{}\n\n{}", piece, definitions)))
+        .collect()
+}
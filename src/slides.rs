@@ -0,0 +1,165 @@
+//! A beamer slide deck or handout derived from the book, for teaching from
+//! the book material.
+//!
+//! Level one headings become sections, level two and three headings become
+//! frames, with the level three heading as subtitle. The lists and code
+//! blocks below a heading make up the body of its frame, prose is left to
+//! the book.
+
+use crate::raw::{escape, events_to_tex};
+use crate::{comments, LatexConfig};
+use mdbook::book::{Book, BookItem};
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag};
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SlidesConfig {
+    // Derive a slide deck from the book.
+    pub enable: bool,
+
+    // Chapters the slides are derived from, as named in the SUMMARY.md, all if empty.
+    pub chapters: Vec<String>,
+
+    // A handout without overlays instead of a presentation.
+    pub handout: bool,
+}
+
+/// A frame being collected.
+#[derive(Debug, Default)]
+struct Frame<'a> {
+    title: String,
+    subtitle: Option<String>,
+    body: Vec<Event<'a>>,
+    // Verbatim content requires a fragile frame.
+    fragile: bool,
+}
+
+impl Frame<'_> {
+    /// The frame, `None` if nothing was collected for it.
+    fn to_tex(&self) -> color_eyre::Result<Option<String>> {
+        if self.body.is_empty() {
+            return Ok(None);
+        }
+        let mut tex = String::from("\\begin{frame}");
+        if self.fragile {
+            tex.push_str("[fragile]");
+        }
+        tex.push_str(&format!("{{{}}}", escape(&self.title)));
+        if let Some(ref subtitle) = self.subtitle {
+            tex.push_str(&format!("{{{}}}", escape(subtitle)));
+        }
+        tex.push('\n');
+        tex.push_str(&events_to_tex(&self.body)?);
+        tex.push_str("\n\\end{frame}\n\n");
+        Ok(Some(tex))
+    }
+}
+
+/// Collect the events up to the end of the element started by `start`.
+fn element<'a>(start: Event<'a>, events: &mut impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+    let mut element = vec![start];
+    let mut depth = 1;
+    for event in events {
+        match event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => {}
+        }
+        element.push(event);
+        if depth == 0 {
+            break;
+        }
+    }
+    element
+}
+
+/// Frames of one chapter.
+fn chapter(content: &str, out: &mut String) -> color_eyre::Result<()> {
+    let content = comments::conditionals(content)?;
+    let mut events = Parser::new_ext(&content, Options::all());
+    let mut frame: Option<Frame> = None;
+    // Title of the last level two heading, for frames of level three headings.
+    let mut section = String::new();
+    while let Some(event) = events.next() {
+        match event {
+            Event::Start(Tag::Heading(level, ..)) => {
+                let title = element(event, &mut events)
+                    .into_iter()
+                    .filter_map(|event| match event {
+                        Event::Text(text) | Event::Code(text) => Some(text.to_string()),
+                        _ => None,
+                    })
+                    .collect::<String>();
+                if let Some(tex) = frame.take().map(|frame| frame.to_tex()).transpose()? {
+                    out.extend(tex);
+                }
+                match level {
+                    HeadingLevel::H1 => {
+                        out.push_str(&format!("\\section{{{}}}\n\n", escape(&title)));
+                    }
+                    HeadingLevel::H2 => {
+                        section = title.clone();
+                        frame = Some(Frame {
+                            title,
+                            ..Default::default()
+                        });
+                    }
+                    HeadingLevel::H3 => {
+                        frame = Some(Frame {
+                            title: section.clone(),
+                            subtitle: Some(title),
+                            ..Default::default()
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            Event::Start(Tag::List(_)) => {
+                let list = element(event, &mut events);
+                if let Some(ref mut frame) = frame {
+                    frame.body.extend(list);
+                }
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                let code = element(event, &mut events);
+                if let Some(ref mut frame) = frame {
+                    frame.body.extend(code);
+                    frame.fragile = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(tex) = frame.map(|frame| frame.to_tex()).transpose()? {
+        out.extend(tex);
+    }
+    Ok(())
+}
+
+/// The slide deck of the chapters selected.
+pub(crate) fn document(
+    book: &Book,
+    cfg: &LatexConfig,
+    title: &str,
+    authors: &str,
+) -> color_eyre::Result<String> {
+    let mut out = format!(
+        "\\documentclass{}{{beamer}}\n\\usepackage{{listings}}\n\\title{{{}}}\n\\author{{{}}}\n\\date{{{}}}\n\\begin{{document}}\n\\frame{{\\titlepage}}\n\n",
+        if cfg.slides.handout { "[handout]" } else { "" },
+        title,
+        authors,
+        cfg.date
+    );
+    for item in book.iter() {
+        let ch = match item {
+            BookItem::Chapter(ch) if !cfg.ignores.contains(&ch.name) => ch,
+            _ => continue,
+        };
+        if !cfg.slides.chapters.is_empty() && !cfg.slides.chapters.contains(&ch.name) {
+            continue;
+        }
+        chapter(&crate::encoding::normalize(&ch.content), &mut out)?;
+    }
+    out.push_str("\\end{document}\n");
+    Ok(out)
+}
@@ -0,0 +1,192 @@
+//! Comparison of the generated LaTeX against a checked in baseline, so
+//! changes affecting the output can be reviewed without building PDFs.
+
+use fs_err as fs;
+use std::path::{Path, PathBuf};
+
+/// Lines of context around changes.
+const CONTEXT: usize = 3;
+
+/// Above this many cells the middle part is not diffed line by line but
+/// reported as replaced as a whole.
+const MAX_TABLE: usize = 16_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Baseline of an edition, `book-de.tex` next to `book.tex`.
+pub(crate) fn baseline(path: &Path, edition: Option<&str>) -> PathBuf {
+    let edition = match edition {
+        Some(edition) => edition,
+        None => return path.to_owned(),
+    };
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(format!("-{}", edition));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// Line by line diff, the longest common subsequence between the common
+/// prefix and suffix.
+fn diff<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut lines = old[..prefix]
+        .iter()
+        .map(|line| Line::Same(line))
+        .collect::<Vec<_>>();
+    if (a.len() + 1) * (b.len() + 1) > MAX_TABLE {
+        lines.extend(a.iter().map(|line| Line::Removed(line)));
+        lines.extend(b.iter().map(|line| Line::Added(line)));
+    } else {
+        // lcs[i][j] is the length of the common subsequence of a[i..] and b[j..].
+        let width = b.len() + 1;
+        let mut lcs = vec![0_u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i * width + j] = if a[i] == b[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                lines.push(Line::Same(a[i]));
+                i += 1;
+                j += 1;
+            } else if j == b.len()
+                || (i < a.len() && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+            {
+                lines.push(Line::Removed(a[i]));
+                i += 1;
+            } else {
+                lines.push(Line::Added(b[j]));
+                j += 1;
+            }
+        }
+    }
+    lines.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| Line::Same(line)),
+    );
+    lines
+}
+
+/// Unified diff of `old` and `new`, `None` if they are the same.
+pub(crate) fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> Option<String> {
+    if old == new {
+        return None;
+    }
+    let lines = diff(
+        &old.lines().collect::<Vec<_>>(),
+        &new.lines().collect::<Vec<_>>(),
+    );
+    let changed = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(_)))
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+    // Line endings differ only.
+    if changed.is_empty() {
+        return None;
+    }
+
+    // Merge changes closer than twice the context into one hunk.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for idx in changed {
+        let start = idx.saturating_sub(CONTEXT);
+        let end = (idx + CONTEXT + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for (start, end) in hunks {
+        let count = |range: &[Line], removed: bool| {
+            range
+                .iter()
+                .filter(|line| match line {
+                    Line::Same(_) => true,
+                    Line::Removed(_) => removed,
+                    Line::Added(_) => !removed,
+                })
+                .count()
+        };
+        let old_start = count(&lines[..start], true) + 1;
+        let new_start = count(&lines[..start], false) + 1;
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start,
+            count(&lines[start..end], true),
+            new_start,
+            count(&lines[start..end], false)
+        ));
+        for line in &lines[start..end] {
+            match line {
+                Line::Same(line) => out.push_str(&format!(" {}\n", line)),
+                Line::Removed(line) => out.push_str(&format!("-{}\n", line)),
+                Line::Added(line) => out.push_str(&format!("+{}\n", line)),
+            }
+        }
+    }
+    Some(out)
+}
+
+/// Compare `tex` against the baseline at `path`, or replace the baseline
+/// with it if `update` is set.
+///
+/// Fails with the diff printed if they differ.
+pub(crate) fn check(path: &Path, tex: &str, update: bool) -> color_eyre::Result<()> {
+    if update {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, tex)?;
+        log::info!("Updated the LaTeX baseline {}", path.display());
+        return Ok(());
+    }
+    if !path.exists() {
+        color_eyre::eyre::bail!(
+            "Missing LaTeX baseline {}, create it with `--update-tex`",
+            path.display()
+        );
+    }
+    let baseline = fs::read_to_string(path)?;
+    match unified(&baseline, tex, &path.display().to_string(), "generated") {
+        Some(diff) => {
+            log::warn!("{}", diff);
+            color_eyre::eyre::bail!(
+                "Generated LaTeX differs from the baseline {}",
+                path.display()
+            )
+        }
+        None => {
+            log::info!("Generated LaTeX matches the baseline {}", path.display());
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Spell checking of the prose with `hunspell`.
+
+use crate::report::prose;
+use crate::subprocess;
+use crate::Traversal;
+use pulldown_cmark::Event;
+use std::collections::BTreeMap;
+use std::process::Command;
+
+/// Report section of the spell check.
+pub(crate) const SECTION: &str = "spelling";
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SpellcheckConfig {
+    // Check the spelling of all chapters.
+    pub enable: bool,
+
+    // Hunspell dictionary, derived from `book.language` if not set.
+    pub dictionary: Option<String>,
+
+    // Project specific word list, one word per line, relative to the book root.
+    pub word_list: Option<String>,
+
+    // Fail the build on misspellings.
+    pub strict: bool,
+}
+
+/// Hunspell dictionary name for a language tag, `en` becomes `en_US`.
+fn dictionary(language: Option<&str>) -> String {
+    let language = language.unwrap_or("en").replace('-', "_");
+    match language.as_str() {
+        "en" => "en_US".to_owned(),
+        lang if !lang.contains('_') => format!("{}_{}", lang, lang.to_uppercase()),
+        _ => language,
+    }
+}
+
+/// Check the prose of a chapter, code is skipped, and add the misspelled
+/// words to the report.
+pub(crate) fn check(events: &[Event], state: &mut Traversal) -> color_eyre::Result<()> {
+    let cfg = &state.cfg.spellcheck;
+    if !cfg.enable {
+        return Ok(());
+    }
+    let hunspell = match which::which("hunspell") {
+        Ok(hunspell) => hunspell,
+        Err(_) if !cfg.strict => {
+            log::warn!("Spell checking requires `hunspell` to be installed, skipping");
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let text = prose(events, &state.raw);
+    let dictionary = cfg
+        .dictionary
+        .clone()
+        .unwrap_or_else(|| dictionary(state.context.config.book.language.as_deref()));
+    let mut command = Command::new(hunspell);
+    command.arg("-l").arg("-d").arg(dictionary);
+    if let Some(ref word_list) = cfg.word_list {
+        command.arg("-p").arg(state.context.root.join(word_list));
+    }
+    let output = match subprocess::run(command, Some(text.as_bytes()), &state.cfg.limits) {
+        Ok(output) => output,
+        Err(err @ subprocess::Error::Timeout { .. }) => {
+            log::warn!("Skipping the spell check of a chapter, {}", err);
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut misspelled = BTreeMap::<&str, usize>::new();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for word in stdout.lines().filter(|word| !word.is_empty()) {
+        *misspelled.entry(word).or_default() += 1;
+    }
+    let findings = misspelled
+        .into_iter()
+        .map(|(word, count)| match count {
+            1 => word.to_owned(),
+            count => format!("{} ({}×)", word, count),
+        })
+        .collect();
+    let chapter = state
+        .chapter
+        .map(|chapter| chapter.name.as_str())
+        .unwrap_or_default();
+    state.report.add(SECTION, chapter, findings);
+    Ok(())
+}
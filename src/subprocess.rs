@@ -0,0 +1,157 @@
+//! External tools run on the content of the book, like `rustc` checking the
+//! examples, within limits, so bad input can not hang the whole build or
+//! exhaust the memory.
+//!
+//! A tool running longer than `timeout` is killed. The `memory` limit is
+//! set by `ulimit`, on Unix only.
+//!
+//! Diagrams are rendered by preprocessors like `mdbook-mermaid`, before this
+//! backend sees the book, so `mmdc` and `dot` run outside of these limits.
+
+use std::io::{Read, Write};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+/// Limits of every run of an external tool.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Limits {
+    // Seconds a run may take before it is killed.
+    pub timeout: u64,
+
+    // Virtual memory a run may use, in MiB, unlimited if not set.
+    pub memory: Option<u64>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            timeout: 60,
+            memory: None,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum Error {
+    #[error("`{program}` timed out after {seconds} seconds{}", last_words(stderr))]
+    Timeout {
+        program: String,
+        seconds: u64,
+        // What the tool wrote to stderr until it was killed.
+        stderr: String,
+    },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// The last line the tool wrote to stderr, what it was stuck at.
+fn last_words(stderr: &str) -> String {
+    match stderr.lines().rev().find(|line| !line.trim().is_empty()) {
+        Some(line) => format!(", last writing `{}`", line.trim()),
+        None => String::new(),
+    }
+}
+
+/// `command` run by a shell which limits the memory first.
+#[cfg(unix)]
+fn with_memory_limit(command: &Command, mebibytes: u64) -> Command {
+    let mut limited = Command::new("sh");
+    limited
+        .arg("-c")
+        .arg("ulimit -v \"$0\" && exec \"$@\"")
+        .arg((mebibytes * 1024).to_string())
+        .arg(command.get_program())
+        .args(command.get_args());
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => limited.env(key, value),
+            None => limited.env_remove(key),
+        };
+    }
+    if let Some(dir) = command.get_current_dir() {
+        limited.current_dir(dir);
+    }
+    limited
+}
+
+/// Read all of `pipe` on a thread of its own, so a full pipe does not
+/// block the tool.
+fn drain(
+    pipe: Option<impl Read + Send + 'static>,
+) -> std::thread::JoinHandle<std::io::Result<Vec<u8>>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buf)?;
+        }
+        Ok(buf)
+    })
+}
+
+/// Run `command` with `input` on stdin, within `limits`, and collect its
+/// output.
+pub(crate) fn run(
+    mut command: Command,
+    input: Option<&[u8]>,
+    limits: &Limits,
+) -> Result<Output, Error> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    if let Some(memory) = limits.memory {
+        #[cfg(unix)]
+        {
+            command = with_memory_limit(&command, memory);
+        }
+        #[cfg(not(unix))]
+        log::debug!("Ignoring the memory limit of {} MiB, Unix only", memory);
+    }
+    let mut child = command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let writer = match (child.stdin.take(), input) {
+        (Some(mut stdin), Some(input)) => {
+            let input = input.to_vec();
+            Some(std::thread::spawn(move || stdin.write_all(&input)))
+        }
+        _ => None,
+    };
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let deadline = Instant::now() + Duration::from_secs(limits.timeout);
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            break None;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    // A tool exiting without reading all of its input is none of our concern.
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+    let stdout = stdout.join().expect("Reading does not panic. qed")?;
+    let stderr = stderr.join().expect("Reading does not panic. qed")?;
+    match status {
+        Some(status) => Ok(Output {
+            status,
+            stdout,
+            stderr,
+        }),
+        None => Err(Error::Timeout {
+            program,
+            seconds: limits.timeout,
+            stderr: String::from_utf8_lossy(&stderr).into_owned(),
+        }),
+    }
+}
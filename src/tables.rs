@@ -0,0 +1,646 @@
+//! Tables, as `tabular` environments with the rules of `booktabs`, the
+//! columns aligned as the `:---:` markers of the delimiter row say.
+//!
+//! Tables with more rows than `long-rows` break across pages as `longtable`,
+//! repeating the header row on each page. A directive right before a table
+//! decides for it alone:
+//!
+//! ```markdown
+//! <!-- table: long -->
+//! ```
+//!
+//! Tables estimated wider than the text, by the characters of their widest
+//! cells, are turned sideways or shrunk as `wide-table` says, or as the
+//! directive says, `<!-- table: rotate -->`.
+//!
+//! With `wrap-prose`, tables with cells of long prose wrap the text of
+//! those columns instead, as `X` columns of `tabularx`, or paragraph
+//! columns of long tables, sharing the width by the length of their cells.
+//!
+//! Tables written as HTML, mostly for cells spanning several columns or
+//! rows, are converted as well, with `\multicolumn` and `\multirow`.
+
+use crate::capabilities::Outcome;
+use crate::html::{join_html, keep_html};
+use crate::raw::escape;
+use crate::{Traversal, WideTable};
+use pulldown_cmark::{Alignment, Event, Tag};
+use regex::Regex;
+use scraper::{ElementRef, Html};
+use std::ops::Range;
+
+/// Report section of HTML tables left as they are.
+pub(crate) const SECTION: &str = "html-tables";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct TablesConfig {
+    // Tables with more rows break across pages, repeating the header.
+    pub long_rows: usize,
+
+    // Characters fitting the text width, for estimating whether a table is too wide.
+    pub text_width: usize,
+
+    // Wrap the text of columns with long prose within the text width.
+    pub wrap_prose: bool,
+
+    // Characters of a cell from which on it counts as prose.
+    pub prose_width: usize,
+}
+
+impl Default for TablesConfig {
+    fn default() -> Self {
+        Self {
+            long_rows: 30,
+            text_width: 90,
+            wrap_prose: false,
+            prose_width: 40,
+        }
+    }
+}
+
+/// How a table is laid out, by the `<!-- table: ... -->` directive.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Directive {
+    // `long` or `short`, else by the number of rows.
+    pub(crate) long: Option<bool>,
+    // `rotate`, `shrink` or `none`, else by `wide-table` if too wide.
+    pub(crate) wide: Option<WideTable>,
+}
+
+/// The directive of the comment `html`, if it is one.
+pub(crate) fn directive(html: &str) -> Option<Directive> {
+    let body = html
+        .trim()
+        .strip_prefix("<!--")?
+        .strip_suffix("-->")?
+        .trim()
+        .strip_prefix("table:")?;
+    let mut directive = Directive::default();
+    for word in body.split([',', ' ']).filter(|word| !word.is_empty()) {
+        match word {
+            "long" => directive.long = Some(true),
+            "short" => directive.long = Some(false),
+            "rotate" => directive.wide = Some(WideTable::Rotate),
+            "shrink" => directive.wide = Some(WideTable::Shrink),
+            "none" => directive.wide = Some(WideTable::None),
+            word => log::warn!("Unknown table directive `{}`", word),
+        }
+    }
+    Some(directive)
+}
+
+/// A table taken out of the events, the cells keeping their inline events.
+#[derive(Debug, Default)]
+struct Table<'a> {
+    alignments: Vec<Alignment>,
+    head: Vec<Vec<Event<'a>>>,
+    rows: Vec<Vec<Vec<Event<'a>>>>,
+}
+
+/// Take the table starting with `start` out of `events`.
+fn take_table<'a>(start: Tag<'a>, events: &mut impl Iterator<Item = Event<'a>>) -> Table<'a> {
+    let mut table = Table {
+        alignments: match start {
+            Tag::Table(alignments) => alignments,
+            _ => Vec::new(),
+        },
+        ..Default::default()
+    };
+    let mut in_head = false;
+    let mut cell = None;
+    for event in events {
+        match event {
+            Event::End(Tag::Table(_)) => break,
+            Event::Start(Tag::TableHead) => in_head = true,
+            Event::End(Tag::TableHead) => in_head = false,
+            Event::Start(Tag::TableRow) => table.rows.push(Vec::new()),
+            Event::Start(Tag::TableCell) => cell = Some(Vec::new()),
+            Event::End(Tag::TableCell) => {
+                let cell = cell.take().unwrap_or_default();
+                match table.rows.last_mut() {
+                    Some(row) if !in_head => row.push(cell),
+                    _ => table.head.push(cell),
+                }
+            }
+            Event::End(Tag::TableRow) => {}
+            event => {
+                if let Some(ref mut cell) = cell {
+                    cell.push(event);
+                }
+            }
+        }
+    }
+    table
+}
+
+impl<'a> Table<'a> {
+    /// Characters of the widest cell of each column.
+    fn column_widths(&self) -> Vec<usize> {
+        let mut widths = vec![0; self.alignments.len()];
+        for row in std::iter::once(&self.head).chain(&self.rows) {
+            for (column, cell) in row.iter().enumerate() {
+                let width: usize = cell
+                    .iter()
+                    .map(|event| match event {
+                        Event::Text(text) | Event::Code(text) => text.chars().count(),
+                        _ => 0,
+                    })
+                    .sum();
+                if let Some(max) = widths.get_mut(column) {
+                    *max = width.max(*max);
+                }
+            }
+        }
+        widths
+    }
+
+    /// Estimated width in characters, the widest cell of each column and
+    /// the space between the columns.
+    fn width(&self) -> usize {
+        let widths = self.column_widths();
+        widths.iter().sum::<usize>() + 2 * widths.len()
+    }
+}
+
+/// Column specification of `tabular`, `l`, `c` or `r` per column.
+pub(crate) fn column_spec(alignments: &[Alignment]) -> String {
+    alignments
+        .iter()
+        .map(|alignment| match alignment {
+            Alignment::None | Alignment::Left => 'l',
+            Alignment::Center => 'c',
+            Alignment::Right => 'r',
+        })
+        .collect()
+}
+
+/// Column specification wrapping the columns wider than `prose_width`, as
+/// `X` columns of `tabularx`, or paragraph columns if `long`. Their share of
+/// the width follows the length of their widest cell.
+pub(crate) fn prose_spec(
+    alignments: &[Alignment],
+    widths: &[usize],
+    cfg: &TablesConfig,
+    long: bool,
+) -> String {
+    let is_prose = |width: usize| width > cfg.prose_width;
+    let prose: Vec<usize> = widths.iter().copied().filter(|w| is_prose(*w)).collect();
+    let total = prose.iter().sum::<usize>().max(1) as f64;
+    // Paragraph columns get what the other columns leave of the text width.
+    let rest = widths
+        .iter()
+        .filter(|width| !is_prose(**width))
+        .map(|width| width + 2)
+        .sum::<usize>() as f64;
+    let available = (1.0 - rest / cfg.text_width.max(1) as f64).max(0.3);
+    alignments
+        .iter()
+        .zip(widths)
+        .map(|(alignment, width)| {
+            let align = match alignment {
+                Alignment::None | Alignment::Left => "\\raggedright",
+                Alignment::Center => "\\centering",
+                Alignment::Right => "\\raggedleft",
+            };
+            if !is_prose(*width) {
+                column_spec(&[*alignment])
+            } else if long {
+                format!(
+                    ">{{{}\\arraybackslash}}p{{{:.2}\\linewidth}}",
+                    align,
+                    available * *width as f64 / total
+                )
+            } else {
+                // The factors of the `X` columns add up to their number.
+                format!(
+                    ">{{{}\\arraybackslash\\hsize={:.2}\\hsize}}X",
+                    align,
+                    prose.len() as f64 * *width as f64 / total
+                )
+            }
+        })
+        .collect()
+}
+
+/// LaTeX around the table environment, laid out as `wide` says.
+fn wrapper(long: bool, wide: WideTable, state: &mut Traversal) -> (&'static str, &'static str) {
+    match (long, wide) {
+        (false, WideTable::None) => ("\\begin{center}\n", "\n\\end{center}"),
+        (false, WideTable::Rotate) => {
+            state.packages.insert("rotating");
+            (
+                "\\begin{sidewaystable}\n\\centering\n",
+                "\n\\end{sidewaystable}",
+            )
+        }
+        (false, WideTable::Shrink) => {
+            state.packages.insert("adjustbox");
+            (
+                "\\begin{center}\n\\begin{adjustbox}{max width=\\textwidth}\n",
+                "\n\\end{adjustbox}\n\\end{center}",
+            )
+        }
+        (true, WideTable::None) => ("", ""),
+        // Pages of a long table turn, in the PDF viewer too.
+        (true, WideTable::Rotate) => {
+            state.packages.insert("pdflscape");
+            ("\\begin{landscape}\n", "\n\\end{landscape}")
+        }
+        // Long tables can not be scaled, only set smaller.
+        (true, WideTable::Shrink) => ("{\\footnotesize\\setlength{\\tabcolsep}{3pt}\n", "}"),
+    }
+}
+
+/// Whether `event` is an HTML line break, `<br>` or `<br/>`.
+fn is_break(event: &Event) -> bool {
+    match event {
+        Event::Html(html) => matches!(
+            html.split_whitespace()
+                .collect::<String>()
+                .to_ascii_lowercase()
+                .as_str(),
+            "<br>" | "<br/>"
+        ),
+        _ => false,
+    }
+}
+
+/// The events of a cell, set as `\makecell` of the template if it breaks
+/// lines by `<br>`, as `\\` would end the row otherwise.
+fn cell<'a>(events: Vec<Event<'a>>, state: &mut Traversal) -> Vec<Event<'a>> {
+    if !events.iter().any(is_break) {
+        return events;
+    }
+    let mut out = vec![state.raw.inline("\\makecell{")];
+    for event in events {
+        if is_break(&event) {
+            out.push(state.raw.inline("\\\\"));
+        } else {
+            out.push(event);
+        }
+    }
+    out.push(state.raw.inline("}"));
+    out
+}
+
+/// The events of `table` as `tabular`, `tabularx` if it has columns of
+/// `prose`, or `longtable` if `long`, in a paragraph of its own so the cells
+/// are converted as any other inline content.
+fn emit<'a>(
+    table: Table<'a>,
+    long: bool,
+    prose: bool,
+    wide: WideTable,
+    state: &mut Traversal,
+) -> Vec<Event<'a>> {
+    let spec = if prose {
+        state.packages.insert("array");
+        prose_spec(
+            &table.alignments,
+            &table.column_widths(),
+            &state.cfg.tables,
+            long,
+        )
+    } else {
+        column_spec(&table.alignments)
+    };
+    let env = match (long, prose) {
+        (true, _) => "longtable",
+        (false, true) => {
+            state.packages.insert("tabularx");
+            "tabularx"
+        }
+        (false, false) => "tabular",
+    };
+    let (open, close) = wrapper(long, wide, state);
+    let mut out = vec![Event::Start(Tag::Paragraph)];
+    let width = if env == "tabularx" {
+        "{\\linewidth}"
+    } else {
+        ""
+    };
+    out.push(state.raw.inline(format!(
+        "{}\\begin{{{}}}{}{{{}}}\n\\toprule\n",
+        open, env, width, spec
+    )));
+    for (idx, head) in table.head.into_iter().enumerate() {
+        if idx > 0 {
+            out.push(state.raw.inline(" & "));
+        }
+        out.push(state.raw.inline("\\textbf{"));
+        out.extend(cell(head, state));
+        out.push(state.raw.inline("}"));
+    }
+    // The header repeats on each page of a long table.
+    out.push(state.raw.inline(if long {
+        " \\\\\n\\midrule\n\\endhead\n\\bottomrule\n\\endlastfoot\n"
+    } else {
+        " \\\\\n\\midrule\n"
+    }));
+    for row in table.rows {
+        for (idx, events) in row.into_iter().enumerate() {
+            if idx > 0 {
+                out.push(state.raw.inline(" & "));
+            }
+            out.extend(cell(events, state));
+        }
+        out.push(state.raw.inline(" \\\\\n"));
+    }
+    out.push(state.raw.inline(if long {
+        format!("\\end{{longtable}}{}", close)
+    } else {
+        format!("\\bottomrule\n\\end{{{}}}{}", env, close)
+    }));
+    out.push(Event::End(Tag::Paragraph));
+    out
+}
+
+/// Turn tables into `tabular` or `longtable` environments.
+pub(crate) fn tables<'a>(events: Vec<Event<'a>>, state: &mut Traversal) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter().peekable();
+    let mut pending = Directive::default();
+    while let Some(event) = events.next() {
+        let start = match event {
+            Event::Start(tag @ Tag::Table(_)) => tag,
+            Event::Html(ref html) if matches!(events.peek(), Some(Event::Start(Tag::Table(_)))) => {
+                match directive(html) {
+                    Some(directive) => pending = directive,
+                    None => out.push(event),
+                }
+                continue;
+            }
+            event => {
+                out.push(event);
+                continue;
+            }
+        };
+        let directive = std::mem::take(&mut pending);
+        let table = take_table(start, &mut events);
+        let long = directive
+            .long
+            .unwrap_or(table.rows.len() > state.cfg.tables.long_rows);
+        let prose = state.cfg.tables.wrap_prose
+            && table
+                .column_widths()
+                .iter()
+                .any(|width| *width > state.cfg.tables.prose_width);
+        let wide = match directive.wide {
+            Some(wide) => wide,
+            // Wrapped columns fit the text width.
+            None if prose => WideTable::None,
+            None if table.width() > state.cfg.tables.text_width => state.cfg.wide_table,
+            None => WideTable::None,
+        };
+        state.capabilities.record("Tables", Outcome::Rendered);
+        out.extend(emit(table, long, prose, wide, state));
+    }
+    out
+}
+
+/// A cell of an HTML table.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HtmlCell {
+    pub(crate) header: bool,
+    pub(crate) colspan: usize,
+    pub(crate) rowspan: usize,
+    pub(crate) alignment: Alignment,
+    // The text of the cell, as LaTeX.
+    pub(crate) tex: String,
+}
+
+/// Text of an HTML element, without its tags and entities, as LaTeX.
+fn html_text(element: ElementRef) -> String {
+    let text = element.text().collect::<String>();
+    escape(&text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// The `text-align` of an inline `style`.
+fn text_align(style: &str) -> Option<&str> {
+    style.split(';').find_map(|declaration| {
+        let (property, value) = declaration.split_once(':')?;
+        property
+            .trim()
+            .eq_ignore_ascii_case("text-align")
+            .then(|| value.trim())
+    })
+}
+
+/// The cells of the HTML table row `row`.
+fn html_row(row: ElementRef) -> Vec<HtmlCell> {
+    row.children()
+        .filter_map(ElementRef::wrap)
+        .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+        .map(|cell| {
+            let span = |name| {
+                cell.value()
+                    .attr(name)
+                    .and_then(|span| span.trim().parse::<usize>().ok())
+                    .unwrap_or(1)
+                    .max(1)
+            };
+            let alignment = cell
+                .value()
+                .attr("align")
+                .or_else(|| cell.value().attr("style").and_then(text_align));
+            HtmlCell {
+                header: cell.value().name() == "th",
+                colspan: span("colspan"),
+                rowspan: span("rowspan"),
+                alignment: match alignment.map(str::to_ascii_lowercase).as_deref() {
+                    Some("center") => Alignment::Center,
+                    Some("right") => Alignment::Right,
+                    _ => Alignment::None,
+                },
+                tex: html_text(cell),
+            }
+        })
+        .collect()
+}
+
+/// The caption and the rows of cells of the HTML `table`, parsed as a
+/// browser would, closing what was left open. Rows of tables nested in
+/// cells are part of the text of those cells.
+pub(crate) fn parse_html_table(table: &str) -> (Option<String>, Vec<Vec<HtmlCell>>) {
+    let fragment = Html::parse_fragment(table);
+    let table = match fragment
+        .root_element()
+        .descendants()
+        .filter_map(ElementRef::wrap)
+        .find(|element| element.value().name() == "table")
+    {
+        Some(table) => table,
+        None => return (None, Vec::new()),
+    };
+    let mut caption = None;
+    let mut rows = Vec::new();
+    for child in table.children().filter_map(ElementRef::wrap) {
+        match child.value().name() {
+            "caption" => caption = Some(html_text(child)),
+            "thead" | "tbody" | "tfoot" => rows.extend(
+                child
+                    .children()
+                    .filter_map(ElementRef::wrap)
+                    .filter(|row| row.value().name() == "tr")
+                    .map(html_row),
+            ),
+            "tr" => rows.push(html_row(child)),
+            _ => {}
+        }
+    }
+    rows.retain(|row| !row.is_empty());
+    (caption.filter(|caption| !caption.is_empty()), rows)
+}
+
+/// Byte ranges of the outermost tables in `html`, by the opening and
+/// closing `tags`. An unclosed table reaches to the end.
+fn outermost_tables(html: &str, tags: &Regex) -> Vec<Range<usize>> {
+    let mut tables = Vec::new();
+    let mut depth = 0_usize;
+    let mut start = 0;
+    for tag in tags.captures_iter(html) {
+        let whole = tag.get(0).expect("Group 0 always matches. qed");
+        if tag[1].is_empty() {
+            if depth == 0 {
+                start = whole.start();
+            }
+            depth += 1;
+        } else if depth > 0 {
+            depth -= 1;
+            if depth == 0 {
+                tables.push(start..whole.end());
+            }
+        }
+    }
+    if depth > 0 {
+        tables.push(start..html.len());
+    }
+    tables
+}
+
+/// LaTeX of the `rows` of an HTML table. Leading rows of header cells only
+/// are the header. Positions covered by a cell spanning rows from above
+/// are left empty, as `\multirow` expects.
+pub(crate) fn html_table_to_tex(rows: &[Vec<HtmlCell>]) -> String {
+    // Lay out the cells on a grid, by the first position they cover.
+    let mut grid: Vec<Vec<Option<&HtmlCell>>> = vec![Vec::new(); rows.len()];
+    let mut covered: Vec<Vec<bool>> = vec![Vec::new(); rows.len()];
+    for (idx, row) in rows.iter().enumerate() {
+        let mut column = 0;
+        for cell in row {
+            while covered[idx].get(column).copied().unwrap_or(false) {
+                column += 1;
+            }
+            // As in browsers, cells span no further than the last row.
+            for covered in &mut covered[idx..(idx + cell.rowspan).min(rows.len())] {
+                covered.resize(covered.len().max(column + cell.colspan), false);
+                covered[column..column + cell.colspan].fill(true);
+            }
+            let positions = &mut grid[idx];
+            positions.resize(positions.len().max(column + 1), None);
+            positions[column] = Some(cell);
+            column += cell.colspan;
+        }
+    }
+    let columns = covered.iter().map(Vec::len).max().unwrap_or_default();
+    let head = rows
+        .iter()
+        .take_while(|row| row.iter().all(|cell| cell.header))
+        .count();
+
+    let mut tex = format!("\\begin{{tabular}}{{{}}}\n\\toprule\n", "l".repeat(columns));
+    for (idx, positions) in grid.iter().enumerate() {
+        if idx == head && head > 0 {
+            tex.push_str("\\midrule\n");
+        }
+        let mut cells = Vec::new();
+        let mut column = 0;
+        while column < columns {
+            let cell = match positions.get(column).copied().flatten() {
+                Some(cell) => cell,
+                None => {
+                    cells.push(String::new());
+                    column += 1;
+                    continue;
+                }
+            };
+            let mut content = if cell.header {
+                format!("\\textbf{{{}}}", cell.tex)
+            } else {
+                cell.tex.clone()
+            };
+            if cell.rowspan > 1 {
+                content = format!("\\multirow{{{}}}{{*}}{{{}}}", cell.rowspan, content);
+            }
+            if cell.colspan > 1 || cell.alignment != Alignment::None {
+                content = format!(
+                    "\\multicolumn{{{}}}{{{}}}{{{}}}",
+                    cell.colspan,
+                    column_spec(&[cell.alignment]),
+                    content
+                );
+            }
+            cells.push(content);
+            column += cell.colspan;
+        }
+        tex.push_str(&cells.join(" & "));
+        tex.push_str(" \\\\\n");
+    }
+    tex.push_str("\\bottomrule\n\\end{tabular}");
+    tex
+}
+
+/// Convert tables written as HTML into `tabular` environments, spanning
+/// cells with `\multicolumn` and `\multirow`.
+pub(crate) fn html_tables<'a>(
+    events: Vec<Event<'a>>,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    let tags = Regex::new(r"(?i)<(/?)table\b[^>]*>")?;
+    let mut findings = Vec::new();
+    let mut out = Vec::with_capacity(events.len());
+    let mut events = events.into_iter().peekable();
+    while let Some(event) = events.next() {
+        let html = match event {
+            Event::Html(html) => join_html(html, &mut events),
+            event => {
+                out.push(event);
+                continue;
+            }
+        };
+        let mut last = 0;
+        for table in outermost_tables(&html, &tags) {
+            keep_html(&html[last..table.start], &mut out);
+            last = table.end;
+            let (caption, rows) = parse_html_table(&html[table.clone()]);
+            if rows.is_empty() {
+                log::warn!("Keeping an HTML table without rows as HTML");
+                findings.push(match caption {
+                    Some(caption) => format!("{}: no rows, kept as HTML", caption),
+                    None => "A table without rows, kept as HTML".to_owned(),
+                });
+                keep_html(&html[table], &mut out);
+                continue;
+            }
+            state.packages.insert("booktabs");
+            state.packages.insert("multirow");
+            state.capabilities.record("HTML tables", Outcome::Rendered);
+            let tabular = html_table_to_tex(&rows);
+            out.extend(state.raw.block(match caption {
+                Some(caption) => format!(
+                    "\\begin{{table}}[htbp]\n\\centering\n{}\n\\caption{{{}}}\n\\end{{table}}",
+                    tabular, caption
+                ),
+                None => format!("\\begin{{center}}\n{}\n\\end{{center}}", tabular),
+            }));
+        }
+        keep_html(&html[last..], &mut out);
+    }
+    let chapter = state
+        .chapter
+        .map(|chapter| chapter.name.as_str())
+        .unwrap_or_default();
+    state.report.add(SECTION, chapter, findings);
+    Ok(out)
+}
@@ -0,0 +1,257 @@
+//! Markers of templates, which the generated content is inserted at.
+//!
+//! Besides the book content, custom templates may position the front
+//! matter, the back matter, single parts and the preamble additions with
+//! markers of their own, which fall back to the content marker, or
+//! `\begin{document}` for the preamble.
+
+use color_eyre::eyre::bail;
+use fs_err as fs;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Marker the book content is inserted after.
+pub(crate) const BEGIN: &str = "%% mdbook-tectonic begin";
+
+/// Marker of the prefix chapters, those before the first numbered one.
+pub(crate) const FRONTMATTER: &str = "%% mdbook-tectonic frontmatter";
+
+/// Marker of the suffix chapters and appendices like the answer key.
+pub(crate) const BACKMATTER: &str = "%% mdbook-tectonic backmatter";
+
+/// Marker of the index, which falls back to the back matter.
+pub(crate) const INDEX: &str = "%% mdbook-tectonic index";
+
+/// Marker of the packages and settings the content requires.
+pub(crate) const PREAMBLE: &str = "%% mdbook-tectonic preamble";
+
+/// Marker of the chapters of the part titled `title` in `SUMMARY.md`.
+pub(crate) fn part(title: &str) -> String {
+    format!("%% mdbook-tectonic part: {}", title)
+}
+
+/// The marker content is inserted at, `wanted` if `template` has it, else
+/// the content marker.
+pub(crate) fn or_begin<'m>(template: &str, wanted: &'m str) -> &'m str {
+    if template.lines().any(|line| line.trim() == wanted) {
+        wanted
+    } else {
+        BEGIN
+    }
+}
+
+/// Marker of a chapter in `part`, the prefix and suffix chapters being
+/// unnumbered ones before and after the first numbered chapter.
+pub(crate) fn chapter_marker(
+    template: &str,
+    numbered: bool,
+    after_numbered: bool,
+    part: Option<&str>,
+) -> String {
+    let wanted = match part {
+        _ if !numbered && !after_numbered => FRONTMATTER.to_owned(),
+        _ if !numbered => BACKMATTER.to_owned(),
+        Some(title) => self::part(title),
+        None => BEGIN.to_owned(),
+    };
+    or_begin(template, &wanted).to_owned()
+}
+
+/// Definitions of `\mdbookfrontmatter`, switching to `pagestyle` and roman
+/// page numbers for the prefix chapters, and `\mdbookmainmatter`, back to
+/// arabic numbers and the page style the template had set. That style is
+/// saved rather than named, whichever it was.
+pub(crate) fn matter_preamble(pagestyle: &str) -> String {
+    format!(
+        r"\makeatletter
+\newcommand{{\mdbookfrontmatter}}{{%
+  \clearpage
+  \let\mdbook@oddhead\@oddhead\let\mdbook@evenhead\@evenhead
+  \let\mdbook@oddfoot\@oddfoot\let\mdbook@evenfoot\@evenfoot
+  \pagestyle{{{}}}\pagenumbering{{roman}}}}
+\newcommand{{\mdbookmainmatter}}{{%
+  \clearpage
+  \let\@oddhead\mdbook@oddhead\let\@evenhead\mdbook@evenhead
+  \let\@oddfoot\mdbook@oddfoot\let\@evenfoot\mdbook@evenfoot
+  \pagenumbering{{arabic}}}}
+\makeatother
+",
+        pagestyle
+    )
+}
+
+/// Position right after the line of `marker`, preferably on a line of its
+/// own, as one part title may start with another. Markers are comments,
+/// anything inserted on their line would be commented out.
+pub(crate) fn find(template: &str, marker: &str) -> Option<usize> {
+    let mut pos = 0;
+    for line in template.split_inclusive('\n') {
+        if line.trim() == marker {
+            return Some(pos + line.len());
+        }
+        pos += line.len();
+    }
+    template.find(marker).map(|pos| pos + marker.len())
+}
+
+/// Content collected per marker, in the order of first appearance.
+#[derive(Debug, Default)]
+pub(crate) struct Content {
+    regions: Vec<(String, String)>,
+}
+
+impl Content {
+    /// The content collected for `marker`, empty if none yet.
+    pub(crate) fn at(&mut self, marker: &str) -> &mut String {
+        let idx = match self.regions.iter().position(|(m, _)| m == marker) {
+            Some(idx) => idx,
+            None => {
+                self.regions.push((marker.to_owned(), String::new()));
+                self.regions.len() - 1
+            }
+        };
+        &mut self.regions[idx].1
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.regions
+            .iter()
+            .all(|(_, content)| content.trim().is_empty())
+    }
+
+    /// All content, in order.
+    pub(crate) fn concat(&self) -> String {
+        self.regions
+            .iter()
+            .map(|(_, content)| content.as_str())
+            .collect()
+    }
+
+    pub(crate) fn into_regions(self) -> impl Iterator<Item = (String, String)> {
+        self.regions.into_iter()
+    }
+
+    /// The content right after the markers in `template`, as pieces rather
+    /// than copied into it.
+    pub(crate) fn assemble(self, template: &str) -> color_eyre::Result<Assembly<'_>> {
+        let mut inserts = Vec::with_capacity(self.regions.len());
+        for (marker, content) in self.regions {
+            match find(template, &marker) {
+                Some(pos) => inserts.push((pos, content)),
+                None => bail!("Missing injection point `{}` in tex template", marker),
+            }
+        }
+        inserts.sort_by_key(|(pos, _)| *pos);
+        let mut pieces = Vec::with_capacity(2 * inserts.len() + 1);
+        let mut last = 0;
+        for (pos, content) in inserts {
+            pieces.push(Cow::Borrowed(&template[last..pos]));
+            pieces.push(Cow::Owned(content));
+            last = pos;
+        }
+        pieces.push(Cow::Borrowed(&template[last..]));
+        Ok(Assembly { pieces })
+    }
+}
+
+/// A document as the pieces of the template and the content between them,
+/// which for large books is written out piece by piece, never held in one
+/// string, nor the template shifted for each insertion.
+#[derive(Debug)]
+pub(crate) struct Assembly<'t> {
+    pieces: Vec<Cow<'t, str>>,
+}
+
+impl Assembly<'_> {
+    /// Length of the document in bytes.
+    pub(crate) fn len(&self) -> usize {
+        self.pieces.iter().map(|piece| piece.len()).sum()
+    }
+
+    pub(crate) fn write_to(&self, out: &mut dyn Write) -> io::Result<()> {
+        for piece in &self.pieces {
+            out.write_all(piece.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Assembly<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.pieces.iter().try_for_each(|piece| f.write_str(piece))
+    }
+}
+
+/// Markers of a template, whether the build requires them, and what they
+/// are for.
+pub(crate) const MARKERS: &[(&str, bool, &str)] = &[
+    (BEGIN, true, "the book content is inserted after it"),
+    (
+        r"\begin{document}",
+        true,
+        "packages the content requires are loaded before it",
+    ),
+    (r"\title{}", false, "filled with the title of the book"),
+    (r"\author{}", false, "filled with the authors of the book"),
+    (r"\date{}", false, "filled with the `date` option"),
+];
+
+/// The markers missing in `template`, with their purpose.
+pub(crate) fn missing(template: &str) -> Vec<&'static (&'static str, bool, &'static str)> {
+    MARKERS
+        .iter()
+        .filter(|(marker, ..)| !template.contains(marker))
+        .collect()
+}
+
+/// Check the custom template at `path` carries all markers, listing the
+/// ones missing. Placeholders only lead to a warning, a template may as
+/// well spell out the title.
+pub(crate) fn validate(template: &str, path: &str) -> color_eyre::Result<()> {
+    let missing = missing(template);
+    let list = |required: bool| {
+        missing
+            .iter()
+            .filter(|(_, r, _)| *r == required)
+            .map(|(marker, _, purpose)| format!("\n  `{}`: {}", marker, purpose))
+            .collect::<String>()
+    };
+    let placeholders = list(false);
+    if !placeholders.is_empty() {
+        log::warn!(
+            "Custom template {} lacks the placeholders:{}",
+            path,
+            placeholders
+        );
+    }
+    let required = list(true);
+    if !required.is_empty() {
+        bail!(
+            "Custom template {} lacks the markers:{}\nSee \"Custom templates\" in the documentation for all markers.",
+            path,
+            required
+        );
+    }
+    Ok(())
+}
+
+/// Copy the files within `dir` into `target`, keeping their paths relative
+/// to it, so the template finds its style and class files, logos and fonts.
+/// Returns the number of files copied.
+pub(crate) fn copy_dir(dir: &Path, target: &Path) -> color_eyre::Result<usize> {
+    let mut copied = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let target = target.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            fs::create_dir_all(&target)?;
+            copied += copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+            copied += 1;
+        }
+    }
+    Ok(copied)
+}
@@ -9,7 +9,12 @@ fn test_traverse_markdown() {
     // create a temporary directory in /tmp/
     fs::create_dir_all(imgpath.parent().unwrap()).expect("failure while creating testdirs");
     // touch the mock png file
-    let _ = match OpenOptions::new().create(true).write(true).open(imgpath) {
+    let _ = match OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(imgpath)
+    {
         Ok(_) => Ok(()),
         Err(e) => Err(e),
     };
@@ -21,11 +26,2259 @@ fn test_traverse_markdown() {
         mdbook::Config::default(),
         Path::new("/tmp/dest/"),
     );
-    let new_content = traverse_markdown(content, &path, &context).unwrap();
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let new_content = traverse_markdown(content, &path, true, &mut state).unwrap();
     assert_eq!("![123](images/chap/xyz.png)", new_content);
     let respath = Path::new("/tmp/dest/images/chap/xyz.png");
     assert!(respath.exists());
+    // Included relative to the `.tex` file, where the image is copied to.
+    let tex = markdown_to_tex(new_content).unwrap();
+    assert!(tex.contains("\\includegraphics[width=\\textwidth]{images/chap/xyz.png}"));
 
     fs::remove_dir_all("/tmp/test").unwrap();
     fs::remove_dir_all("/tmp/dest").unwrap();
 }
+
+/// A render context of `book`, rooted in a directory of its own, which is
+/// removed once the returned handle is dropped.
+fn fixture(book: mdbook::book::Book) -> (tempfile::TempDir, RenderContext) {
+    let dir = tempfile::tempdir().unwrap();
+    let context = RenderContext::new(
+        dir.path(),
+        book,
+        mdbook::Config::default(),
+        dir.path().join("book"),
+    );
+    (dir, context)
+}
+
+/// The events of `markdown`.
+fn parse(markdown: &str) -> Vec<Event<'_>> {
+    Parser::new_ext(markdown, Options::all()).collect()
+}
+
+/// The text of `events` with the raw fragments restored, the LaTeX they
+/// are printed as.
+fn restored(events: &[Event], state: &Traversal) -> String {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Text(text) => Some(state.raw.restore(text)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_unnumbered_headings() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let events = parse("# Preface {#preface .unnumbered}\n\n## Numbered\n");
+    let events = sections::unnumbered_headings(events, true, &mut state).unwrap();
+    let tex = restored(&events, &state);
+    assert!(tex.starts_with(
+        "\\chapter*{Preface}\n\\phantomsection\n\\label{preface}\n\\pdfbookmark[0]{Preface}{"
+    ));
+    assert!(tex.ends_with("Numbered"));
+
+    // All headings of an unnumbered chapter, listed in the contents if asked.
+    let cfg = LatexConfig {
+        unnumbered_toc: true,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let events = parse("## Thanks\n");
+    let events = sections::unnumbered_headings(events, false, &mut state).unwrap();
+    let tex = restored(&events, &state);
+    assert!(tex.starts_with("\\section*{"));
+    assert!(tex.contains("}\n\\phantomsection\n\\addcontentsline{toc}{section}{"));
+}
+
+#[test]
+fn test_details() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let events = parse("<details>\n\nHidden text.\n\n</details>\n");
+    let events = html::details(events, &mut state).unwrap();
+    let tex = restored(&events, &state);
+    assert_eq!(
+        tex,
+        "\\begin{framed}\n\\noindent\\textbf{Details}\\parHidden text.\\end{framed}"
+    );
+    let events = parse("<details><summary>The *real* story</summary>\n\nHidden.\n\n</details>\n");
+    let events = html::details(events, &mut state).unwrap();
+    let tex = restored(&events, &state);
+    assert!(tex.starts_with("\\begin{framed}\n\\noindent\\textbf{The \\emph{real} story}\\par"));
+
+    let cfg = LatexConfig {
+        details: Details::Omit,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let events = parse(
+        "Before\n\n<details><summary>Spoiler</summary>\n\nHidden text.\n\n</details>\n\nAfter\n",
+    );
+    let events = html::details(events, &mut state).unwrap();
+    let tex = restored(&events, &state);
+    assert!(tex.starts_with("Before\\emph{The collapsed section “"));
+    assert!(tex.ends_with("” is omitted in print.}After"));
+    assert!(!tex.contains("Hidden"));
+}
+
+#[test]
+fn test_tabs_flattened() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig {
+        tabs: vec!["Rust".to_owned()],
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let content = r#"Intro
+{{#tabs }}
+{{#tab name="Rust" }}
+`let x = 1;`
+{{#endtab }}
+{{#tab name="Python" }}
+`x = 1`
+{{#endtab }}
+{{#endtabs }}
+\{{#tabs}}, `\{{#tabs}}{{#endtabs}}`
+```markdown
+{{#tabs }}{{#endtabs }}
+```"#;
+    let flat = shortcodes::tabs(content, &mut state).unwrap();
+    let flat = shortcodes::unescape(&flat).unwrap();
+    assert_eq!(
+        flat,
+        "Intro\n\n\n**Rust:**\n\n`let x = 1;`\n\n\n{{#tabs}}, `\\{{#tabs}}{{#endtabs}}`\n\
+         ```markdown\n{{#tabs }}{{#endtabs }}\n```"
+    );
+}
+
+#[test]
+fn test_conditionals() {
+    let content = "a<!-- only:pdf -->b<!-- /only --><!-- not: epub, print -->c<!-- /not -->\
+                   <!-- only:epub -->d<!-- /only --><!-- not:html -->e<!-- /not -->";
+    assert_eq!(comments::conditionals(content).unwrap(), "abe");
+}
+
+#[test]
+fn test_image_placement() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let events = parse("![](map.png){.fullpage}\n");
+    let events = images::placement(events, &mut state).unwrap();
+    let tex = restored(&events, &state);
+    assert_eq!(
+        tex,
+        "\\begin{figure}[p]\n\\centering\n\\includegraphics[width=\\textwidth,height=0.9\\textheight,keepaspectratio]{map.png}\n\\end{figure}"
+    );
+
+    let events = parse("![The harbour](harbour.png){.spread} in 1850\n");
+    let events = images::placement(events, &mut state).unwrap();
+    let tex = restored(&events, &state);
+    assert!(tex.starts_with("\\clearpage\n\\ifodd\\value{page}\\hbox{}\\newpage\\fi\n"));
+    assert!(tex.ends_with(
+        "\\includegraphics[width=\\textwidth,height=\\textheight,keepaspectratio]{harbour.png}\\end{center}\n\\clearpage in 1850"
+    ));
+    assert!(state.packages.contains("caption"));
+
+    // Images without a placement class are left alone.
+    let events = parse("![](map.png){.wide}\n");
+    let placed = images::placement(events.clone(), &mut state).unwrap();
+    assert_eq!(placed, events);
+}
+
+#[test]
+fn test_thumb_tabs() {
+    assert_eq!(
+        thumbtabs::select(3, 5),
+        "\\setcounter{thumbtab}{3}\\setcounter{thumbtabchapter}{3}"
+    );
+    // Past the last slot, tabs start over at the top, numbered on.
+    assert_eq!(
+        thumbtabs::select(14, 20),
+        "\\setcounter{thumbtab}{2}\\setcounter{thumbtabchapter}{14}"
+    );
+    assert!(thumbtabs::preamble(5)
+        .contains("\\setlength{\\thumbtabheight}{\\dimexpr(\\paperheight-2cm)/5\\relax}"));
+    assert!(thumbtabs::preamble(20).contains("\\paperheight-2cm)/12\\relax"));
+}
+
+#[test]
+fn test_code_info() {
+    let info = code::Info::parse(r#"rust,ignore caption="Parsing, looping",label=lst:parse"#);
+    assert_eq!(info.language.as_deref(), Some("rust"));
+    assert!(info.has_flag("ignore"));
+    assert_eq!(
+        info.attributes,
+        vec![
+            ("caption".to_owned(), "Parsing, looping".to_owned()),
+            ("label".to_owned(), "lst:parse".to_owned()),
+        ]
+    );
+    assert_eq!(code::Info::parse("Rust").listings_language(), "rust");
+    assert_eq!(code::Info::parse("brainfuck").listings_language(), "text");
+}
+
+#[test]
+fn test_readability() {
+    let (dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig {
+        readability: true,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let events = parse("The cat sat. The dog was chased by it!\n\n```\nnot = counted\n```\n");
+    metrics::measure(&events, &mut state).unwrap();
+    let path = dir.path().join("report.md");
+    state.report.write(&path, &state.labels).unwrap();
+    let report = fs::read_to_string(path).unwrap();
+    assert!(report.contains("\n## Readability\n"));
+    assert!(report.contains("- Words: 9\n- Sentences: 2\n- Average sentence length (words): 4.5\n"));
+    assert!(report.contains("- Passive voice (sentences): 1, 50%\n"));
+}
+
+#[test]
+fn test_labels_override() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("labels.toml");
+    fs::write(
+        &path,
+        "[de]\nquestion = \"Frage\"\nanswer-key = \"Lösungen\"\n[de-CH]\nanswer-key = \"Antworten\"\n",
+    )
+    .unwrap();
+    let labels = labels::Labels::load(&path, Some("de-CH")).unwrap();
+    assert_eq!(labels.question, "Frage");
+    assert_eq!(labels.answer_key, "Antworten");
+    assert_eq!(labels.see_page, "siehe Seite");
+    assert_eq!(labels.video, "Video");
+}
+
+#[test]
+fn test_ruby() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let content = "Read <ruby>漢<rp>(</rp><rt>かん</rt><rp>)</rp>字<rt>じ</rt></ruby> aloud.";
+    let events = parse(content);
+    let events = html::ruby(events, &mut state).unwrap();
+    let text = restored(&events, &state);
+    assert_eq!(text, "Read \\ruby{漢}{かん}\\ruby{字}{じ} aloud.");
+    assert!(state.packages.contains("pxrubrica"));
+
+    // Unclosed, the element ends with its paragraph, left as html.
+    let events = parse("Read <ruby>漢<rt>かん</rt> aloud.\n\nThe next paragraph.");
+    let ruby = html::ruby(events.clone(), &mut state).unwrap();
+    assert_eq!(ruby, events);
+}
+
+#[test]
+fn test_line_breaking_scripts() {
+    assert_eq!(
+        scripts::line_breaking(Some("th-TH"), Engine::Tectonic).as_deref(),
+        Some("\\XeTeXlinebreaklocale \"th\"\n\\XeTeXlinebreakskip = 0pt plus 1pt\n")
+    );
+    assert_eq!(
+        scripts::line_breaking(Some("km"), Engine::Lualatex).as_deref(),
+        Some("\\babelprovide[import, main]{khmer}\n")
+    );
+    assert_eq!(scripts::line_breaking(Some("de"), Engine::Tectonic), None);
+    assert_eq!(scripts::line_breaking(None, Engine::Tectonic), None);
+}
+
+#[test]
+fn test_tex_diff() {
+    let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\n";
+    let new = "a\nb\nc\nd\nE\nf\ng\nh\ni\nj\n";
+    assert_eq!(snapshot::unified(old, old, "old", "new"), None);
+    assert_eq!(
+        snapshot::unified(old, new, "old", "new").unwrap(),
+        "--- old\n+++ new\n@@ -2,8 +2,9 @@\n b\n c\n d\n-e\n+E\n f\n g\n h\n i\n+j\n"
+    );
+}
+
+#[test]
+fn test_outline() {
+    let mut book = mdbook::book::Book::new();
+    book.push_item(mdbook::book::Chapter::new(
+        "Intro",
+        "# Intro\n\n## The `main`   function\n\n#### Too deep\n\n## Setup\n".to_owned(),
+        "intro.md",
+        Vec::new(),
+    ));
+    assert_eq!(
+        outline::headings("# Intro\n\n## The `main`   function\n\n#### Too deep\n").unwrap(),
+        vec![(1, "Intro".to_owned()), (2, "The main function".to_owned())]
+    );
+
+    let document: outline::Document = serde_json::from_str(
+        r#"{"outlines": [{"title": "Intro", "destpageposfrom1": 1, "kids": [
+            {"title": "The main function", "kids": []},
+            {"title": "The main function", "kids": []}
+        ]}]}"#,
+    )
+    .unwrap();
+    let findings = outline::compare(&document.outlines, &book, &LatexConfig::default()).unwrap();
+    assert_eq!(
+        findings,
+        vec![
+            "missing bookmark at level 2: Setup",
+            "duplicated bookmark at level 2: The main function (2 times, expected 1)"
+        ]
+    );
+}
+
+#[test]
+fn test_attachments() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join("code/sub")).unwrap();
+    fs::write(root.join("code/sub/b.rs"), "").unwrap();
+    fs::write(root.join("code/a.rs"), "").unwrap();
+    fs::write(root.join("data_set.csv"), "").unwrap();
+
+    let files = attachments::files(root, &["code".to_owned(), "data_set.csv".to_owned()]).unwrap();
+    assert_eq!(
+        files,
+        vec![
+            root.join("code/a.rs"),
+            root.join("code/sub/b.rs"),
+            root.join("data_set.csv")
+        ]
+    );
+    assert!(attachments::files(root, &["gone.rs".to_owned()]).is_err());
+
+    let tex = attachments::appendix(&files[2..], root, "Files & data");
+    assert_eq!(
+        tex,
+        format!(
+            "\\section*{{Files \\& data}}\n\\phantomsection\n\\addcontentsline{{toc}}{{section}}{{Files \\& data}}\n\\begin{{itemize}}\n\\item \\textattachfile{{{}}}{{\\texttt{{data\\_set.csv}}}}\n\\end{{itemize}}\n",
+            paths::to_tex(&files[2])
+        )
+    );
+}
+
+#[test]
+fn test_source_links() {
+    let source = "```rust\n{{#include ../../examples/main.rs:setup}}\n```\n\n```\nplain\n```\n";
+    let content = "```rust\nfn main() {}\n```\n\n```\nplain\n```\n";
+    let mut book = mdbook::book::Book::new();
+    book.push_item(mdbook::book::Chapter::new(
+        "Setup",
+        content.to_owned(),
+        "guide/setup.md",
+        Vec::new(),
+    ));
+    let (dir, context) = fixture(book.clone());
+    fs::create_dir_all(dir.path().join("src/guide")).unwrap();
+    fs::create_dir_all(dir.path().join("examples")).unwrap();
+    fs::write(dir.path().join("src/guide/setup.md"), source).unwrap();
+    fs::write(
+        dir.path().join("examples/main.rs"),
+        "// ANCHOR: setup\nfn main() {}\n// ANCHOR_END: setup\n",
+    )
+    .unwrap();
+
+    let cfg = LatexConfig {
+        source_url: Some("https://example.com/blob/{rev}/{path}#L{start}".to_owned()),
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    state.chapter = match book.iter().next() {
+        Some(mdbook::BookItem::Chapter(ch)) => Some(ch),
+        _ => unreachable!(),
+    };
+    state.revision = Some("abc".to_owned());
+    let events = code::source_links(parse(content), &mut state).unwrap();
+    let tex = restored(&events, &state);
+    assert_eq!(
+        tex,
+        "fn main() {}\n{\\footnotesize\\hfill\\href{https://example.com/blob/abc/examples/main.rs\\#L2}{\\texttt{examples/main.rs:2}}}plain\n"
+    );
+}
+
+#[test]
+fn test_check_examples() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig {
+        check_examples: true,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let content = "```rust\n# use std::fmt::Write;\nlet mut s = String::new();\nwrite!(s, \"ok\").unwrap();\n```\n\n\
+                   ```rust,edition2021\nlet x: u32 = \"no\";\n```\n\n\
+                   ```rust,compile_fail\nlet x: u32 = \"no\";\n```\n";
+    let events = parse(content);
+    code::check_examples(events, &mut state).unwrap();
+    assert_eq!(state.report.count(code::SECTION), 1);
+}
+
+#[test]
+fn test_missing_image() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let events = Parser::new_ext(
+        "Before ![The harbour](harbour_1850.png) after",
+        Options::all(),
+    )
+    .collect::<Vec<_>>();
+    let events = images::resolve_all(events, Path::new(""), &mut state).unwrap();
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, Event::Start(Tag::Image(..)))));
+    let tex = restored(&events, &state);
+    assert!(tex.contains("\\texttt{harbour\\_1850.png}\\\\The harbour"));
+    assert_eq!(state.report.count(images::SECTION), 1);
+
+    let events = parse("See ![The *harbour*](https://example.com/harbour.png#1850) here.");
+    let events = images::resolve_all(events, Path::new(""), &mut state).unwrap();
+    let tex = restored(&events, &state);
+    assert_eq!(
+        tex,
+        "See The harbour (\\url{https://example.com/harbour.png\\#1850}) here."
+    );
+    assert_eq!(state.report.count(images::SECTION), 1);
+    assert_eq!(state.report.count(images::REMOTE_SECTION), 1);
+}
+
+#[test]
+fn test_image_target() {
+    let mut cfg = LatexConfig {
+        image_dir: "assets".to_owned(),
+        ..Default::default()
+    };
+    let mut assets = images::Assets::default();
+    let source = images::source("./figures/50%_done.png", Path::new("chap"));
+    assert_eq!(
+        assets.insert(source.clone(), b"png", "Intro", &cfg),
+        Path::new("assets/chap/figures/50--done.png")
+    );
+    assert_eq!(
+        assets.insert(
+            images::source("/chap/figures/50# done.png", Path::new("")),
+            b"png",
+            "Intro",
+            &cfg
+        ),
+        Path::new("assets/chap/figures/50--done-2.png")
+    );
+    assert_eq!(
+        assets.get(&source, "Harbour"),
+        Some(Path::new("assets/chap/figures/50--done.png"))
+    );
+    let manifest = tempfile::NamedTempFile::new().unwrap();
+    assets.write(manifest.path()).unwrap();
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(manifest.path()).unwrap()).unwrap();
+    assert_eq!(
+        manifest[1]["chapters"],
+        serde_json::json!(["Harbour", "Intro"])
+    );
+    assert_eq!(manifest[0]["output"], "assets/chap/figures/50--done-2.png");
+
+    cfg.image_layout = ImageLayout::Flatten;
+    let mut assets = images::Assets::default();
+    let flattened = assets.insert(source, b"png", "Intro", &cfg);
+    assert_eq!(flattened.parent(), Some(Path::new("assets")));
+    assert_matches!(flattened.extension(), Some(extension) if extension == "png");
+    assert_eq!(
+        flattened,
+        assets.insert(PathBuf::from("other.png"), b"png", "Intro", &cfg)
+    );
+}
+
+#[test]
+fn test_image_resolution() {
+    let root = tempfile::tempdir().unwrap();
+    let outside = tempfile::tempdir().unwrap();
+    fs::create_dir_all(root.path().join("src/Chap")).unwrap();
+    fs::write(root.path().join("src/Chap/Image.PNG"), b"png").unwrap();
+    fs::write(outside.path().join("secret.png"), b"png").unwrap();
+    let context = RenderContext::new(
+        root.path(),
+        mdbook::book::Book::new(),
+        mdbook::Config::default(),
+        root.path().join("book"),
+    );
+    let cfg = LatexConfig::default();
+    let state = Traversal::new(&context, &cfg);
+    assert_eq!(
+        images::resolve(Path::new("chap/image.png"), &state).unwrap(),
+        Some(PathBuf::from("Chap/Image.PNG"))
+    );
+    assert_eq!(
+        images::resolve(Path::new("chap/other.png"), &state).unwrap(),
+        None
+    );
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(
+            outside.path().join("secret.png"),
+            root.path().join("src/secret.png"),
+        )
+        .unwrap();
+        assert!(images::resolve(Path::new("secret.png"), &state).is_err());
+    }
+}
+
+#[test]
+fn test_license_sidecar() {
+    assert_eq!(
+        licenses::License::parse_sidecar(
+            "SPDX-FileCopyrightText: 1850 Jane Doe\nSPDX-License-Identifier: CC-BY-4.0\n"
+        ),
+        licenses::License {
+            license: "CC-BY-4.0".to_owned(),
+            author: Some("1850 Jane Doe".to_owned()),
+            source: None,
+        }
+    );
+    assert_eq!(
+        licenses::License::parse_sidecar("Public domain\n").license,
+        "Public domain"
+    );
+}
+
+#[test]
+fn test_split_chapters() {
+    let mut book = mdbook::book::Book::new();
+    for (name, content) in [
+        ("Intro", "# Intro\n\n## Details\n"),
+        ("Notes", "No heading.\n"),
+        ("Usage & more", "# Usage\n"),
+    ] {
+        book.push_item(mdbook::book::Chapter::new(
+            name,
+            content.to_owned(),
+            format!("{}.md", name),
+            Vec::new(),
+        ));
+    }
+    let document: outline::Document = serde_json::from_str(
+        r#"{"outlines": [
+            {"title": "Intro", "destpageposfrom1": 1, "kids": [
+                {"title": "Details", "destpageposfrom1": 2, "kids": []}
+            ]},
+            {"title": "Usage", "destpageposfrom1": 4, "kids": []}
+        ], "pages": [{}, {}, {}, {}, {}]}"#,
+    )
+    .unwrap();
+    let ranges = pages::ranges(&document, &book, &LatexConfig::default()).unwrap();
+    assert_eq!(
+        ranges
+            .iter()
+            .map(|range| (range.chapter.as_str(), range.first, range.last))
+            .collect::<Vec<_>>(),
+        vec![("Intro", 1, 3), ("Usage & more", 4, 5)]
+    );
+    assert_eq!(
+        pages::part(Path::new("chapters"), 1, &ranges[1]),
+        Path::new("chapters/02-Usage---more.pdf")
+    );
+}
+
+#[test]
+fn test_imposition() {
+    assert_eq!(
+        imposition::document(Path::new("book.pdf"), Imposition::Booklet),
+        "\\documentclass{article}\n\\usepackage{pdfpages}\n\\begin{document}\n\\includepdf[pages=-,booklet=true,landscape]{book.pdf}\n\\end{document}\n"
+    );
+    assert!(
+        imposition::document(Path::new("book.pdf"), Imposition::TwoUp)
+            .contains("\\includepdf[pages=-,nup=2x1,landscape]{book.pdf}")
+    );
+    assert_eq!(imposition::suffix(Imposition::TwoUp), "2up");
+}
+
+#[test]
+fn test_slides() {
+    let mut book = mdbook::book::Book::new();
+    book.push_item(mdbook::book::Chapter::new(
+        "Setup",
+        "# Setup\n\nSome prose.\n\n## Install\n\n### With cargo\n\n- fetch\n- build\n\n## Empty\n\nJust prose.\n".to_owned(),
+        "setup.md",
+        Vec::new(),
+    ));
+    let cfg = LatexConfig::default();
+    let slides = slides::document(&book, &cfg, "Book", "Jane").unwrap();
+    assert!(slides.contains("\\section{Setup}"));
+    assert!(slides.contains("\\begin{frame}{Install}{With cargo}"));
+    assert!(!slides.contains("{Empty}"));
+}
+
+#[test]
+fn test_profiles() {
+    let cfg: LatexConfig = toml::from_str("profile = \"ereader\"\n").unwrap();
+    assert_eq!(cfg.profile, Some(Profile::Ereader));
+    let tex = profile::preamble(Profile::Ereader);
+    assert!(tex.starts_with(
+        "\\usepackage{geometry}\n\\geometry{paperwidth=90mm,paperheight=120mm,margin=0mm}\n"
+    ));
+    assert!(tex.contains("\\renewcommand\\normalsize{\\@setfontsize\\normalsize{12}{15}}\n"));
+
+    // Nothing is set below 12pt.
+    let tex = profile::preamble(Profile::LargePrint);
+    assert!(tex.contains("\\renewcommand\\normalsize{\\@setfontsize\\normalsize{14}{21}}\n"));
+    assert!(tex.contains("\\renewcommand\\tiny{\\@setfontsize\\tiny{12}{18}}\n"));
+    assert!(tex.contains("\\lstset{basicstyle=\\normalsize\\ttfamily,"));
+
+    // The font depends on what is installed, the spacing does not.
+    let tex = profile::preamble(Profile::Dyslexia);
+    assert!(tex.ends_with(
+        "\\usepackage{ragged2e}\n\\AtBeginDocument{\\RaggedRight}\n\\linespread{1.3}\n"
+    ));
+    match profile::DYSLEXIA_FONTS
+        .iter()
+        .find(|family| profile::installed(family))
+    {
+        Some(font) => assert!(tex.starts_with(&format!(
+            "\\setmainfont{{{}}}[LetterSpace=4,WordSpace=1.4]\n",
+            font
+        ))),
+        None => assert!(!tex.contains("\\setmainfont")),
+    }
+}
+
+#[test]
+fn test_env_overrides() {
+    let mut config: toml::Value = toml::from_str(
+        "pdf = true\n[todo]\nenable = true\n[profiles.release.todo]\nstrict = false\nmarkers = [\"TODO\"]\n",
+    )
+    .unwrap();
+    assert!(overrides::select(&mut config.clone(), Some("draft")).is_err());
+    overrides::select(&mut config, Some("release")).unwrap();
+    overrides::apply(
+        &mut config,
+        vec![
+            ("MDBOOK_LATEX__PDF".to_owned(), "false".to_owned()),
+            ("MDBOOK_LATEX__TODO__STRICT".to_owned(), "true".to_owned()),
+            ("MDBOOK_LATEX__ENGINE".to_owned(), "lualatex".to_owned()),
+            ("MDBOOK_LATEX__IMAGE_DIR".to_owned(), "figures".to_owned()),
+            ("MDBOOK_OTHER__PDF".to_owned(), "true".to_owned()),
+        ]
+        .into_iter(),
+    );
+    let cfg: LatexConfig = config.try_into().unwrap();
+    assert!(!cfg.pdf);
+    assert!(cfg.todo.enable && cfg.todo.strict);
+    assert_eq!(cfg.todo.markers, ["TODO"]);
+    assert_eq!(cfg.engine, Some(Engine::Lualatex));
+    assert_eq!(cfg.image_dir, "figures");
+}
+
+#[test]
+fn test_doctor() {
+    assert!(doctor::required(&LatexConfig::default()).is_empty());
+    let cfg = LatexConfig {
+        page_map: true,
+        split_chapters: true,
+        check_examples: true,
+        source_url: Some("https://example.com/{path}".to_owned()),
+        ..Default::default()
+    };
+    assert_eq!(doctor::required(&cfg), ["qpdf", "rustc", "git"]);
+
+    // A broken config is never ready.
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("book.toml"),
+        "[output.latex]\nengine = \"pdftex\"\n",
+    )
+    .unwrap();
+    assert!(!doctor::run(dir.path()).unwrap());
+}
+
+#[test]
+fn test_footnotes() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let events = Parser::new_ext(
+        "Ships[^ships], more ships[^ships], boats[^boats].\n\n[^ships]: First.\n\n    Second.\n",
+        Options::all(),
+    )
+    .collect::<Vec<_>>();
+    let events = inline::footnotes(events, &mut state).unwrap();
+    assert!(!events.iter().any(|event| matches!(
+        event,
+        Event::FootnoteReference(_) | Event::Start(Tag::FootnoteDefinition(_))
+    )));
+    let tex = restored(&events, &state);
+    let label = ids::Ids::new("").next("footnote");
+    assert_eq!(tex.matches("\\footnote{").count(), 1);
+    assert!(tex.contains(&format!("\\label{{{}}}}}", label)));
+    assert!(tex.contains(&format!("\\textsuperscript{{\\ref{{{}}}}}", label)));
+    assert!(tex.contains("[^boats]"));
+}
+
+#[test]
+fn test_build_manifest() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("Book.pdf"), "%PDF").unwrap();
+    fs::create_dir_all(dir.path().join("chapters/Book")).unwrap();
+    fs::write(dir.path().join("chapters/Book/02-b.pdf"), "b").unwrap();
+    fs::write(dir.path().join("chapters/Book/01-a.pdf"), "a").unwrap();
+    let mut manifest = manifest::Manifest::new(Some("de"), None, Engine::Tectonic);
+    manifest.artifact("Book.pdf");
+    manifest.artifact("Book.tex");
+    manifest.artifact(Path::new("chapters").join("Book"));
+    manifest.phase("typeset", std::time::Instant::now());
+    let path = dir.path().join("build-de.json");
+    manifest.write(dir.path(), &path).unwrap();
+    let written: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(written["edition"], "de");
+    assert_eq!(written["engine"], "tectonic");
+    let artifacts = written["artifacts"].as_array().unwrap();
+    assert_eq!(artifacts.len(), 3);
+    assert_eq!(artifacts[0]["path"], "Book.pdf");
+    assert_eq!(artifacts[0]["size"], 4);
+    assert_eq!(artifacts[1]["path"], "chapters/Book/01-a.pdf");
+    assert_eq!(written["phases"][0]["name"], "typeset");
+}
+
+#[test]
+fn test_strikethrough() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let events = parse("Price ~~10~~ 8 EUR");
+    let events = inline::strikethrough(events, &mut state);
+    let tex = restored(&events, &state);
+    assert_eq!(tex, "Price \\sout{10} 8 EUR");
+}
+
+#[test]
+fn test_line_breaks() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig {
+        soft_break: SoftBreak::Newline,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let events = parse("Dear Sir\\\nor Madam\n\nTwo\\\nlines\n=====\n");
+    let events = inline::line_breaks(events, &mut state);
+    let tex = restored(&events, &state);
+    assert_eq!(tex, "Dear Sir\\\\\nor MadamTwo lines");
+
+    // Cells break by `<br>`, set as a cell of its own.
+    let markdown = traverse_markdown(
+        "| Address |\n|---|\n| Main St.<br>Springfield |\n",
+        Path::new("chap/"),
+        true,
+        &mut state,
+    )
+    .unwrap();
+    let tex = state.raw.restore(&markdown_to_tex(markdown).unwrap());
+    assert!(tex.contains("\\makecell{Main St.\\\\Springfield} \\\\\n"));
+}
+
+#[test]
+fn test_task_lists() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let events = parse("- [ ] review\n- [x] build\n");
+    let events = inline::task_lists(events, &mut state);
+    let tex = restored(&events, &state);
+    assert_eq!(tex, "$\\square$~review$\\boxtimes$~build");
+    assert!(state.packages.contains("amssymb"));
+}
+
+#[test]
+fn test_definition_lists() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let events = Parser::new_ext(
+        "Borrow checker\n: Enforces ownership.\n: Rejects some programs.\n\nLifetime\n: A region.\n\nPlain prose\nover two lines.\n",
+        Options::all(),
+    )
+    .collect::<Vec<_>>();
+    let events = definitions::definition_lists(events, &mut state);
+    let tex = events
+        .iter()
+        .map(|event| match event {
+            Event::Text(text) => state.raw.restore(text),
+            Event::Start(Tag::Paragraph) => "<p>".to_owned(),
+            _ => String::new(),
+        })
+        .collect::<String>();
+    assert_eq!(
+        tex,
+        "<p>\\begin{description}<p>\\item[{Borrow checker}] Enforces ownership.\\par Rejects some programs.\n\
+         <p>\\item[{Lifetime}] A region.\n<p>\\end{description}<p>Plain proseover two lines."
+    );
+}
+
+#[test]
+fn test_template_markers() {
+    assert!(templates::validate(include_str!("template.tex"), "template.tex").is_ok());
+    let template = "\\documentclass{article}\n\\title{Fixed}\n\\author{}\n\\begin{document}\n\\end{document}\n";
+    let missing = templates::missing(template)
+        .into_iter()
+        .map(|(marker, ..)| *marker)
+        .collect::<Vec<_>>();
+    assert_eq!(missing, [templates::BEGIN, "\\title{}", "\\date{}"]);
+    let err = templates::validate(template, "custom.tex").unwrap_err();
+    assert!(err.to_string().contains(templates::BEGIN));
+    assert!(!err.to_string().contains("\\title{}"));
+}
+
+#[test]
+fn test_alerts() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let mut cfg = LatexConfig::default();
+    cfg.admonitions.insert(
+        "warning".to_owned(),
+        admonitions::AdmonitionStyle {
+            color: Some("orange".to_owned()),
+            icon: None,
+        },
+    );
+    let mut state = Traversal::new(&context, &cfg);
+    let events = Parser::new_ext(
+        "> [!WARNING]\n> Back up first.\n\n> [!note]\n\n> Just a quote.\n",
+        Options::all(),
+    )
+    .collect::<Vec<_>>();
+    let events = admonitions::alerts(events, &mut state);
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, Event::Start(Tag::BlockQuote)))
+            .count(),
+        1
+    );
+    let tex = events
+        .iter()
+        .map(|event| match event {
+            Event::Text(text) => state.raw.restore(text),
+            Event::Start(Tag::Paragraph) => "<p>".to_owned(),
+            _ => String::new(),
+        })
+        .collect::<String>();
+    assert_eq!(
+        tex,
+        "<p>\\begin{admonition}{orange}{$\\triangle$}{Warning}<p>Back up first.<p>\\end{admonition}\
+         <p>\\begin{admonition}{admonitionnote}{\\textcircled{\\scriptsize i}}{Note}<p>\\end{admonition}\
+         <p>Just a quote."
+    );
+}
+
+#[test]
+fn test_template_regions() {
+    let template = "\\begin{document}\n%% mdbook-tectonic frontmatter\n\\mainmatter\n%% mdbook-tectonic begin\n%% mdbook-tectonic part: Advanced Topics\n\\backmatter\n%% mdbook-tectonic part: Advanced\n\\end{document}\n";
+    let marker = |numbered, after_numbered, part| {
+        templates::chapter_marker(template, numbered, after_numbered, part)
+    };
+    assert_eq!(marker(false, false, None), templates::FRONTMATTER);
+    assert_eq!(marker(true, true, None), templates::BEGIN);
+    assert_eq!(marker(true, true, Some("Basics")), templates::BEGIN);
+    assert_eq!(
+        marker(true, true, Some("Advanced")),
+        templates::part("Advanced")
+    );
+    // No back matter marker, suffix chapters go with the content.
+    assert_eq!(marker(false, true, Some("Advanced")), templates::BEGIN);
+
+    let mut content = templates::Content::default();
+    assert!(content.is_empty());
+    content.at(templates::FRONTMATTER).push_str("Preface");
+    content.at(templates::BEGIN).push_str("One");
+    content.at(&templates::part("Advanced")).push_str("Three");
+    content.at(templates::BEGIN).push_str("Two");
+    assert_eq!(content.concat(), "PrefaceOneTwoThree");
+    let tex = content.assemble(template).unwrap().to_string();
+    assert!(tex.contains("frontmatter\nPreface\\mainmatter"));
+    assert!(tex.contains("begin\nOneTwo%%"));
+    assert!(tex.contains("part: Advanced Topics\n\\backmatter"));
+    assert!(tex.contains("part: Advanced\nThree"));
+
+    // Written out piece by piece, in the order of the markers.
+    let mut content = templates::Content::default();
+    content.at(&templates::part("Advanced")).push_str("Three");
+    content.at(templates::FRONTMATTER).push_str("Preface");
+    content.at(templates::BEGIN).push_str("OneTwo");
+    let document = content.assemble(template).unwrap();
+    assert_eq!(document.len(), tex.len());
+    let mut out = Vec::new();
+    document.write_to(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), tex);
+    assert_eq!(document.to_string(), tex);
+
+    let mut content = templates::Content::default();
+    content.at("%% mdbook-tectonic missing").push_str("Lost");
+    assert!(content.assemble(template).is_err());
+}
+
+#[test]
+fn test_authors() {
+    let authors = ["Jane Doe".to_owned(), "Smith & Sons".to_owned()];
+    let mut details = BTreeMap::new();
+    details.insert(
+        "Jane Doe".to_owned(),
+        authors::AuthorDetails {
+            affiliation: Some("Dept. of R&D".to_owned()),
+            orcid: Some("0000-0002-1825-0097".to_owned()),
+            email: Some("jane_doe@example.org".to_owned()),
+        },
+    );
+    assert_eq!(authors::list(&authors), "Jane Doe, Smith \\& Sons");
+    assert_eq!(
+        authors::block(&authors, &details),
+        "Jane Doe\\\\\n\\small Dept. of R\\&D\
+         \\\\\n\\small\\href{mailto:jane_doe@example.org}{\\texttt{jane\\_doe@example.org}}\
+         \\\\\n\\small ORCID \\href{https://orcid.org/0000-0002-1825-0097}{0000-0002-1825-0097} \\and \
+         Smith \\& Sons"
+    );
+}
+
+#[test]
+fn test_admonish() {
+    let parse = |info| admonitions::parse_admonish(info);
+    assert_eq!(parse("rust"), None);
+    assert_eq!(parse("admonishment"), None);
+    assert_eq!(
+        parse("admonish"),
+        Some(("note".to_owned(), "Note".to_owned()))
+    );
+    assert_eq!(
+        parse("admonish warning"),
+        Some(("warning".to_owned(), "Warning".to_owned()))
+    );
+    assert_eq!(
+        parse("admonish danger \"Data loss\""),
+        Some(("danger".to_owned(), "Data loss".to_owned()))
+    );
+    assert_eq!(
+        parse("admonish tip title=\"Shortcut\" collapsible=true"),
+        Some(("tip".to_owned(), "Shortcut".to_owned()))
+    );
+
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig {
+        admonish: true,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let events = Parser::new_ext(
+        "```admonish danger\nBack up *first*.\n```\n\n```rust\nfn main() {}\n```\n",
+        Options::all(),
+    )
+    .collect::<Vec<_>>();
+    let events = admonitions::admonish(events, &mut state).unwrap();
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, Event::Start(Tag::CodeBlock(_))))
+            .count(),
+        1
+    );
+    let tex = restored(&events, &state);
+    assert!(tex.starts_with("\\begin{admonition}{admonitioncaution}{$\\otimes$}"));
+    assert!(tex.contains("\\end{admonition}"));
+}
+
+#[test]
+fn test_math() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig {
+        math: true,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let content =
+        "Inline $a_1 \\{x\\}$, from $5 to $10, escaped \\$x$, `$code$`.\n\n$$\\frac{a}{b}$$\n";
+    let converted = math::math(content, &mut state).unwrap();
+    let restored = state.raw.restore(&converted);
+    assert_eq!(
+        restored,
+        "Inline $a_1 \\{x\\}$, from $5 to $10, escaped \\$x$, `$code$`.\n\n\\[\\frac{a}{b}\\]\n"
+    );
+    assert!(!converted.contains("a_1"));
+    assert!(state.packages.contains("amsmath"));
+
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    assert_eq!(math::math(content, &mut state).unwrap(), content);
+
+    let cfg = LatexConfig {
+        katex_delimiters: true,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let content = "Inline \\(a_1\\), $b_1$, `\\(code\\)`.\n\n\\[\n\\sum_k k\n\\]\n";
+    let converted = math::math(content, &mut state).unwrap();
+    assert!(!converted.contains("a_1") && !converted.contains("sum_k"));
+    assert!(converted.contains("$b_1$"));
+    assert_eq!(state.raw.restore(&converted), content);
+}
+
+#[test]
+fn test_deterministic_ids() {
+    let labels = |seed: &str| {
+        let mut ids = ids::Ids::new(seed);
+        let mut labels = Vec::new();
+        for chapter in ["intro.md", "setup/install.md"] {
+            ids.enter(chapter);
+            labels.push(ids.next("footnote"));
+            labels.push(ids.next("footnote"));
+            labels.push(ids.next("unnumbered"));
+        }
+        labels
+    };
+    let first = labels("");
+    assert_eq!(first, labels(""));
+    assert!(first[0].starts_with("footnote-") && first[0].ends_with("-1"));
+    assert!(first[1].ends_with("-2"));
+    assert!(first[2].starts_with("unnumbered-") && first[2].ends_with("-1"));
+    // Counters restart with each chapter, the chapter hash differs.
+    assert!(first[3].ends_with("-1"));
+    assert_ne!(first[0], first[3]);
+    assert_ne!(first, labels("volume-2"));
+    assert_eq!(
+        first
+            .iter()
+            .collect::<std::collections::BTreeSet<_>>()
+            .len(),
+        first.len()
+    );
+}
+
+#[test]
+fn test_draft_only_book() {
+    let dest = tempfile::tempdir().unwrap();
+    let mut book = mdbook::book::Book::new();
+    book.push_item(mdbook::book::Chapter::new_draft("Later", Vec::new()));
+    let context = RenderContext::new(dest.path(), book, mdbook::Config::default(), dest.path());
+    let cfg = LatexConfig {
+        latex: false,
+        pdf: false,
+        markdown: false,
+        ..Default::default()
+    };
+    render(&context, &cfg, None, Flags::default()).unwrap();
+}
+
+#[test]
+fn test_title() {
+    assert_eq!(title::plain("R&D #1"), "R\\&D \\#1");
+    assert_eq!(title::page("R&D #1", &[], None), "R\\&D \\#1");
+    let lines = ["The Rust".to_owned(), "Programming Language".to_owned()];
+    assert_eq!(
+        title::page("The Rust Programming Language", &lines, Some("2nd edition")),
+        "The Rust\\\\\nProgramming Language\\\\[0.5em]\n\\large 2nd edition"
+    );
+}
+
+#[test]
+fn test_series() {
+    let labels = labels::Labels::default();
+    let mut series = series::Series::default();
+    assert_eq!(series::title("Title", &series, &labels), "Title");
+    series.name = Some("Rust & Co".to_owned());
+    series.volume = Some(2);
+    series.volumes = vec![
+        series::Volume {
+            title: "Ownership".to_owned(),
+            url: Some("https://example.org/1.pdf".to_owned()),
+        },
+        series::Volume {
+            title: "Traits".to_owned(),
+            url: None,
+        },
+        series::Volume {
+            title: "Async".to_owned(),
+            url: None,
+        },
+    ];
+    assert_eq!(series::line(&series, &labels), "Rust \\& Co, Volume 2");
+    assert_eq!(
+        series::title("Traits", &series, &labels),
+        "{\\normalsize Rust \\& Co, Volume 2}\\\\[1em]\nTraits\
+         \\thanks{Also in this series: Volume 1: \\href{https://example.org/1.pdf}{Ownership}; \
+         Volume 3: Async.}"
+    );
+
+    assert_eq!(
+        series::front_pages("Traits", &series, false, false, &labels),
+        ""
+    );
+    assert_eq!(
+        series::front_pages("Traits", &series, true, true, &labels),
+        "\\mdbookhalftitlepage{Traits}\\mdbookseriespage{Rust \\& Co}{Volume 1: Ownership\\\\\n\
+         \\textbf{Volume 2: Traits}\\\\\nVolume 3: Async}"
+    );
+    assert_eq!(
+        series::front_pages("Traits", &series::Series::default(), true, true, &labels),
+        "\\mdbookhalftitlepage{Traits}"
+    );
+}
+
+#[test]
+fn test_version() {
+    let root = Path::new("/tmp");
+    assert_eq!(
+        version::resolve(root, Some("1.2.0")),
+        Some("1.2.0".to_owned())
+    );
+    let footer = version::footer("v1.2.0-3-g1a2b3c4_dirty");
+    assert!(footer.contains("\\usepackage{fancyhdr}"));
+    assert!(footer.contains("\\fancyfoot[R]{\\footnotesize v1.2.0-3-g1a2b3c4\\_dirty}"));
+}
+
+#[test]
+fn test_minted() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig {
+        highlighting: Some(Highlighting::Minted),
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let events = Parser::new("```rs,ignore\nfn main() {}\n```\n\n```brainfuck\n+.\n```\n")
+        .collect::<Vec<_>>();
+    let events = highlighting::highlight(events, &mut state);
+    let tex = state.raw.restore(
+        &events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Text(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect::<String>(),
+    );
+    assert_eq!(
+        tex,
+        "\\begin{minted}{rust}\nfn main() {}\n\\end{minted}\\begin{minted}{text}\n+.\n\\end{minted}"
+    );
+    assert!(state.packages.contains("minted"));
+    assert!(highlighting::needs_shell_escape(cfg.highlighting));
+    assert!(!highlighting::needs_shell_escape(None));
+}
+
+#[test]
+fn test_abstract_title() {
+    assert_eq!(
+        abstracts::strip_title("\n# Abstract\n\nWe show that.\n"),
+        "\nWe show that.\n"
+    );
+    assert_eq!(abstracts::strip_title("# Abstract"), "");
+    assert_eq!(abstracts::strip_title("We show that.\n"), "We show that.\n");
+    assert_eq!(abstracts::strip_title("## Aside\n"), "## Aside\n");
+}
+
+#[test]
+fn test_listings_highlighting() {
+    let mut languages = BTreeMap::new();
+    languages.insert("zig".to_owned(), "C".to_owned());
+    let options = |info| highlighting::listings_options(&code::Info::parse(info), &languages);
+    assert_eq!(options("rust,ignore"), "style={mdbookrust}");
+    assert_eq!(options("JS"), "style={mdbookjavascript}");
+    assert_eq!(options("zig"), "style=mdbookcode,language={C}");
+    assert_eq!(options("brainfuck"), "style=mdbookplain");
+    assert_eq!(options(""), "style=mdbookplain");
+}
+
+#[test]
+fn test_code_captions() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let events = Parser::new(
+        "```rust,caption=\"Parsing & looping\",label=lst:parse\nloop {}\n```\n\n```rust\nfn main() {}\n```\n",
+    )
+    .collect::<Vec<_>>();
+    let events = highlighting::highlight(events, &mut state);
+    assert_eq!(
+        state.raw.restore(match &events[1] {
+            Event::Text(text) => text,
+            event => panic!("Expected raw LaTeX, got {:?}", event),
+        }),
+        "\\begin{lstlisting}[language=rust,caption={Parsing \\& looping},label={lst:parse}]\nloop {}\n\\end{lstlisting}"
+    );
+    // Not captioned and no highlighting, left to `cmark2tex`.
+    assert!(matches!(
+        events[3],
+        Event::Start(Tag::CodeBlock(pulldown_cmark::CodeBlockKind::Fenced(_)))
+    ));
+    assert!(state.capabilities.contains("Code captions"));
+    assert!(state.code_captions);
+
+    let converted = shortcodes::pagerefs("Listing {{#ref lst:parse}}.", &mut state).unwrap();
+    assert_eq!(state.raw.restore(&converted), "Listing \\ref{lst:parse}.");
+}
+
+#[test]
+fn test_keywords() {
+    let labels = labels::Labels::default();
+    let keywords = ["ownership".to_owned(), "R&D".to_owned()];
+    let mut classification = BTreeMap::new();
+    classification.insert("MSC2020".to_owned(), vec!["68N15".to_owned()]);
+    assert_eq!(
+        abstracts::keywords(&keywords, &classification, &labels),
+        "\\par\\smallskip\\noindent\\textbf{Keywords:} ownership, R\\&D\
+         \\par\\smallskip\\noindent\\textbf{MSC2020:} 68N15"
+    );
+    assert_eq!(
+        abstracts::pdf_keywords(&keywords, &classification),
+        "ownership, R\\&D, MSC2020 68N15"
+    );
+    assert_eq!(abstracts::keywords(&[], &BTreeMap::new(), &labels), "");
+}
+
+#[test]
+fn test_line_highlighting() {
+    assert_eq!(highlighting::line_ranges("2-4"), vec![2, 3, 4]);
+    assert_eq!(highlighting::line_ranges("5 1,3-4 x"), vec![1, 3, 4, 5]);
+
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let events = Parser::new("```rust,hl_lines=\"1 3\"\na\nb\nc\n```\n").collect::<Vec<_>>();
+    let events = highlighting::highlight(events, &mut state);
+    assert_eq!(
+        state.raw.restore(match &events[1] {
+            Event::Text(text) => text,
+            event => panic!("Expected raw LaTeX, got {:?}", event),
+        }),
+        "{\\def\\mdbookhighlighted{1,3}\\begin{lstlisting}[language=rust,numberstyle=\\mdbookhlnumber]\na\nb\nc\n\\end{lstlisting}}"
+    );
+    assert!(state.highlighted_lines);
+
+    let cfg = LatexConfig {
+        highlighting: Some(Highlighting::Minted),
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let events = Parser::new("```rust,hl_lines=2-3\na\nb\nc\n```\n").collect::<Vec<_>>();
+    let events = highlighting::highlight(events, &mut state);
+    assert!(matches!(&events[1], Event::Text(text)
+        if state.raw.restore(text).starts_with("\\begin{minted}[highlightlines={2,3}]{rust}")));
+}
+
+#[test]
+fn test_copy_template_dir() {
+    let source = tempfile::tempdir().unwrap();
+    let target = tempfile::tempdir().unwrap();
+    fs::write(source.path().join("mystyle.sty"), "% style").unwrap();
+    fs::create_dir(source.path().join("logos")).unwrap();
+    fs::write(source.path().join("logos").join("logo.png"), "png").unwrap();
+    assert_eq!(
+        templates::copy_dir(source.path(), target.path()).unwrap(),
+        2
+    );
+    assert!(target.path().join("mystyle.sty").is_file());
+    assert!(target.path().join("logos").join("logo.png").is_file());
+}
+
+#[test]
+fn test_typesetter_search_paths() {
+    let mut typesetter = typeset::Typesetter::new(Engine::Tectonic);
+    typesetter.search_paths.push(PathBuf::from("/book/latex"));
+    assert_eq!(
+        typesetter.args(),
+        [
+            "--outfmt=pdf",
+            "--keep-logs",
+            "-Z",
+            "search-path=/book/latex"
+        ]
+        .iter()
+        .map(std::ffi::OsString::from)
+        .collect::<Vec<_>>()
+    );
+    assert_eq!(typesetter.texinputs().unwrap(), None);
+
+    typesetter.engine = Engine::Lualatex;
+    typesetter.shell_escape = true;
+    assert!(!typesetter
+        .args()
+        .iter()
+        .any(|arg| arg.to_string_lossy().contains("search-path")));
+    assert!(typesetter.args().contains(&"-shell-escape".into()));
+    let separator = if cfg!(windows) { ";" } else { ":" };
+    assert_eq!(
+        typesetter.texinputs().unwrap(),
+        Some(format!(".{0}/book/latex{0}", separator).into())
+    );
+
+    // Directories of `texinputs` follow in order.
+    typesetter
+        .search_paths
+        .push(PathBuf::from("/shared-styles"));
+    assert_eq!(
+        typesetter.texinputs().unwrap(),
+        Some(format!(".{0}/book/latex{0}/shared-styles{0}", separator).into())
+    );
+}
+
+#[test]
+fn test_hidden_lines() {
+    assert_eq!(code::rust_line("# use std::io;", true), None);
+    assert_eq!(code::rust_line("    #", true), None);
+    assert_eq!(
+        code::rust_line("    # let x = 1;", false),
+        Some("    let x = 1;".to_owned())
+    );
+    assert_eq!(
+        code::rust_line("##[derive(Debug)]", true),
+        Some("#[derive(Debug)]".to_owned())
+    );
+    assert_eq!(code::rust_line("#[test]", true), Some("#[test]".to_owned()));
+
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let events =
+        Parser::new("```rust\n# fn main() {\nlet x = 1;\n# }\n```\n\n```python\n# comment\n```\n")
+            .collect::<Vec<_>>();
+    let events = code::hidden_lines(events, &mut state);
+    assert_eq!(events[1], Event::Text("let x = 1;\n".into()));
+    assert_eq!(events[4], Event::Text("# comment\n".into()));
+}
+
+#[test]
+fn test_long_lines() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let mut cfg = LatexConfig::default();
+    cfg.long_lines.warn = true;
+    cfg.long_lines.max_width = 10;
+    let mut state = Traversal::new(&context, &cfg);
+    let content =
+        "A paragraph much longer than ten columns.\n\n```text\nshort\nway too long a line\n```\n";
+    longlines::scan(content, &mut state);
+    assert_eq!(state.report.count(longlines::SECTION), 1);
+
+    let preamble = longlines::preamble(&cfg.long_lines, Some(Highlighting::Minted));
+    assert!(preamble.contains("postbreak="));
+    assert!(preamble.contains(r"\setminted{breaklines=true"));
+    cfg.long_lines.wrap = false;
+    assert!(longlines::preamble(&cfg.long_lines, None).contains("breaklines=false"));
+}
+
+#[test]
+fn test_ansi() {
+    let text = "\x1b[1;32mok\x1b[0m {done}\n\x1b[38;5;196mfail\x1b[39m\x1b]0;title\x07\n";
+    assert_eq!(ansi::strip(text), "ok {done}\nfail\n");
+    assert_eq!(
+        ansi::to_latex(text),
+        "\\textcolor{ansi2}{\\textbf{ok}} \\{done\\}\n\\textcolor[RGB]{255,0,0}{fail}\n"
+    );
+
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig {
+        ansi: Ansi::Strip,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let events = Parser::new("```console\n\x1b[31merror\x1b[0m\n```\n").collect::<Vec<_>>();
+    let stripped = ansi::console(events.clone(), &mut state);
+    assert_eq!(stripped[1], Event::Text("error\n".into()));
+    assert!(!state.ansi_colors);
+
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    ansi::console(events, &mut state);
+    assert!(state.ansi_colors);
+}
+
+#[test]
+fn test_log_filter() {
+    assert!(typeset::is_relevant(
+        "LaTeX Warning: Reference `lst:parse' on page 3 undefined."
+    ));
+    assert!(typeset::is_relevant("! Undefined control sequence."));
+    assert!(typeset::is_relevant("l.42 \\foo"));
+    assert!(!typeset::is_relevant(
+        "(/usr/share/texmf/tex/latex/listings/listings.sty"
+    ));
+    assert!(!typeset::is_relevant(
+        "Package hyperref Info: Link coloring OFF"
+    ));
+}
+
+#[test]
+fn test_tables() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let events = Parser::new_ext(
+        "| a | b | c | d |\n| --- | :-- | :-: | --: |\n| 1 | 2 | 3 | 4 |\n",
+        Options::all(),
+    )
+    .collect::<Vec<_>>();
+    let events = tables::tables(events, &mut state);
+    assert_eq!(events.first(), Some(&Event::Start(Tag::Paragraph)));
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, Event::Start(Tag::Table(_)))));
+    let tex = restored(&events, &state);
+    assert!(tex.contains("\\begin{tabular}{llcr}"));
+    assert!(tex.contains("\\textbf{a} & \\textbf{b}"));
+    assert!(tex.contains("1 & 2 & 3 & 4 \\\\"));
+}
+
+#[test]
+fn test_network_failures() {
+    assert!(typeset::is_network_failure(
+        "error: failed to download \"tectonic-format-latex.tar\"\ncaused by: error sending request"
+    ));
+    assert!(!typeset::is_network_failure(
+        "! Undefined control sequence.\nl.42 \\foo"
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_subprocess_limits() {
+    let limits = subprocess::Limits {
+        timeout: 5,
+        memory: Some(512),
+    };
+    let output =
+        subprocess::run(std::process::Command::new("cat"), Some(b"echo"), &limits).unwrap();
+    assert_eq!(output.stdout, b"echo");
+
+    let mut command = std::process::Command::new("sleep");
+    command.arg("10");
+    let limits = subprocess::Limits {
+        timeout: 0,
+        memory: None,
+    };
+    assert_matches!(
+        subprocess::run(command, None, &limits),
+        Err(subprocess::Error::Timeout { seconds: 0, .. })
+    );
+
+    let mut command = std::process::Command::new("sh");
+    command.arg("-c").arg("echo stuck >&2; exec sleep 10");
+    let limits = subprocess::Limits {
+        timeout: 1,
+        memory: None,
+    };
+    let err = subprocess::run(command, None, &limits).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "`sh` timed out after 1 seconds, last writing `stuck`"
+    );
+}
+
+#[test]
+fn test_long_tables() {
+    assert_eq!(
+        tables::directive("<!-- table: long -->\n"),
+        Some(tables::Directive {
+            long: Some(true),
+            ..Default::default()
+        })
+    );
+    assert_eq!(tables::directive("<!-- margin: note -->"), None);
+
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let mut cfg = LatexConfig::default();
+    cfg.tables.long_rows = 2;
+    let mut state = Traversal::new(&context, &cfg);
+    let markdown = "| a |\n| - |\n| 1 |\n| 2 |\n| 3 |\n\n<!-- table: short -->\n| b |\n| - |\n| 1 |\n| 2 |\n| 3 |\n";
+    let events = parse(markdown);
+    let events = tables::tables(events, &mut state);
+    let tex = restored(&events, &state);
+    assert_eq!(tex.matches("\\begin{longtable}{l}").count(), 1);
+    assert!(tex.contains("\\endhead"));
+    assert_eq!(tex.matches("\\begin{tabular}{l}").count(), 1);
+    assert!(!events.iter().any(|event| matches!(event, Event::Html(_))));
+}
+
+#[test]
+fn test_wide_tables() {
+    assert_eq!(
+        tables::directive("<!-- table: long, rotate -->"),
+        Some(tables::Directive {
+            long: Some(true),
+            wide: Some(WideTable::Rotate)
+        })
+    );
+
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let mut cfg = LatexConfig {
+        wide_table: WideTable::Rotate,
+        ..Default::default()
+    };
+    cfg.tables.text_width = 10;
+    let mut state = Traversal::new(&context, &cfg);
+    let markdown = "| a | b |\n| - | - |\n| 1 | 2 |\n\n| wide column | wider column |\n| - | - |\n| 1 | 2 |\n\n<!-- table: shrink -->\n| wide column | wider column |\n| - | - |\n| 1 | 2 |\n";
+    let events = parse(markdown);
+    let events = tables::tables(events, &mut state);
+    let tex = restored(&events, &state);
+    assert_eq!(tex.matches("\\begin{sidewaystable}").count(), 1);
+    assert_eq!(tex.matches("\\begin{adjustbox}").count(), 1);
+    assert!(state.packages.contains("rotating"));
+}
+
+#[test]
+fn test_prose_tables() {
+    let cfg = tables::TablesConfig {
+        prose_width: 10,
+        ..Default::default()
+    };
+    use pulldown_cmark::Alignment;
+    let alignments = [Alignment::Left, Alignment::Right, Alignment::None];
+    assert_eq!(
+        tables::prose_spec(&alignments, &[5, 30, 10], &cfg, false),
+        "l>{\\raggedleft\\arraybackslash\\hsize=1.00\\hsize}Xl"
+    );
+    assert_eq!(
+        tables::prose_spec(&alignments, &[20, 60, 10], &cfg, false),
+        "\
+            >{\\raggedright\\arraybackslash\\hsize=0.50\\hsize}X\
+            >{\\raggedleft\\arraybackslash\\hsize=1.50\\hsize}X\
+            l"
+    );
+    assert_eq!(
+        tables::prose_spec(&alignments, &[5, 30, 13], &cfg, true),
+        "l\
+            >{\\raggedleft\\arraybackslash}p{0.64\\linewidth}\
+            >{\\raggedright\\arraybackslash}p{0.28\\linewidth}"
+    );
+
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let mut cfg = LatexConfig::default();
+    cfg.tables.wrap_prose = true;
+    let mut state = Traversal::new(&context, &cfg);
+    let markdown = "| a | b |\n| - | - |\n| 1 | 2 |\n\n| Term | Meaning |\n| - | - |\n| x | a cell long enough to wrap rather than run off the page |\n";
+    let events = parse(markdown);
+    let events = tables::tables(events, &mut state);
+    let tex = restored(&events, &state);
+    assert_eq!(tex.matches("\\begin{tabular}{ll}").count(), 1);
+    assert_eq!(tex.matches("\\begin{tabularx}{\\linewidth}{l>").count(), 1);
+    assert!(state.packages.contains("tabularx"));
+}
+
+#[test]
+fn test_html_tables() {
+    let html = r#"<table>
+<caption>Opening &amp; closing</caption>
+<tr><th>Day</th><th colspan="2">Hours</th></tr>
+<tr><td rowspan="2">Weekdays</td><td>8</td><td align="right">18</td></tr>
+<tr><td colspan=2>by appointment</td></tr>
+</table>"#;
+    let (caption, rows) = tables::parse_html_table(html);
+    assert_eq!(caption.as_deref(), Some("Opening \\& closing"));
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[1][0].rowspan, 2);
+    assert_eq!(rows[2][0].colspan, 2);
+    assert_eq!(
+        tables::html_table_to_tex(&rows),
+        "\\begin{tabular}{lll}\n\\toprule\n\
+        \\textbf{Day} & \\multicolumn{2}{l}{\\textbf{Hours}} \\\\\n\\midrule\n\
+        \\multirow{2}{*}{Weekdays} & 8 & \\multicolumn{1}{r}{18} \\\\\n \
+        & \\multicolumn{2}{l}{by appointment} \\\\\n\
+        \\bottomrule\n\\end{tabular}"
+    );
+
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let markdown = format!("Before\n\n{}\n\nAfter\n", html);
+    let events = parse(&markdown);
+    let events = tables::html_tables(events, &mut state).unwrap();
+    assert!(!events.iter().any(|event| matches!(event, Event::Html(_))));
+    let tex = restored(&events, &state);
+    assert!(tex.contains("\\caption{Opening \\& closing}"));
+    assert!(state.packages.contains("multirow"));
+
+    // Entities of any kind, cells left open and tables within cells.
+    let html = "<table><tr><td>&eacute;t&#233; &#x2014;<td><table><tr><td>inner</td></tr></table>\
+                <tr><td>last</table> after";
+    let (_, rows) = tables::parse_html_table(html);
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0][0].tex, "été —");
+    assert_eq!(rows[0][1].tex, "inner");
+    assert_eq!(rows[1][0].tex, "last");
+
+    let markdown = format!("{}\n\n<table><caption>Empty</caption></table>\n", html);
+    let events = parse(&markdown);
+    let events = tables::html_tables(events, &mut state).unwrap();
+    let kept = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Html(html) => Some(html.as_ref()),
+            _ => None,
+        })
+        .collect::<String>();
+    assert_eq!(kept, " after\n<table><caption>Empty</caption></table>");
+    assert_eq!(state.report.count(tables::SECTION), 1);
+}
+
+#[test]
+fn test_encoding() {
+    assert_eq!(
+        encoding::decode(b"\xef\xbb\xbfplain"),
+        ("plain".to_owned(), None)
+    );
+    assert_eq!(
+        encoding::decode(b"\xff\xfeh\0\xe9\0"),
+        ("hé".to_owned(), Some("UTF-16LE"))
+    );
+    assert_eq!(
+        encoding::decode(b"caf\xe9 \x93quoted\x94"),
+        ("café “quoted”".to_owned(), Some("Windows-1252"))
+    );
+
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let content = "\u{feff}# Café\n\nIncluded \u{feff}text\n\nCafÃ© and caf\u{fffd}\n";
+    assert_eq!(
+        encoding::clean(content, &mut state),
+        "# Café\n\nIncluded text\n\nCafÃ© and caf\u{fffd}\n"
+    );
+    assert_eq!(state.report.count(encoding::SECTION), 1);
+}
+
+#[test]
+fn test_line_endings() {
+    assert_eq!(encoding::normalize("a\r\nb\rc\n\u{feff}d"), "a\nb\nc\nd");
+    assert_matches!(
+        encoding::normalize("as is\n"),
+        std::borrow::Cow::Borrowed(_)
+    );
+
+    let unix = "Roses  \nviolets\n\n```\nfn main() {}\n```\n";
+    let windows = unix.replace('\n', "\r\n");
+    let windows = encoding::normalize(&windows);
+    assert_eq!(
+        Parser::new(unix).collect::<Vec<_>>(),
+        Parser::new(&windows).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_internal_links() {
+    let mut book = mdbook::book::Book::new();
+    for path in ["README.md", "guide/intro.md", "reference/config.md"] {
+        book.push_item(mdbook::book::Chapter::new(
+            path,
+            "# Title\n\n## Options\n\n## Options\n".to_owned(),
+            path,
+            Vec::new(),
+        ));
+    }
+    let map = links::LinkMap::new(&book);
+    let chapter = Path::new("guide/intro.md");
+    assert_eq!(
+        map.resolve(chapter, "../reference/config.md#options-1"),
+        links::Target::Internal("heading:reference/config.md:options-1".to_owned())
+    );
+    assert_eq!(
+        map.resolve(chapter, "../reference/config.md#unknown"),
+        links::Target::Internal("chapter:reference/config.md".to_owned())
+    );
+    assert_eq!(
+        map.resolve(chapter, "../index.html"),
+        links::Target::Internal("chapter:README.md".to_owned())
+    );
+    assert_eq!(
+        map.resolve(chapter, "#section"),
+        links::Target::Internal("chapter:guide/intro.md".to_owned())
+    );
+    assert_eq!(map.resolve(chapter, "missing.md"), links::Target::Dead);
+    assert_eq!(
+        map.resolve(chapter, "https://example.com/a.md"),
+        links::Target::External
+    );
+    assert_eq!(map.resolve(chapter, "data.csv"), links::Target::External);
+
+    let (_dir, context) = fixture(book.clone());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let ch = match book.iter().nth(1) {
+        Some(mdbook::BookItem::Chapter(ch)) => ch,
+        _ => unreachable!(),
+    };
+    state.chapter = Some(ch);
+    let markdown = "# Intro\n\nSee [options](../reference/config.md) and [gone](gone.md).\n";
+    let events = parse(markdown);
+    let events = links::resolve(events, &mut state);
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, Event::Start(Tag::Link(..)))));
+    let tex = restored(&events, &state);
+    assert_eq!(
+        tex,
+        "IntroSee \\hyperref[chapter:reference/config.md]{options} and gone."
+    );
+}
+
+#[test]
+fn test_heading_labels() {
+    assert_eq!(
+        anchors::ids(
+            "# Hello, `World`!\n\n## Hello World\n\n## Custom {#own}\n\n# ![logo](a.png) Logo\n"
+        ),
+        vec!["hello-world", "hello-world-1", "own", "logo"]
+    );
+
+    let mut book = mdbook::book::Book::new();
+    book.push_item(mdbook::book::Chapter::new(
+        "Intro",
+        String::new(),
+        "guide/intro.md",
+        Vec::new(),
+    ));
+    let (_dir, context) = fixture(book.clone());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let ch = match book.iter().next() {
+        Some(mdbook::BookItem::Chapter(ch)) => ch,
+        _ => unreachable!(),
+    };
+    state.chapter = Some(ch);
+    let restore = |events: &[Event], state: &Traversal| {
+        events
+            .iter()
+            .map(|event| match event {
+                Event::Text(text) => state.raw.restore(text),
+                _ => String::new(),
+            })
+            .collect::<String>()
+    };
+    let events = Parser::new("# Intro\n\n## Intro\n").collect::<Vec<_>>();
+    let events = anchors::label_headings(events, &mut state);
+    assert_eq!(
+        restore(&events, &state),
+        "Intro\\label{heading:guide/intro.md:intro}\\label{chapter:guide/intro.md}\
+        Intro\\label{heading:guide/intro.md:intro-1}"
+    );
+
+    state.anchors = Default::default();
+    let events = Parser::new("No heading.\n").collect::<Vec<_>>();
+    let events = anchors::label_headings(events, &mut state);
+    assert_eq!(
+        restore(&events, &state),
+        "\\phantomsection\\label{chapter:guide/intro.md}No heading."
+    );
+
+    // The explicit id only shows up as the label, not in the title.
+    let markdown = traverse_markdown(
+        "# Intro\n\n## Sub heading {#custom-id}\n",
+        Path::new("guide/"),
+        true,
+        &mut state,
+    )
+    .unwrap();
+    let tex = state.raw.restore(&markdown_to_tex(markdown).unwrap());
+    assert!(tex.contains("\\section{Sub heading}"));
+    assert!(tex.contains("\\label{heading:guide/intro.md:custom-id}"));
+    assert!(!tex.contains("{#custom-id}"));
+}
+
+#[test]
+fn test_chapter_size() {
+    let filler = "Lorem ipsum dolor sit amet. ".repeat(40);
+    let mut content = String::new();
+    for section in 1..=8 {
+        content.push_str(&format!(
+            "## Section {}\n\n{} See [the docs][docs].[^note]\n\n",
+            section, filler
+        ));
+    }
+    content.push_str("[docs]: https://example.com\n\n[^note]: A note.\n");
+    content.push_str("\n```text\n[code]: https://example.com/code\n```\n");
+
+    let pieces = size::pieces(&content, 4);
+    assert_eq!(pieces.len(), 3);
+    for section in 1..=8 {
+        let heading = format!("## Section {}\n", section);
+        assert_eq!(
+            pieces
+                .iter()
+                .map(|piece| piece.matches(&heading).count())
+                .sum::<usize>(),
+            1
+        );
+    }
+    for piece in &pieces {
+        assert!(piece.contains("[docs]: https://example.com"));
+        assert!(piece.contains("[^note]: A note."));
+    }
+    // Only in the code block it is written in.
+    assert_eq!(
+        pieces
+            .iter()
+            .map(|piece| piece.matches("[code]:").count())
+            .sum::<usize>(),
+        1
+    );
+    assert_eq!(size::pieces("# Small\n", 4).len(), 1);
+
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let mut cfg = LatexConfig::default();
+    cfg.chapter_size.warn = 1;
+    cfg.chapter_size.limit = Some(5);
+    let mut state = Traversal::new(&context, &cfg);
+    let cut = size::guard(&content, &mut state);
+    assert_eq!(state.report.count(size::SECTION), 1);
+    assert!(cut.len() <= 5 * 1024 + 200);
+    // Cut between the fifth heading and its paragraph.
+    assert!(cut.contains("## Section 5"));
+    assert_eq!(cut.matches(&filler).count(), 4);
+    assert!(!cut.contains("## Section 6"));
+
+    cfg.chapter_size.oversize = Oversize::Outline;
+    let mut state = Traversal::new(&context, &cfg);
+    let outline = size::guard(&content, &mut state);
+    assert!(outline.contains("## Section 8"));
+    assert_eq!(outline.matches(&filler).count(), 4);
+
+    // Measured once, as a whole, though converted in pieces.
+    let mut cfg = LatexConfig {
+        readability: true,
+        ..Default::default()
+    };
+    cfg.chapter_size.piece = 4;
+    let mut state = Traversal::new(&context, &cfg);
+    metrics::measure(&parse(&content), &mut state).unwrap();
+    let findings = state.report.count(metrics::SECTION);
+    let mut state = Traversal::new(&context, &cfg);
+    traverse_markdown(&content, Path::new(""), true, &mut state).unwrap();
+    assert_eq!(state.report.count(metrics::SECTION), findings);
+}
+
+#[test]
+fn test_edition_book() {
+    let dir = tempfile::tempdir().unwrap();
+    let src = dir.path().join("src.de");
+    fs::create_dir_all(&src).unwrap();
+    fs::write(src.join("SUMMARY.md"), "[Einleitung](README.md)\n").unwrap();
+    fs::write(
+        src.join("README.md"),
+        "# Einleitung\n\n{{#include gruss.txt}}\n",
+    )
+    .unwrap();
+    fs::write(src.join("gruss.txt"), "Hallo").unwrap();
+
+    let mut config = mdbook::Config::default();
+    config.book.src = PathBuf::from("src.de");
+    let book = edition_book(dir.path(), &config).unwrap();
+    let ch = match book.iter().next() {
+        Some(mdbook::BookItem::Chapter(ch)) => ch,
+        _ => unreachable!(),
+    };
+    assert_eq!(ch.content, "# Einleitung\n\nHallo\n");
+    assert_eq!(ch.path.as_deref(), Some(Path::new("index.md")));
+}
+
+#[test]
+fn test_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = lock::Lock::acquire(dir.path()).unwrap();
+    let path = dir.path().join(".mdbook-latex.lock");
+    assert!(path.exists());
+    assert!(!lock::is_stale(&path));
+    drop(lock);
+    assert!(!path.exists());
+
+    // Left behind by a process long gone.
+    fs::write(&path, "4294967295\n").unwrap();
+    if Path::new("/proc/self").exists() {
+        assert!(lock::is_stale(&path));
+        drop(lock::Lock::acquire(dir.path()).unwrap());
+    }
+
+    let file = dir.path().join("book.tex");
+    lock::write_atomic(&file, b"\\documentclass{book}").unwrap();
+    assert_eq!(fs::read_to_string(&file).unwrap(), "\\documentclass{book}");
+    lock::write_atomic_with(&file, |out| {
+        out.write_all(b"\\documentclass")?;
+        out.write_all(b"{article}")
+    })
+    .unwrap();
+    assert_eq!(
+        fs::read_to_string(&file).unwrap(),
+        "\\documentclass{article}"
+    );
+    assert!(!fs::read_dir(dir.path()).unwrap().any(|entry| entry
+        .unwrap()
+        .path()
+        .to_string_lossy()
+        .ends_with(".tmp")));
+}
+
+#[test]
+fn test_windows_paths() {
+    assert_eq!(
+        paths::normalize(r"images\chap\xyz.png", true),
+        "images/chap/xyz.png"
+    );
+    assert_eq!(
+        paths::normalize(r"\\?\C:\book\latex\book.pdf", true),
+        "C:/book/latex/book.pdf"
+    );
+    assert_eq!(
+        paths::normalize(r"\\?\UNC\server\share\book.pdf", true),
+        "//server/share/book.pdf"
+    );
+    assert_eq!(
+        paths::normalize(r"\\server\share\logo.png", true),
+        "//server/share/logo.png"
+    );
+    // A backslash is part of the name elsewhere.
+    assert_eq!(paths::normalize(r"odd\name.png", false), r"odd\name.png");
+}
+
+#[cfg(windows)]
+#[test]
+fn test_windows_tex_paths() {
+    assert_eq!(
+        paths::to_tex(Path::new(r"C:\book\images\a.png")),
+        "C:/book/images/a.png"
+    );
+    assert_eq!(
+        imposition::document(Path::new(r"latex\book.pdf"), Imposition::Booklet),
+        imposition::document(Path::new("latex/book.pdf"), Imposition::Booklet)
+    );
+    assert_eq!(
+        images::source("./img/a.png", Path::new(r"chap\sub")),
+        Path::new(r"chap\sub\img\a.png")
+    );
+}
+
+#[test]
+fn test_timing() {
+    use std::time::{Duration, Instant};
+    assert_eq!(timing::format_duration(Duration::from_millis(42)), "42 ms");
+    assert_eq!(
+        timing::format_duration(Duration::from_millis(12_340)),
+        "12.3 s"
+    );
+
+    let mut timings = timing::Timings::default();
+    let start = Instant::now();
+    timings.chapter_phase("parse", start);
+    timings.chapter_phase("convert", start);
+    timings.chapter_phase("parse", start);
+    let chapter = timings.finish_chapter();
+    assert!(chapter.starts_with("parse "));
+    assert!(chapter.contains(", convert "));
+    assert_eq!(chapter.matches("parse").count(), 1);
+    assert_eq!(timings.finish_chapter(), "");
+    timings.book_phase("compile", start);
+    assert!(timings.book().ends_with(" ms") && timings.book().contains("compile"));
+}
+
+#[test]
+fn test_bench_book() {
+    let book = bench::synthetic(3);
+    let (_dir, context) = fixture(book);
+    let cfg = LatexConfig {
+        timing: true,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let chapter = match context.book.iter().nth(1) {
+        Some(mdbook::book::BookItem::Chapter(chapter)) => chapter,
+        _ => panic!("Synthetic book has chapters"),
+    };
+    state.chapter = Some(chapter);
+    traverse_markdown(
+        &chapter.content,
+        Path::new("chapter-1.md"),
+        true,
+        &mut state,
+    )
+    .unwrap();
+    assert_eq!(state.report.count(timing::SECTION), 1);
+    assert!(state.timings.book().contains("convert"));
+}
+
+#[test]
+fn test_url_footnotes() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig {
+        url_style: UrlStyle::Footnote,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let markdown = "# The [site](https://example.com)\n\n\
+                    Read [the docs](https://example.com/a#b%20c) or <https://example.com>.\n\n\
+                    | Where |\n|---|\n| [here](https://example.com/t) |\n\n\
+                    A note.[^n]\n\n[^n]: See [there](https://example.com/n).\n";
+    let events = parse(markdown);
+    let events = links::resolve(events, &mut state);
+    let links = events
+        .iter()
+        .filter(|event| matches!(event, Event::Start(Tag::Link(..))))
+        .count();
+    // The heading and the autolink.
+    assert_eq!(links, 2);
+    let tex = restored(&events, &state);
+    assert!(tex.contains("the docs\\footnote{\\url{https://example.com/a\\#b\\%20c}}"));
+    assert!(tex.contains("here (\\url{https://example.com/t})"));
+    assert!(tex.contains("there (\\url{https://example.com/n})"));
+
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let events = parse(markdown);
+    let events = links::resolve(events, &mut state);
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, Event::Start(Tag::Link(..))))
+            .count(),
+        5
+    );
+}
+
+#[test]
+fn test_link_colors() {
+    assert_eq!(links::hypersetup(&LatexConfig::default()), None);
+    let cfg = LatexConfig {
+        link_color: Some("teal".to_owned()),
+        cite_color: Some("red!50!black".to_owned()),
+        ..Default::default()
+    };
+    assert_eq!(
+        links::hypersetup(&cfg).unwrap(),
+        "\\hypersetup{linkcolor={teal}, citecolor={red!50!black}}\n"
+    );
+    let cfg = LatexConfig {
+        colorlinks: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        links::hypersetup(&cfg).unwrap(),
+        "\\hypersetup{colorlinks=false, pdfborder={0 0 1}}\n"
+    );
+    let cfg = LatexConfig {
+        colorlinks: false,
+        link_boxes: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        links::hypersetup(&cfg).unwrap(),
+        "\\hypersetup{hidelinks}\n"
+    );
+}
+
+#[test]
+fn test_qr_codes() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig {
+        qr_codes: QrCodes::Margin,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let markdown = "# The [site](https://example.com)\n\n\
+                    Read [the docs](https://example.com/a#b) or [a file](data.csv).\n\n\
+                    | Where |\n|---|\n| [here](https://example.com/t) |\n";
+    let events = parse(markdown);
+    let events = links::resolve(events, &mut state);
+    // Still links, with the codes after them.
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, Event::End(Tag::Link(..))))
+            .count(),
+        4
+    );
+    let tex = restored(&events, &state);
+    assert!(tex.starts_with(
+        "The siteRead the docs\\marginpar{\\qrcode[height=1.5cm]{https://example.com/a\\#b}}"
+    ));
+    assert!(tex.contains(
+        "here\\,\\raisebox{-0.3\\height}{\\qrcode[height=1.5cm]{https://example.com/t}}"
+    ));
+    assert_eq!(tex.matches("qrcode").count(), 2);
+    assert!(state.packages.contains("qrcode"));
+
+    let cfg = LatexConfig {
+        qr_codes: QrCodes::Inline,
+        url_style: UrlStyle::Footnote,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let events = parse("[docs](https://example.com)");
+    let events = links::resolve(events, &mut state);
+    let tex = restored(&events, &state);
+    assert_eq!(
+        tex,
+        "docs\\footnote{\\url{https://example.com}}\\,\\raisebox{-0.3\\height}{\\qrcode[height=1.5cm]{https://example.com}}"
+    );
+}
+
+#[test]
+fn test_figures() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig {
+        figures: true,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let markdown = "![The harbour](harbour.png)\n\n\
+                    ![](map.png \"Map of the harbour\")\n\n\
+                    An ![icon](icon.png) within text.\n\n\
+                    - ![In a list](list.png)\n\n  More.\n";
+    let events = parse(markdown);
+    let events = images::figures(events, &mut state).unwrap();
+    let images = events
+        .iter()
+        .filter(|event| matches!(event, Event::Start(Tag::Image(..))))
+        .count();
+    assert_eq!(images, 2);
+    let tex = restored(&events, &state);
+    assert!(tex.contains("{harbour.png}\n"));
+    assert!(tex.contains("{map.png}\n\\caption{Map of the harbour}\n\\label{figure-"));
+    assert_eq!(tex.matches("\\begin{figure}[htbp]").count(), 2);
+    assert!(state.capabilities.contains("Figures"));
+
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let events = parse(markdown);
+    let events = images::figures(events, &mut state).unwrap();
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, Event::Start(Tag::Image(..))))
+            .count(),
+        4
+    );
+}
+
+#[test]
+fn test_frontmatter_pagestyle() {
+    let preamble = templates::matter_preamble("empty");
+    assert!(preamble.contains("\\pagestyle{empty}\\pagenumbering{roman}}"));
+    assert!(preamble.contains("\\newcommand{\\mdbookmainmatter}{%"));
+    assert!(preamble.contains("\\let\\@oddhead\\mdbook@oddhead"));
+    assert!(preamble.starts_with("\\makeatletter\n") && preamble.ends_with("\\makeatother\n"));
+}
+
+#[test]
+fn test_glossary_index() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig {
+        glossary: vec!["Ölfeld".to_owned(), "Pipe|Line".to_owned()],
+        first_use: TermStyle::Bold,
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let events = parse("# ÖLFELD\n\nAn ölfeld, another Ölfeld, and Pipe|Line twice: pipe|line.");
+    let events = glossary::first_use(events, &mut state).unwrap();
+    let tex = restored(&events, &state);
+    assert_eq!(
+        tex,
+        "ÖLFELDAn \\textbf{ölfeld}\\index{Ölfeld}, another Ölfeld, and \
+         \\textbf{Pipe|Line}\\index{Pipe\"|Line} twice: pipe|line."
+    );
+    assert!(state.packages.contains(index::PACKAGE));
+    assert!(index::print(&mut state).is_some());
+}
+
+#[test]
+fn test_index_directive() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    assert_eq!(index::print(&mut state), None);
+    let events = parse("Borrowing<!-- index: borrow!checker --> rules.");
+    let events = comments::html_comments(events, &mut state).unwrap();
+    let tex = restored(&events, &state);
+    assert_eq!(tex, "Borrowing\\index{borrow!checker} rules.");
+    let index = index::print(&mut state).unwrap();
+    assert_eq!(state.raw.restore(&index).trim(), "\\printindex");
+}
+
+#[test]
+fn test_engine_features() {
+    let mut book = mdbook::book::Book::new();
+    book.push_item(mdbook::book::Chapter::new(
+        "Logic",
+        "Where $α ≤ β$, `$ש$` is code and שלום is Hebrew.".to_owned(),
+        "logic.md",
+        Vec::new(),
+    ));
+    let cfg = LatexConfig::default();
+    let features = engine::Features::scan(&book, &cfg).unwrap();
+    assert!(features.rtl && !features.unicode_math && !features.opentype);
+    assert_eq!(features.bidi(Engine::Tectonic), "\\usepackage{bidi}\n");
+    assert_eq!(features.bidi(Engine::Lualatex), "");
+
+    let cfg = LatexConfig {
+        math: true,
+        fonts: [("he".to_owned(), "David CLM".to_owned())].into(),
+        ..Default::default()
+    };
+    let features = engine::Features::scan(&book, &cfg).unwrap();
+    assert!(features.unicode_math && features.opentype);
+    let preamble = features.preamble(Engine::Tectonic, false);
+    assert_eq!(
+        preamble,
+        "\\usepackage{fontspec}\n\\usepackage{unicode-math}\n"
+    );
+}
+
+#[test]
+fn test_leftover_markers() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig {
+        todo: todo::TodoConfig {
+            enable: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut state = Traversal::new(&context, &cfg);
+    let content = "# Setup\n\nTODO: explain, FIXME too.\n\n\
+                   Use `TODO` as a marker.\n\n\
+                   ```rust\n// TODO: in code\n```\n\n\
+                   Spelled todo, or TODOS, is fine.\n";
+    todo::scan(content, &mut state).unwrap();
+    assert_eq!(state.report.count(todo::SECTION), 1);
+}
+
+#[test]
+fn test_invocation() {
+    let parse = |args: &[&str]| Invocation::parse(args.iter().map(|arg| arg.to_string()));
+    assert_eq!(
+        parse(&["doctor", "book"]).unwrap(),
+        Invocation::Doctor(Some(PathBuf::from("book")))
+    );
+    assert_eq!(parse(&["--bench"]).unwrap(), Invocation::Bench(200));
+    assert_eq!(parse(&["--bench", "20"]).unwrap(), Invocation::Bench(20));
+    assert!(parse(&["--bench", "many"]).is_err());
+    assert_eq!(
+        parse(&["--verbose", "--profile", "release", "--check-tex"]).unwrap(),
+        Invocation::Render {
+            flags: Flags {
+                check_tex: true,
+                verbose: true,
+                ..Default::default()
+            },
+            profile: Some("release".to_owned()),
+        }
+    );
+    assert!(parse(&["--profile"]).is_err());
+}
+
+#[test]
+fn test_columns() {
+    let (_dir, context) = fixture(mdbook::book::Book::new());
+    let cfg = LatexConfig::default();
+    let mut state = Traversal::new(&context, &cfg);
+    let content = "::: columns\n::: column width=70%\nOriginal\n:::\n::: column width=50%\n\
+                   ````markdown\n~~~\n:::\n```\n:::\n````\n:::\n:::\nAfter\n";
+    let columns = columns::columns(content, &mut state).unwrap();
+    let tex = state.raw.restore(&columns);
+    // Shrunk to the 98% of the text width left besides the gap.
+    assert!(tex.contains("\\noindent\\begin{minipage}[t]{0.572\\textwidth}"));
+    assert!(tex.contains("\\end{minipage}\\hfill\\begin{minipage}[t]{0.408\\textwidth}"));
+    assert!(tex.contains("````markdown\n~~~\n:::\n```\n:::\n````\n"));
+    assert!(tex.ends_with("\\end{minipage}\\par\n\nAfter\n"));
+}
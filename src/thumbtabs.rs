@@ -0,0 +1,45 @@
+//! Thumb tabs, marks on the outer page edge which shift down per chapter,
+//! as known from reference manuals.
+
+/// Number of positions before tabs start over at the top.
+const SLOTS: usize = 12;
+
+/// Preamble drawing the tab of the current chapter onto every page, for a
+/// book with `chapters` top level chapters.
+pub(crate) fn preamble(chapters: usize) -> String {
+    let slots = chapters.clamp(1, SLOTS);
+    format!(
+        r"
+%% Thumb tabs.
+\usepackage{{eso-pic}}
+\newcounter{{thumbtab}}
+\newcounter{{thumbtabchapter}}
+\newlength{{\thumbtabheight}}
+\setlength{{\thumbtabheight}}{{\dimexpr(\paperheight-2cm)/{slots}\relax}}
+\newlength{{\thumbtabwidth}}
+\setlength{{\thumbtabwidth}}{{0.8cm}}
+\AddToShipoutPictureBG{{%
+  \ifnum\value{{thumbtab}}>0\relax
+    \AtPageUpperLeft{{%
+      \hspace*{{\dimexpr\paperwidth-\thumbtabwidth\relax}}%
+      \raisebox{{\dimexpr-1cm-\value{{thumbtab}}\thumbtabheight\relax}}{{%
+        \rlap{{\color{{black!60}}\rule{{\thumbtabwidth}}{{\thumbtabheight}}}}%
+        \raisebox{{0.5\thumbtabheight}}{{\makebox[\thumbtabwidth]{{\color{{white}}\bfseries\arabic{{thumbtabchapter}}}}}}%
+      }}%
+    }}%
+  \fi
+}}
+",
+        slots = slots
+    )
+}
+
+/// Select the tab of the `index`th top level chapter, counting from one.
+pub(crate) fn select(index: usize, chapters: usize) -> String {
+    let slots = chapters.clamp(1, SLOTS);
+    format!(
+        "\\setcounter{{thumbtab}}{{{}}}\\setcounter{{thumbtabchapter}}{{{}}}",
+        (index - 1) % slots + 1,
+        index
+    )
+}
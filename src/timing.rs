@@ -0,0 +1,70 @@
+//! Durations of the phases of the conversion, per chapter and for the whole
+//! book, listed in the build report with `timing`, so slow chapters and
+//! regressions of the conversion stand out.
+//!
+//! The phases of a chapter are `parse`, the passes over the markdown source
+//! and its parsing, `images`, resolving and copying them, and `convert`, the
+//! passes over the events. The book adds `template`, the conversion to LaTeX
+//! and filling the template, and `compile`, the engine run.
+
+use std::time::{Duration, Instant};
+
+pub(crate) const SECTION: &str = "timing";
+
+/// Phases measured so far, in the order they were first measured.
+#[derive(Debug, Default)]
+pub(crate) struct Timings {
+    chapter: Vec<(&'static str, Duration)>,
+    book: Vec<(&'static str, Duration)>,
+}
+
+/// Add `elapsed` to `phase` of `phases`.
+fn add(phases: &mut Vec<(&'static str, Duration)>, phase: &'static str, elapsed: Duration) {
+    match phases.iter_mut().find(|(name, _)| *name == phase) {
+        Some((_, total)) => *total += elapsed,
+        None => phases.push((phase, elapsed)),
+    }
+}
+
+/// `phases` as a line of the report, `parse 12 ms, convert 40 ms`.
+fn describe(phases: &[(&'static str, Duration)]) -> String {
+    phases
+        .iter()
+        .map(|(name, elapsed)| format!("{} {}", name, format_duration(*elapsed)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `elapsed` for humans, in milliseconds up to ten seconds.
+pub(crate) fn format_duration(elapsed: Duration) -> String {
+    if elapsed < Duration::from_secs(10) {
+        format!("{} ms", elapsed.as_millis())
+    } else {
+        format!("{:.1} s", elapsed.as_secs_f64())
+    }
+}
+
+impl Timings {
+    /// Record `phase` of the current chapter as ending now.
+    pub(crate) fn chapter_phase(&mut self, phase: &'static str, start: Instant) {
+        let elapsed = start.elapsed();
+        add(&mut self.chapter, phase, elapsed);
+        add(&mut self.book, phase, elapsed);
+    }
+
+    /// Record `phase` of the whole book as ending now.
+    pub(crate) fn book_phase(&mut self, phase: &'static str, start: Instant) {
+        add(&mut self.book, phase, start.elapsed());
+    }
+
+    /// The phases of the current chapter, as a finding, starting over for
+    /// the next one.
+    pub(crate) fn finish_chapter(&mut self) -> String {
+        describe(&std::mem::take(&mut self.chapter))
+    }
+
+    /// The phases of the whole book, as a finding.
+    pub(crate) fn book(&self) -> String {
+        describe(&self.book)
+    }
+}
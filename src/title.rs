@@ -0,0 +1,30 @@
+//! Title of the book, as in `book.title`, escaped for LaTeX.
+//!
+//! The title page may break a long title into the lines of `title-lines`
+//! and print the `subtitle` below, the PDF metadata keeps the plain title.
+
+use crate::raw::escape;
+
+/// The argument of `\title`, broken into `lines` if there are any, followed
+/// by the subtitle in a smaller size.
+pub(crate) fn page(title: &str, lines: &[String], subtitle: Option<&str>) -> String {
+    let mut tex = if lines.is_empty() {
+        escape(title)
+    } else {
+        lines
+            .iter()
+            .map(|line| escape(line))
+            .collect::<Vec<_>>()
+            .join("\\\\\n")
+    };
+    if let Some(subtitle) = subtitle {
+        tex.push_str(&format!("\\\\[0.5em]\n\\large {}", escape(subtitle)));
+    }
+    tex
+}
+
+/// The title on one line, for the PDF metadata and templates to use as they
+/// see fit.
+pub(crate) fn plain(title: &str) -> String {
+    escape(title)
+}
@@ -0,0 +1,85 @@
+//! Scan for leftover markers like `TODO` or `FIXME`.
+
+use crate::{math, Traversal};
+
+/// Report section of the scan.
+pub(crate) const SECTION: &str = "leftover-markers";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct TodoConfig {
+    // Scan all chapters for markers.
+    pub enable: bool,
+
+    // Markers to look for, matched as whole words.
+    pub markers: Vec<String>,
+
+    // Fail release builds, i.e. all but draft builds, if markers remain.
+    pub strict: bool,
+}
+
+impl Default for TodoConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            markers: ["TODO", "FIXME", "XXX", "tbd"]
+                .iter()
+                .map(|marker| marker.to_string())
+                .collect(),
+            strict: false,
+        }
+    }
+}
+
+/// Add all lines of the chapter containing a marker outside of code to the
+/// report. The chapter is the one preprocessors made of the source file,
+/// whose line numbers would not match, so the lines are only quoted.
+pub(crate) fn scan(content: &str, state: &mut Traversal) -> color_eyre::Result<()> {
+    let cfg = &state.cfg.todo;
+    if !cfg.enable || cfg.markers.is_empty() {
+        return Ok(());
+    }
+    let re = regex::Regex::new(&format!(
+        r"\b(?:{})\b",
+        cfg.markers
+            .iter()
+            .map(|marker| regex::escape(marker))
+            .collect::<Vec<_>>()
+            .join("|")
+    ))?;
+
+    let file = state
+        .chapter
+        .and_then(|chapter| chapter.source_path.as_ref().or(chapter.path.as_ref()))
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+    let code = math::code_ranges(content);
+    let mut findings = Vec::new();
+    let mut last_line = None;
+    for found in re.find_iter(content) {
+        if code.iter().any(|range| range.contains(&found.start())) {
+            continue;
+        }
+        let start = content[..found.start()]
+            .rfind('\n')
+            .map_or(0, |pos| pos + 1);
+        if last_line.replace(start) == Some(start) {
+            continue;
+        }
+        let end = content[start..]
+            .find('\n')
+            .map_or(content.len(), |pos| start + pos);
+        let line_excerpt = content[start..end]
+            .trim()
+            .chars()
+            .take(80)
+            .collect::<String>();
+        findings.push(format!("`{}`: {}", file, line_excerpt));
+    }
+    let chapter = state
+        .chapter
+        .map(|chapter| chapter.name.as_str())
+        .unwrap_or_default();
+    state.report.add(SECTION, chapter, findings);
+    Ok(())
+}
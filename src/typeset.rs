@@ -0,0 +1,230 @@
+//! Running the TeX engine on a `.tex` file.
+//!
+//! Files the document inputs, like packages and images, are looked up in
+//! the directory of the `.tex` file first, then in the search paths in
+//! order, then in the TeX distribution.
+//!
+//! The full log of the engine is kept next to the PDF, `book.log`, while the
+//! console only shows its warnings and errors unless `log-filter` is off or
+//! the renderer is run with `--verbose`.
+//!
+//! Tectonic downloads the packages it needs on the fly. Runs failing for
+//! the network, rather than the document, are retried as `retry` says,
+//! waiting longer each time.
+//!
+//! The engine writes the PDF under a name of its own, which is renamed once
+//! complete, so a viewer never opens a PDF still being written.
+
+use crate::Engine;
+use color_eyre::eyre::bail;
+use fs_err as fs;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Output of the engine telling of a failed download, rather than an error
+/// in the document.
+const NETWORK_FAILURES: &[&str] = &[
+    "failed to download",
+    "error sending request",
+    "connection refused",
+    "connection reset",
+    "timed out",
+    "dns error",
+    "could not resolve",
+    "temporary failure in name resolution",
+    "network is unreachable",
+];
+
+/// Retries of engine runs failing for the network.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RetryConfig {
+    // Retries after the first attempt, none if zero.
+    pub attempts: usize,
+
+    // Seconds to wait before the first retry, doubling for each one after.
+    pub delay: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: 2,
+            delay: 5.0,
+        }
+    }
+}
+
+/// How the engine is run.
+#[derive(Debug, Clone)]
+pub(crate) struct Typesetter {
+    pub(crate) engine: Engine,
+    // Allow the engine to run programs, as `minted` needs.
+    pub(crate) shell_escape: bool,
+    // Directories searched for input files, after the one of the `.tex` file.
+    pub(crate) search_paths: Vec<PathBuf>,
+    // Print only the warnings and errors of the engine output.
+    pub(crate) filter_log: bool,
+    pub(crate) retry: RetryConfig,
+}
+
+impl Typesetter {
+    pub(crate) fn new(engine: Engine) -> Self {
+        Self {
+            engine,
+            shell_escape: false,
+            search_paths: Vec::new(),
+            filter_log: true,
+            retry: RetryConfig::default(),
+        }
+    }
+
+    /// Arguments of the engine besides the `.tex` file.
+    pub(crate) fn args(&self) -> Vec<OsString> {
+        let mut args: Vec<OsString> = match self.engine {
+            // Tectonic only writes the log if asked to.
+            Engine::Tectonic => vec!["--outfmt=pdf".into(), "--keep-logs".into()],
+            Engine::Lualatex => vec!["-interaction=nonstopmode".into(), "-halt-on-error".into()],
+        };
+        if self.shell_escape {
+            match self.engine {
+                Engine::Tectonic => args.extend(["-Z".into(), "shell-escape".into()]),
+                Engine::Lualatex => args.push("-shell-escape".into()),
+            }
+        }
+        if self.engine == Engine::Tectonic {
+            for path in &self.search_paths {
+                let mut arg = OsString::from("search-path=");
+                arg.push(path);
+                args.extend(["-Z".into(), arg]);
+            }
+        }
+        args
+    }
+
+    /// `TEXINPUTS` of the engine, if it takes the search paths from there:
+    /// the directory of the `.tex` file, the search paths, and the default
+    /// ones, for which the trailing separator stands.
+    pub(crate) fn texinputs(&self) -> color_eyre::Result<Option<OsString>> {
+        if self.engine != Engine::Lualatex || self.search_paths.is_empty() {
+            return Ok(None);
+        }
+        let mut paths = vec![PathBuf::from(".")];
+        paths.extend(self.search_paths.iter().cloned());
+        paths.push(PathBuf::new());
+        Ok(Some(std::env::join_paths(paths)?))
+    }
+
+    /// Write the PDF, named after the tex file.
+    pub(crate) fn run(&self, tex: &Path) -> color_eyre::Result<()> {
+        let cwd = std::env::current_dir()?;
+        let (name, runs) = match self.engine {
+            // Tectonic reruns on its own as long as needed.
+            Engine::Tectonic => ("tectonic", 1),
+            // A second run for the table of contents and references.
+            Engine::Lualatex => ("lualatex", 2),
+        };
+        log::info!("Writing PDF to {} with {}...", cwd.display(), name);
+        let executable = which::which(name)?;
+        let texinputs = self.texinputs()?;
+        let stem = tex.file_stem().unwrap_or_default().to_string_lossy();
+        let pdf = tex.with_extension("pdf");
+        let log = tex.with_extension("log");
+        // Tectonic writes into a directory, LuaTeX under another job name.
+        let (staging_args, staged_pdf, staged_log): (Vec<OsString>, _, _) = match self.engine {
+            Engine::Tectonic => {
+                let dir = tex.with_file_name(format!(".{}.partial", stem));
+                fs::create_dir_all(&dir)?;
+                (
+                    vec!["--outdir".into(), dir.clone().into()],
+                    dir.join(format!("{}.pdf", stem)),
+                    dir.join(format!("{}.log", stem)),
+                )
+            }
+            Engine::Lualatex => (
+                vec![format!("-jobname={}.partial", stem).into()],
+                tex.with_file_name(format!("{}.partial.pdf", stem)),
+                tex.with_file_name(format!("{}.partial.log", stem)),
+            ),
+        };
+        for _ in 0..runs {
+            let mut command = std::process::Command::new(&executable);
+            command.args(self.args()).args(&staging_args).arg(tex);
+            if let Some(ref texinputs) = texinputs {
+                command.env("TEXINPUTS", texinputs);
+            }
+            let mut attempt = 0;
+            let output = loop {
+                let output = command.output()?;
+                let text = [&output.stdout, &output.stderr]
+                    .iter()
+                    .map(|stream| String::from_utf8_lossy(stream))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                for line in text.lines() {
+                    if is_relevant(line) {
+                        log::warn!("{}", line);
+                    } else if !self.filter_log {
+                        log::info!("{}", line);
+                    }
+                }
+                if output.status.success()
+                    || attempt >= self.retry.attempts
+                    || !is_network_failure(&text)
+                {
+                    break output;
+                }
+                let delay = self.retry.delay.max(0.0) * 2f64.powi(attempt as i32);
+                log::warn!(
+                    "`{}` failed to download, retrying in {:.0} seconds",
+                    name,
+                    delay
+                );
+                std::thread::sleep(Duration::from_secs_f64(delay));
+                attempt += 1;
+            };
+            if staged_log.exists() {
+                fs::rename(&staged_log, &log)?;
+            }
+            if let Some(retval) = output.status.code() {
+                if retval != 0 {
+                    bail!(
+                        "Subprocess `{}` terminated with exit code {}, see {} for the full log",
+                        name,
+                        retval,
+                        log.display()
+                    )
+                }
+            } else {
+                bail!("Failed to launch subprocess `{}`", name)
+            }
+        }
+        fs::rename(&staged_pdf, &pdf)?;
+        if self.engine == Engine::Tectonic {
+            if let Some(dir) = staged_pdf.parent() {
+                fs::remove_dir_all(dir)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the engine failed for the network, by its `output`, worth
+/// another attempt.
+pub(crate) fn is_network_failure(output: &str) -> bool {
+    let output = output.to_lowercase();
+    NETWORK_FAILURES
+        .iter()
+        .any(|failure| output.contains(failure))
+}
+
+/// Whether a line of engine output is a warning or an error, or points to
+/// the line of the error, rather than package chatter.
+pub(crate) fn is_relevant(line: &str) -> bool {
+    let lower = line.to_lowercase();
+    line.starts_with('!')
+        || line.starts_with("l.")
+        || lower.contains("warning")
+        || lower.contains("error")
+}
@@ -0,0 +1,43 @@
+//! Version of the book, for telling distributed PDFs apart, from the
+//! `version` option, which may come from the environment as any option, or
+//! else as described by git.
+
+use crate::raw::escape;
+use std::path::Path;
+use std::process::Command;
+
+/// The `configured` version, else the one `git describe` gives for the book
+/// at `root`, if it is in a repository.
+pub(crate) fn resolve(root: &Path, configured: Option<&str>) -> Option<String> {
+    if let Some(version) = configured {
+        return Some(version.to_owned());
+    }
+    Command::new("git")
+        .args(["describe", "--tags", "--always", "--dirty"])
+        .current_dir(root)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|version| version.trim().to_owned())
+        .filter(|version| !version.is_empty())
+}
+
+/// Preamble printing `version` in the footer of every page, next to the
+/// page number.
+pub(crate) fn footer(version: &str) -> String {
+    format!(
+        r"
+%% Version in the footer.
+\usepackage{{fancyhdr}}
+\fancypagestyle{{plain}}{{%
+  \fancyhf{{}}%
+  \renewcommand{{\headrulewidth}}{{0pt}}%
+  \fancyfoot[C]{{\thepage}}%
+  \fancyfoot[R]{{\footnotesize {}}}%
+}}
+\pagestyle{{plain}}
+",
+        escape(version)
+    )
+}
@@ -0,0 +1,62 @@
+//! Vertical writing, top to bottom and right to left, of Japanese books
+//! with LuaTeX-ja.
+
+use crate::Traversal;
+use pulldown_cmark::{Event, Tag};
+
+/// Document class replacing the default `article`.
+pub(crate) const DOCUMENT_CLASS: &str = "ltjtarticle";
+
+/// Preamble additions, `\rensuji` and pages progressing right to left.
+pub(crate) fn preamble() -> &'static str {
+    "\\usepackage{lltjext}\n\\hypersetup{pdfdirection=R2L}\n"
+}
+
+/// Set numbers of up to two digits upright within the line, tate-chu-yoko,
+/// longer ones stay rotated as Latin text is.
+pub(crate) fn numbers<'a>(
+    events: Vec<Event<'a>>,
+    state: &mut Traversal,
+) -> color_eyre::Result<Vec<Event<'a>>> {
+    if !state.cfg.vertical {
+        return Ok(events);
+    }
+    let digits = regex::Regex::new(r"[0-9]+")?;
+    let mut out = Vec::with_capacity(events.len());
+    let mut in_code = false;
+    for event in events {
+        let text = match event {
+            Event::Text(text) if !in_code => text,
+            event => {
+                match event {
+                    Event::Start(Tag::CodeBlock(_)) => in_code = true,
+                    Event::End(Tag::CodeBlock(_)) => in_code = false,
+                    _ => {}
+                }
+                out.push(event);
+                continue;
+            }
+        };
+        let mut last = 0;
+        for number in digits.find_iter(&text) {
+            if number.as_str().len() > 2 {
+                continue;
+            }
+            if last < number.start() {
+                out.push(Event::Text(text[last..number.start()].to_owned().into()));
+            }
+            out.push(
+                state
+                    .raw
+                    .inline(format!("\\rensuji{{{}}}", number.as_str())),
+            );
+            last = number.end();
+        }
+        if last == 0 {
+            out.push(Event::Text(text));
+        } else if last < text.len() {
+            out.push(Event::Text(text[last..].to_owned().into()));
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,188 @@
+//! Visual regression checks, the pages of the PDF are rasterized with
+//! `pdftoppm` and compared against reference rasters.
+
+use fs_err as fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Report section of the comparison.
+pub(crate) const SECTION: &str = "visual-changes";
+
+/// Gray level difference up to which pixels count as equal, anti-aliasing
+/// varies a little between versions of the rasterizer.
+const TOLERANCE: u8 = 16;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct VisualConfig {
+    // Directory of the reference rasters relative to the book root, comparison is enabled if set.
+    pub references: Option<String>,
+
+    // Resolution of the rasters in DPI.
+    pub resolution: u32,
+
+    // Fraction of differing pixels above which a page counts as changed.
+    pub threshold: f64,
+
+    // Fail the build if any page changed.
+    pub strict: bool,
+}
+
+impl Default for VisualConfig {
+    fn default() -> Self {
+        Self {
+            references: None,
+            resolution: 50,
+            threshold: 0.001,
+            strict: false,
+        }
+    }
+}
+
+/// A binary graymap, as written by `pdftoppm -gray`.
+#[derive(Debug, PartialEq, Eq)]
+struct Graymap {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Graymap {
+    fn parse(data: &[u8]) -> color_eyre::Result<Self> {
+        // Magic number, width, height and maximum gray value, separated by
+        // single whitespace characters each.
+        let mut fields = Vec::with_capacity(4);
+        let mut pos = 0;
+        while fields.len() < 4 {
+            while data.get(pos).map_or(false, u8::is_ascii_whitespace) {
+                pos += 1;
+            }
+            let start = pos;
+            while data.get(pos).map_or(false, |c| !c.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            if start == pos {
+                color_eyre::eyre::bail!("Truncated graymap header");
+            }
+            fields.push(std::str::from_utf8(&data[start..pos])?);
+        }
+        if fields[0] != "P5" || fields[3].parse::<u32>()? > 255 {
+            color_eyre::eyre::bail!("Expected an 8 bit binary graymap");
+        }
+        let width = fields[1].parse()?;
+        let height = fields[2].parse()?;
+        let pixels = data
+            .get(pos + 1..pos + 1 + width * height)
+            .ok_or_else(|| color_eyre::eyre::eyre!("Truncated graymap"))?
+            .to_vec();
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Fraction of pixels which differ, all of them if the sizes do.
+    fn difference(&self, other: &Self) -> f64 {
+        if (self.width, self.height) != (other.width, other.height) {
+            return 1.;
+        }
+        let differing = self
+            .pixels
+            .iter()
+            .zip(&other.pixels)
+            .filter(|(a, b)| a.abs_diff(**b) > TOLERANCE)
+            .count();
+        differing as f64 / self.pixels.len().max(1) as f64
+    }
+}
+
+/// Rasterized pages in `dir`, ordered by page number.
+fn pages(dir: &Path) -> color_eyre::Result<Vec<PathBuf>> {
+    let mut pages = Vec::new();
+    if !dir.exists() {
+        return Ok(pages);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .map_or(false, |extension| extension == "pgm")
+        {
+            pages.push(path);
+        }
+    }
+    // `pdftoppm` pads the page numbers, sorting by name is fine.
+    pages.sort();
+    Ok(pages)
+}
+
+/// Rasterize all pages of `pdf` into `dir`, replacing earlier rasters.
+fn rasterize(pdf: &Path, dir: &Path, resolution: u32) -> color_eyre::Result<Vec<PathBuf>> {
+    for page in pages(dir)? {
+        fs::remove_file(page)?;
+    }
+    fs::create_dir_all(dir)?;
+    let pdftoppm = which::which("pdftoppm")?;
+    let status = Command::new(pdftoppm)
+        .arg("-gray")
+        .arg("-r")
+        .arg(resolution.to_string())
+        .arg(pdf)
+        .arg(dir.join("page"))
+        .status()?;
+    if !status.success() {
+        color_eyre::eyre::bail!("Subprocess `pdftoppm` failed with {}", status);
+    }
+    pages(dir)
+}
+
+/// Rasterize `pdf` into `rasters` and compare the pages against the
+/// references, returns the changed pages. With `update` the references
+/// are replaced instead.
+pub(crate) fn compare(
+    pdf: &Path,
+    rasters: &Path,
+    references: &Path,
+    cfg: &VisualConfig,
+    update: bool,
+) -> color_eyre::Result<Vec<String>> {
+    let pages = rasterize(pdf, rasters, cfg.resolution)?;
+    if update {
+        for page in self::pages(references)? {
+            fs::remove_file(page)?;
+        }
+        fs::create_dir_all(references)?;
+        for page in &pages {
+            fs::copy(page, references.join(page.file_name().unwrap_or_default()))?;
+        }
+        log::info!("Updated the reference pages in {}", references.display());
+        return Ok(Vec::new());
+    }
+
+    let expected = self::pages(references)?;
+    let mut findings = Vec::new();
+    for (idx, page) in pages.iter().enumerate() {
+        let number = idx + 1;
+        let reference = match expected.get(idx) {
+            Some(reference) => reference,
+            None => {
+                findings.push(format!("page {}: added", number));
+                continue;
+            }
+        };
+        let difference =
+            Graymap::parse(&fs::read(page)?)?.difference(&Graymap::parse(&fs::read(reference)?)?);
+        if difference > cfg.threshold {
+            findings.push(format!(
+                "page {}: {:.2}% of pixels differ",
+                number,
+                100. * difference
+            ));
+        }
+    }
+    for number in pages.len() + 1..=expected.len() {
+        findings.push(format!("page {}: removed", number));
+    }
+    Ok(findings)
+}